@@ -1,20 +1,97 @@
-use std::{collections::HashMap, sync::LazyLock};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    fmt::Debug,
+    fs,
+    path::PathBuf,
+    sync::LazyLock,
+};
 
-use anyhow::{anyhow, bail};
+use anyhow::{anyhow, bail, Context};
+use arboard::Clipboard;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     config::{self, get_config},
     db_client::DbClient,
     error::SqlFriendError,
+    history::HistoryScope,
     logging::Logger,
-    lsp::client::LspClient,
-    task::TaskController,
+    lsp::{client::LspClient, completer::CompletionSettings},
+    output,
+    script::{self, ScriptOutput},
+    sql::{self, Dialect},
+    task::{self, TaskController},
+    version,
 };
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Command<'a> {
     pub description: &'a str,
     pub usage: &'a str,
+    /// How the first argument of the command is completed, if at all.
+    pub arg_completion: Option<ArgCompletion>,
+}
+
+/// Values that a command argument can be completed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgCompletion {
+    /// Names of saved connections.
+    ConnectionNames,
+    /// Names of config profiles.
+    Profiles,
+    /// Names of saved snippets.
+    Snippets,
+    /// A fixed set of values.
+    Values(&'static [&'static str]),
+    /// Names of the settings in `SETTINGS`, followed by the values of the setting.
+    Settings,
+}
+
+/// Actions that have to be performed by the frontend (e.g. the line editor) after a command has
+/// been handled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrontendAction {
+    /// Only make history entries in the given scope available for navigation and search.
+    SetHistoryScope(HistoryScope),
+
+    /// Change the key bindings of the line editor.
+    SetEditMode(config::EditMode),
+
+    /// Change what ends an SQL statement.
+    SetTerminator(config::Terminator),
+
+    /// Change the max number of lines in the history of the line editor.
+    SetHistorySize(usize),
+
+    /// Change how completion is triggered and how its candidates are shown.
+    SetCompletionSettings(CompletionSettings),
+
+    /// Apply the settings of the line editor after the config was reloaded.
+    ReloadSettings {
+        edit_mode: config::EditMode,
+        terminator: config::Terminator,
+        history_size: usize,
+        completion: CompletionSettings,
+    },
+
+    /// Run the SQL statement as if it was entered.
+    RunStatement(String),
+
+    /// Let the user edit the SQL in an external editor, and run the result.
+    EditStatement(String),
+
+    /// Show the plan of the query, running it if analyze is true.
+    Explain { query: String, analyze: bool },
+
+    /// Show the next page of the results of the last query.
+    ShowNextPage,
+
+    /// Clear the terminal.
+    ClearScreen,
+
+    /// Exit the REPL.
+    Quit,
 }
 
 #[macro_export]
@@ -45,6 +122,19 @@ macro_rules! input_optional {
     };
 }
 
+macro_rules! confirm {
+    ($prompt:expr) => {
+        dialoguer::Confirm::new()
+            .with_prompt($prompt)
+            .default(false)
+            .interact()
+            .map_err(|err| anyhow!(err))?
+    };
+}
+
+/// Environment variable that `/import --env` reads a connection URL from.
+const DATABASE_URL_VAR: &str = "DATABASE_URL";
+
 pub static COMMANDS: LazyLock<HashMap<&str, Command>> = LazyLock::new(|| {
     HashMap::from([
         (
@@ -52,13 +142,55 @@ pub static COMMANDS: LazyLock<HashMap<&str, Command>> = LazyLock::new(|| {
             Command {
                 description: "Display a list of available commands.",
                 usage: concat!(command_prefix!(), "help"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "version",
+            Command {
+                description: "Show the version of sqlfriend, the database server and the LSP server.",
+                usage: concat!(command_prefix!(), "version"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "more",
+            Command {
+                description: "Show the next page of the results of the last query (also shown by pressing Enter).",
+                usage: concat!(command_prefix!(), "more"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "clear",
+            Command {
+                description: "Clear the screen.",
+                usage: concat!(command_prefix!(), "clear"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "quit",
+            Command {
+                description: "Exit sqlfriend.",
+                usage: concat!(command_prefix!(), "quit"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "i",
+            Command {
+                description: "Execute the statements in an SQL file.",
+                usage: concat!(command_prefix!(), "i <path> [--continue-on-error]"),
+                arg_completion: None,
             },
         ),
         (
             "list",
             Command {
-                description: "List all saved connections.",
-                usage: concat!(command_prefix!(), "list"),
+                description: "List all saved connections, optionally only those with a tag.",
+                usage: concat!(command_prefix!(), "list [--tag <tag>]"),
+                arg_completion: None,
             },
         ),
         (
@@ -66,6 +198,7 @@ pub static COMMANDS: LazyLock<HashMap<&str, Command>> = LazyLock::new(|| {
             Command {
                 description: "Change the active connection.",
                 usage: concat!(command_prefix!(), "use <connection_name>"),
+                arg_completion: Some(ArgCompletion::ConnectionNames),
             },
         ),
         (
@@ -73,6 +206,63 @@ pub static COMMANDS: LazyLock<HashMap<&str, Command>> = LazyLock::new(|| {
             Command {
                 description: "Add a new connection.",
                 usage: concat!(command_prefix!(), "add"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "import",
+            Command {
+                description: "Add the connections in a file of `name=url` lines or a JSON array, or the connection in $DATABASE_URL.",
+                usage: concat!(command_prefix!(), "import <path> | --env <name>"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "export",
+            Command {
+                description: "Write all connections to a JSON file that can be imported, with passwords only if --passwords is given.",
+                usage: concat!(command_prefix!(), "export <path> [--passwords]"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "autocommit",
+            Command {
+                description: "Show or change whether queries outside of a transaction are committed automatically.",
+                usage: concat!(command_prefix!(), "autocommit [on|off]"),
+                arg_completion: Some(ArgCompletion::Values(&["on", "off"])),
+            },
+        ),
+        (
+            "begin",
+            Command {
+                description: "Start a transaction that subsequent queries run in.",
+                usage: concat!(command_prefix!(), "begin"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "commit",
+            Command {
+                description: "Commit the open transaction.",
+                usage: concat!(command_prefix!(), "commit"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "rollback",
+            Command {
+                description: "Roll back the open transaction.",
+                usage: concat!(command_prefix!(), "rollback"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "copy-result",
+            Command {
+                description: "Copy the result of the last query to the clipboard in the current output format.",
+                usage: concat!(command_prefix!(), "copy-result"),
+                arg_completion: None,
             },
         ),
         (
@@ -80,33 +270,379 @@ pub static COMMANDS: LazyLock<HashMap<&str, Command>> = LazyLock::new(|| {
             Command {
                 description: "Delete a saved connection.",
                 usage: concat!(command_prefix!(), "delete <connection_name>"),
+                arg_completion: Some(ArgCompletion::ConnectionNames),
             },
         ),
         (
-            "set_lsp_server",
+            "explain",
+            Command {
+                description: "Show how the database runs the query. With analyze, the query is run to show the actual time and rows.",
+                usage: concat!(command_prefix!(), "explain [analyze] <query>"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "e",
+            Command {
+                description: "Edit the last statement in $EDITOR and run the result.",
+                usage: concat!(command_prefix!(), "e"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "g",
+            Command {
+                description: "Run the last statement again, or all statements of the last /e or /run.",
+                usage: concat!(command_prefix!(), "g"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "history",
+            Command {
+                description: "Show recent history and limit history search to the given scope.",
+                usage: concat!(command_prefix!(), "history [all|sql|commands]"),
+                arg_completion: Some(ArgCompletion::Values(&["all", "sql", "commands"])),
+            },
+        ),
+        (
+            "o",
             Command {
                 description:
-                    "Set the LSP server (Sqls, SqlLs, or PgTools). Should be available in $PATH.",
+                    "Write the result of the next query to a file, or cancel if no path is given.",
+                usage: concat!(command_prefix!(), "o [path]"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "profile",
+            Command {
+                description: "List config profiles, or switch to the given profile.",
+                usage: concat!(command_prefix!(), "profile [profile_name]"),
+                arg_completion: Some(ArgCompletion::Profiles),
+            },
+        ),
+        (
+            "refresh",
+            Command {
+                description: "Reload the tables and columns that are completed while no LSP server is running.",
+                usage: concat!(command_prefix!(), "refresh"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "ping",
+            Command {
+                description: "Check that the database can be reached and show the round-trip time.",
+                usage: concat!(command_prefix!(), "ping"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "lsp",
+            Command {
+                description: "Show the last lines that the LSP server wrote to stderr.",
+                usage: concat!(command_prefix!(), "lsp log"),
+                arg_completion: Some(ArgCompletion::Values(&["log"])),
+            },
+        ),
+        (
+            "reload",
+            Command {
+                description: "Read the config file again and apply the changes made to it.",
+                usage: concat!(command_prefix!(), "reload"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "restart_lsp",
+            Command {
+                description: "Restart the LSP server for the active connection.",
+                usage: concat!(command_prefix!(), "restart_lsp"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "save",
+            Command {
+                description: "Save the last statement as a snippet with the given name.",
+                usage: concat!(command_prefix!(), "save <snippet_name>"),
+                arg_completion: Some(ArgCompletion::Snippets),
+            },
+        ),
+        (
+            "run",
+            Command {
+                description: "Run a saved snippet.",
+                usage: concat!(command_prefix!(), "run <snippet_name>"),
+                arg_completion: Some(ArgCompletion::Snippets),
+            },
+        ),
+        (
+            "snippets",
+            Command {
+                description: "List saved snippets.",
+                usage: concat!(command_prefix!(), "snippets"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "delete-snippet",
+            Command {
+                description: "Delete a saved snippet.",
+                usage: concat!(command_prefix!(), "delete-snippet <snippet_name>"),
+                arg_completion: Some(ArgCompletion::Snippets),
+            },
+        ),
+        (
+            "set",
+            Command {
+                description: "Change a setting.",
+                usage: concat!(command_prefix!(), "set <setting> <value>"),
+                arg_completion: Some(ArgCompletion::Settings),
+            },
+        ),
+        (
+            "show",
+            Command {
+                description: "Show the current value of every setting.",
+                usage: concat!(command_prefix!(), "show"),
+                arg_completion: None,
+            },
+        ),
+        (
+            "set_lsp_server",
+            Command {
+                description: concat!(
+                    "Set the LSP server (Sqls, SqlLs, or PgTools). Should be available in $PATH. ",
+                    "Same as ",
+                    command_prefix!(),
+                    "set lsp_server <lsp_server>."
+                ),
                 usage: concat!(command_prefix!(), "set_lsp_server <lsp_server>"),
+                arg_completion: Some(ArgCompletion::Values(&LSP_SERVER_VALUES)),
             },
         ),
     ])
 });
 
-/// Returns true if the given string looks like a command.
+/// Short names of commands, e.g. `l` for `list`. Any unambiguous prefix of a command can be used
+/// as well, see `resolve_command`.
+pub const ALIASES: [(&str, &str); 4] = [("l", "list"), ("u", "use"), ("h", "help"), ("q", "quit")];
+
+/// Settings that can be changed using `/set`, without underscores between words, and the values
+/// they can be completed with. Settings with free-form values (such as numbers) have no values.
+/// `handle_set` only accepts these settings.
+pub static SETTINGS: LazyLock<BTreeMap<&str, Vec<String>>> = LazyLock::new(|| {
+    let toggle = || vec!["on".to_string(), "off".to_string()];
+    BTreeMap::from([
+        ("editmode", variant_names(&config::EditMode::VALUES)),
+        ("border", variant_names(&config::BorderStyle::VALUES)),
+        ("overflow", variant_names(&config::Overflow::VALUES)),
+        ("maxcolwidth", vec![]),
+        ("format", variant_names(&config::OutputFormat::VALUES)),
+        ("binary", variant_names(&config::BinaryFormat::VALUES)),
+        ("bool", variant_names(&config::BoolFormat::VALUES)),
+        ("completiondebounce", vec![]),
+        ("keywordcase", variant_names(&config::KeywordCase::VALUES)),
+        ("diaglevel", variant_names(&config::DiagnosticLevel::VALUES)),
+        (
+            "completiontriggers",
+            vec![config::Config::COMPLETION_TRIGGERS_OFF.to_string()],
+        ),
+        (
+            "terminator",
+            vec![";".to_string(), config::Terminator::BLANK_LINE.to_string()],
+        ),
+        ("stripcomments", toggle()),
+        ("pager", vec![config::Config::PAGER_OFF.to_string()]),
+        ("prompt", vec![]),
+        ("readonly", toggle()),
+        ("confirmdestructive", toggle()),
+        ("footer", toggle()),
+        ("headertypes", toggle()),
+        ("historysize", vec![]),
+        ("warmconnections", vec![]),
+        ("paging", toggle()),
+        ("pagesize", vec![config::Config::PAGE_SIZE_AUTO.to_string()]),
+        (
+            "lspserver",
+            LSP_SERVER_VALUES
+                .iter()
+                .map(|value| value.to_string())
+                .collect(),
+        ),
+    ])
+});
+
+/// Values of the `lspserver` setting (and `set_lsp_server`).
+const LSP_SERVER_VALUES: [&str; 3] = ["sqls", "sqlls", "pgtools"];
+
+/// Get the values that a setting can be completed with. Words in the setting may be separated by
+/// underscores.
+pub fn setting_values(setting: &str) -> Option<&'static [String]> {
+    SETTINGS
+        .get(setting.replace('_', "").as_str())
+        .map(Vec::as_slice)
+}
+
+/// Names of the variants as accepted by `parse_setting_value`, see `variant_name`.
+fn variant_names<T: Debug>(variants: &[T]) -> Vec<String> {
+    variants.iter().map(variant_name).collect()
+}
+
+/// Name of the variant as accepted by `parse_setting_value`, in snake case (e.g. `true_false` for
+/// `TrueFalse`).
+fn variant_name<T: Debug>(variant: &T) -> String {
+    let mut name = String::new();
+    for c in format!("{variant:?}").chars() {
+        if c.is_uppercase() && !name.is_empty() {
+            name.push('_');
+        }
+        name.push(c.to_ascii_lowercase());
+    }
+    name
+}
+
+/// Get the current value of a setting in `SETTINGS`, formatted like the value passed to `/set`.
+/// Returns None if there is no such setting.
+fn current_setting_value(config: &config::Config, setting: &str) -> Option<String> {
+    let toggle = |enabled: bool| if enabled { "on" } else { "off" }.to_string();
+    let value = match setting {
+        "editmode" => variant_name(&config.get_edit_mode()),
+        "border" => variant_name(&config.get_border_style()),
+        "overflow" => variant_name(&config.get_overflow()),
+        "maxcolwidth" => config.get_max_col_width().to_string(),
+        "format" => variant_name(&config.get_output_format()),
+        "binary" => variant_name(&config.get_binary_format()),
+        "bool" => variant_name(&config.get_bool_format()),
+        "completiondebounce" => config.get_completion_debounce_ms().to_string(),
+        "keywordcase" => variant_name(&config.get_completion_keyword_case()),
+        "diaglevel" => variant_name(&config.get_diag_level()),
+        "completiontriggers" => match config.get_completion_triggers() {
+            "" => config::Config::COMPLETION_TRIGGERS_OFF.to_string(),
+            triggers => format!("{triggers:?}"),
+        },
+        "terminator" => config.get_terminator().to_setting_value(),
+        "stripcomments" => toggle(config.get_strip_comments()),
+        "pager" => config
+            .get_pager()
+            .unwrap_or(config::Config::PAGER_OFF.to_string()),
+        "prompt" => format!("{:?}", config.get_prompt()),
+        "readonly" => toggle(config.get_read_only()),
+        "confirmdestructive" => toggle(config.get_confirm_destructive()),
+        "footer" => toggle(config.get_footer()),
+        "headertypes" => toggle(config.get_header_types()),
+        "historysize" => config.get_history_size().to_string(),
+        "warmconnections" => config.get_warm_connections().to_string(),
+        "paging" => toggle(config.get_paging()),
+        "pagesize" => config
+            .get_page_size()
+            .map(|size| size.to_string())
+            .unwrap_or(config::Config::PAGE_SIZE_AUTO.to_string()),
+        "lspserver" => {
+            let server = config.get_lsp_server().cloned().unwrap_or_default();
+            format!("{server:?}").to_lowercase()
+        }
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Returns true if the given string looks like a command. Leading whitespace is ignored, and a
+/// line starting with a `/*` SQL comment isn't a command.
 pub fn is_maybe_command(line: &str) -> bool {
-    line.starts_with(command_prefix!())
+    let line = line.trim_start();
+    line.starts_with(command_prefix!()) && !line.starts_with("/*")
+}
+
+/// Largest edit distance between a mistyped command and a command for it to be suggested.
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// Find the command that was most likely meant by the given (unknown) command name, without the
+/// prefix. An empty name suggests `help`. Otherwise, the closest command by Levenshtein distance
+/// is suggested if it's close enough, e.g. `list` for `lsit`. If none is, the shortest command
+/// containing the characters of the name in order is suggested, e.g. `copy-result` for
+/// `cpyres`.
+fn suggest_command(name: &str) -> Option<&'static str> {
+    if name.is_empty() {
+        return Some("help");
+    }
+
+    let closest = COMMANDS
+        .keys()
+        .copied()
+        .map(|command| (strsim::levenshtein(name, command), command))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+        .min();
+    if let Some((_, command)) = closest {
+        return Some(command);
+    }
+
+    let is_subsequence = |command: &str| {
+        let mut chars = command.chars();
+        name.chars().all(|c| chars.any(|command_c| command_c == c))
+    };
+
+    COMMANDS
+        .keys()
+        .copied()
+        .filter(|command| is_subsequence(command))
+        .min_by_key(|command| (command.len(), *command))
+}
+
+/// Get the name of the command that the given command name (without the prefix) refers to: the
+/// command with that name, the command it's an alias of, or the only command starting with it.
+pub fn resolve_command(name: &str) -> Result<&'static str, SqlFriendError> {
+    if let Some((&command, _)) = COMMANDS.get_key_value(name) {
+        return Ok(command);
+    }
+
+    if let Some((_, command)) = ALIASES.iter().find(|(alias, _)| *alias == name) {
+        return Ok(command);
+    }
+
+    let mut matching = if name.is_empty() {
+        vec![]
+    } else {
+        COMMANDS
+            .keys()
+            .copied()
+            .filter(|command| command.starts_with(name))
+            .collect::<Vec<_>>()
+    };
+    matching.sort_unstable();
+
+    match matching.as_slice() {
+        [command] => Ok(command),
+        [] => Err(SqlFriendError::InvalidCommand(
+            format!("{}{name}", command_prefix!()),
+            suggest_command(name),
+        )),
+        _ => Err(SqlFriendError::AmbiguousCommand(
+            format!("{}{name}", command_prefix!()),
+            matching,
+        )),
+    }
 }
 
-/// Parse and execute the given command (line).
+/// Parse and execute the given command (line). Returns an action if the frontend needs to do
+/// something as a result of the command.
 pub async fn handle_command(
     task_controller: &TaskController,
     db_client: &DbClient,
     lsp_client: &LspClient,
     line: &str,
-) -> anyhow::Result<()> {
-    let tokens = line.split(" ").collect::<Vec<_>>();
-    let &cmd = tokens
+) -> anyhow::Result<Option<FrontendAction>> {
+    let tokens = match tokenize(line) {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            lsp_client.get_logger().error(&e.to_string())?;
+            return Ok(None);
+        }
+    };
+    let cmd = tokens
         .first()
         .ok_or(anyhow!("an empty string is not a command"))?;
 
@@ -115,27 +651,125 @@ pub async fn handle_command(
     }
 
     let prefix_length = command_prefix!().len();
-    let stripped_cmd = &cmd[prefix_length..];
-    let args = &tokens[1..];
+    let name = match resolve_command(&cmd[prefix_length..]) {
+        Ok(name) => name,
+        Err(e) => {
+            lsp_client.get_logger().error(&e.to_string())?;
+            return Ok(None);
+        }
+    };
+    let args = &tokens[1..].iter().map(String::as_str).collect::<Vec<_>>();
 
-    let cmd_result = match stripped_cmd {
-        "list" => handle_list(lsp_client.get_logger()),
-        "use" => handle_use(task_controller, db_client, lsp_client, args).await,
-        "add" => handle_add(lsp_client.get_logger(), args),
-        "delete" => handle_delete(lsp_client.get_logger(), args),
-        "set_lsp_server" => {
-            handle_set_lsp_server(task_controller, db_client, lsp_client, args).await
+    let cmd_result = match name {
+        "list" => handle_list(lsp_client.get_logger(), args).map(|_| None),
+        "use" => handle_use(task_controller, db_client, lsp_client, args)
+            .await
+            .map(|_| None),
+        "add" => handle_add(lsp_client.get_logger(), args).map(|_| None),
+        "delete" => handle_delete(lsp_client.get_logger(), args).map(|_| None),
+        "import" => handle_import(lsp_client.get_logger(), args).map(|_| None),
+        "export" => handle_export(lsp_client.get_logger(), args).map(|_| None),
+        "set_lsp_server" => handle_set_lsp_server(task_controller, db_client, lsp_client, args)
+            .await
+            .map(|_| None),
+        "lsp" => handle_lsp(lsp_client, args).await.map(|_| None),
+        "restart_lsp" => handle_restart_lsp(task_controller, db_client, lsp_client, args)
+            .await
+            .map(|_| None),
+        "reload" => handle_reload(task_controller, db_client, lsp_client, args)
+            .await
+            .map(Some),
+        "begin" | "commit" | "rollback" => {
+            handle_transaction(db_client, lsp_client.get_logger(), name, args)
+                .await
+                .map(|_| None)
         }
-        "help" => handle_help(lsp_client.get_logger()),
-        _ => Err(SqlFriendError::InvalidCommand(cmd.to_string())),
+        "autocommit" => handle_autocommit(db_client, lsp_client.get_logger(), args).map(|_| None),
+        "o" => handle_o(lsp_client, args).await.map(|_| None),
+        "refresh" => handle_refresh(db_client, lsp_client, args)
+            .await
+            .map(|_| None),
+        "ping" => handle_ping(db_client, lsp_client.get_logger(), args)
+            .await
+            .map(|_| None),
+        "copy-result" => handle_copy_result(lsp_client, args).await.map(|_| None),
+        "profile" => handle_profile(task_controller, db_client, lsp_client, args)
+            .await
+            .map(|_| None),
+        "i" => handle_i(db_client, lsp_client.get_logger(), args)
+            .await
+            .map(|_| None),
+        "set" => handle_set(task_controller, db_client, lsp_client, args).await,
+        "show" => handle_show(lsp_client.get_logger(), args).map(|_| None),
+        "history" => handle_history(lsp_client, args).await.map(Some),
+        "g" => handle_g(lsp_client, args).await.map(Some),
+        "e" => handle_e(lsp_client, args).await.map(Some),
+        "explain" => handle_explain(line, db_client.dialect().await).map(Some),
+        "save" => handle_save(lsp_client, args).await.map(|_| None),
+        "run" => handle_run(args).map(Some),
+        "snippets" => handle_snippets(lsp_client.get_logger(), args).map(|_| None),
+        "delete-snippet" => handle_delete_snippet(lsp_client.get_logger(), args).map(|_| None),
+        "help" => handle_help(lsp_client.get_logger()).map(|_| None),
+        "version" => handle_version(db_client, lsp_client, args)
+            .await
+            .map(|_| None),
+        "more" => handle_more(args).map(Some),
+        "clear" => handle_clear(args).map(Some),
+        "quit" => handle_quit(args).map(Some),
+        _ => Err(anyhow!("internal error: {name} command isn't handled").into()),
     };
 
     match cmd_result {
-        Ok(()) => (),
-        Err(e) => lsp_client.get_logger().error(&e.to_string())?,
-    };
+        Ok(action) => Ok(action),
+        Err(e) => {
+            lsp_client.get_logger().error(&e.to_string())?;
+            Ok(None)
+        }
+    }
+}
 
-    Ok(())
+/// Split a command line into tokens separated by whitespace. Single and double quotes can be used
+/// to include whitespace in a token, and `\"` or `\\` can be used inside double quotes.
+fn tokenize(line: &str) -> Result<Vec<String>, SqlFriendError> {
+    let mut tokens = Vec::new();
+    let mut token: Option<String> = None;
+    let mut chars = line.chars();
+    let unclosed = || SqlFriendError::UnclosedQuote(line.to_string());
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => tokens.extend(token.take()),
+            '\'' => {
+                let token = token.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unclosed)? {
+                        '\'' => break,
+                        c => token.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let token = token.get_or_insert_with(String::new);
+                loop {
+                    match chars.next().ok_or_else(unclosed)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or_else(unclosed)? {
+                            c @ ('"' | '\\') => token.push(c),
+                            c => {
+                                token.push('\\');
+                                token.push(c);
+                            }
+                        },
+                        c => token.push(c),
+                    }
+                }
+            }
+            c => token.get_or_insert_with(String::new).push(c),
+        }
+    }
+    tokens.extend(token);
+
+    Ok(tokens)
 }
 
 fn handle_help(logger: &Logger) -> Result<(), SqlFriendError> {
@@ -145,24 +779,155 @@ fn handle_help(logger: &Logger) -> Result<(), SqlFriendError> {
         .collect::<Vec<_>>();
 
     output_lines.sort_unstable();
-    logger.standard(&output_lines.join("\n"))?;
+    logger.result(&output_lines.join("\n"))?;
+
+    Ok(())
+}
+
+async fn handle_version(
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    args: &[&str],
+) -> Result<(), SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("version")
+            .ok_or(anyhow!("internal error: version command doesn't exist"))?;
 
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    // The executable is only asked if the server isn't reached over TCP, since it may not be
+    // the one that is running then
+    let config = config::get_config()?;
+    let server_type = match config.get_lsp_tcp_address()? {
+        Some(_) => None,
+        None => Some(config.get_lsp_server().cloned().unwrap_or_default()),
+    };
+    drop(config);
+
+    let lines = version::describe(db_client, Some(lsp_client), server_type.as_ref()).await;
+    lsp_client.get_logger().result(&lines.join("\n"))?;
     Ok(())
 }
 
-fn handle_list(logger: &Logger) -> Result<(), SqlFriendError> {
+fn handle_more(args: &[&str]) -> Result<FrontendAction, SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("more")
+            .ok_or(anyhow!("internal error: more command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    Ok(FrontendAction::ShowNextPage)
+}
+
+fn handle_clear(args: &[&str]) -> Result<FrontendAction, SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("clear")
+            .ok_or(anyhow!("internal error: clear command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    Ok(FrontendAction::ClearScreen)
+}
+
+fn handle_quit(args: &[&str]) -> Result<FrontendAction, SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("quit")
+            .ok_or(anyhow!("internal error: quit command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    Ok(FrontendAction::Quit)
+}
+
+fn handle_list(logger: &Logger, args: &[&str]) -> Result<(), SqlFriendError> {
+    let tag = match args {
+        [] => None,
+        ["--tag", tag] => Some(*tag),
+        _ => {
+            let list_usage = COMMANDS
+                .get("list")
+                .ok_or(anyhow!("internal error: list command doesn't exist"))?;
+
+            return Err(SqlFriendError::InvalidCommandUsage(
+                list_usage.usage.to_string(),
+            ));
+        }
+    };
+
     let config = get_config()?;
-    let connections = config.get_connections();
-    let output = connections
+    let connections = config
+        .get_connections()
         .iter()
-        .map(|connection| format!("{}: {:?}", connection.name, connection.settings))
-        .collect::<Vec<_>>()
-        .join("\n");
+        .filter(|connection| tag.is_none_or(|tag| connection.has_tag(tag)))
+        .collect::<Vec<_>>();
+
+    let format_connection =
+        |connection: &config::Connection| format!("{}: {:?}", connection.name, connection.settings);
+
+    // Only group the connections if there is more than one group to show
+    let output = if tag.is_some() || connections.iter().all(|c| c.get_tags().is_empty()) {
+        connections
+            .iter()
+            .map(|connection| format_connection(connection))
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        group_by_tag(&connections)
+            .into_iter()
+            .map(|(tag, connections)| {
+                let header = tag.map_or("(untagged)".to_string(), |tag| format!("[{tag}]"));
+                let lines = connections
+                    .iter()
+                    .map(|connection| format!("  {}", format_connection(connection)));
+                std::iter::once(header)
+                    .chain(lines)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
 
-    logger.standard(&output)?;
+    logger.result(&output)?;
     Ok(())
 }
 
+/// Group connections by tag, sorted by tag with untagged connections last. A connection with
+/// several tags is part of each of their groups.
+fn group_by_tag<'a>(
+    connections: &[&'a config::Connection],
+) -> Vec<(Option<&'a str>, Vec<&'a config::Connection>)> {
+    let mut groups: BTreeMap<&str, Vec<&config::Connection>> = BTreeMap::new();
+    let mut untagged = vec![];
+    for connection in connections {
+        if connection.get_tags().is_empty() {
+            untagged.push(*connection);
+        }
+
+        for tag in connection.get_tags() {
+            groups.entry(tag.as_str()).or_default().push(*connection);
+        }
+    }
+
+    let mut grouped = groups
+        .into_iter()
+        .map(|(tag, connections)| (Some(tag), connections))
+        .collect::<Vec<_>>();
+    if !untagged.is_empty() {
+        grouped.push((None, untagged));
+    }
+
+    grouped
+}
+
 async fn handle_use(
     task_controller: &TaskController,
     db_client: &DbClient,
@@ -205,7 +970,9 @@ fn handle_add(logger: &Logger, args: &[&str]) -> Result<(), SqlFriendError> {
         ));
     }
 
-    let databases = vec!["postgres", "mysql", "sqlite"];
+    let mut config = get_config()?;
+
+    let databases = vec!["postgres", "mysql", "sqlite"];
     let database_index = dialoguer::Select::new()
         .with_prompt("Choose a database type")
         .items(&databases)
@@ -217,41 +984,67 @@ fn handle_add(logger: &Logger, args: &[&str]) -> Result<(), SqlFriendError> {
         .interact_text()
         .map_err(|err| anyhow!(err))?;
 
-    let connection = match databases[database_index] {
+    // Fail before asking for the rest of the settings
+    if config.has_connection(&name) {
+        return Err(SqlFriendError::DuplicateConnectionName(name));
+    }
+
+    let mut connection = match databases[database_index] {
         "postgres" => {
-            let host = input!("Hostname");
+            let socket =
+                input_optional!("Unix socket directory (leave empty to connect using TCP)");
+            let host = match socket {
+                Some(_) => String::new(),
+                None => input!("Hostname"),
+            };
             let port = input_optional!("Port (leave empty if none)");
             let user = input_optional!("Username (leave empty if none)");
             let password = input_optional!("Password (leave empty if none)");
             let database = input_optional!("Database (leave empty if none)");
+            let schema = input_optional!("Default schema (leave empty if none)");
 
             config::Connection {
                 name: name.clone(),
                 settings: config::ConnectionSettings::Postgres {
                     host,
                     port,
+                    socket,
                     user,
                     password,
                     database,
+                    schema,
                 },
+                init_sql: None,
+                read_only: None,
+                tags: None,
             }
         }
         "mysql" => {
-            let host = input!("Hostname");
+            let socket = input_optional!("Unix socket path (leave empty to connect using TCP)");
+            let host = match socket {
+                Some(_) => String::new(),
+                None => input!("Hostname"),
+            };
             let port = input_optional!("Port (leave empty if none)");
             let user = input_optional!("Username (leave empty if none)");
             let password = input_optional!("Password (leave empty if none)");
             let database = input_optional!("Database (leave empty if none)");
+            let schema = input_optional!("Default schema (leave empty if none)");
 
             config::Connection {
                 name: name.clone(),
                 settings: config::ConnectionSettings::MySql {
                     host,
                     port,
+                    socket,
                     user,
                     password,
                     database,
+                    schema,
                 },
+                init_sql: None,
+                read_only: None,
+                tags: None,
             }
         }
         "sqlite" => {
@@ -259,16 +1052,70 @@ fn handle_add(logger: &Logger, args: &[&str]) -> Result<(), SqlFriendError> {
                 .with_prompt("Path to database file")
                 .interact_text()
                 .map_err(|err| anyhow!(err))?;
+            let read_only = confirm!("Open the database file read-only?");
+            let busy_timeout_ms: Option<String> =
+                input_optional!("Busy timeout in milliseconds (leave empty for the default)");
+            let busy_timeout_ms = busy_timeout_ms
+                .map(|value| {
+                    value.parse().map_err(|_| {
+                        SqlFriendError::InvalidSettingValue(
+                            "busy_timeout_ms".to_string(),
+                            value,
+                            "a non-negative number of milliseconds".to_string(),
+                        )
+                    })
+                })
+                .transpose()?;
+            let journal_mode: Option<String> = input_optional!(format!(
+                "Journal mode, one of {:?} (leave empty for the default)",
+                config::JournalMode::VALUES
+            ));
+            let journal_mode = journal_mode
+                .map(|value| {
+                    parse_setting_value("journal_mode", &value, &config::JournalMode::VALUES)
+                })
+                .transpose()?;
+            let create_if_missing = confirm!("Create the database file if it doesn't exist?");
+
             config::Connection {
                 name: name.clone(),
-                settings: config::ConnectionSettings::Sqlite { filename: path },
+                settings: config::ConnectionSettings::Sqlite {
+                    filename: path,
+                    // Only stored if enabled, to keep the config file short
+                    read_only: read_only.then_some(true),
+                    busy_timeout_ms,
+                    journal_mode,
+                    create_if_missing: create_if_missing.then_some(true),
+                },
+                init_sql: None,
+                read_only: None,
+                tags: None,
             }
         }
         _ => unreachable!("dialogue should be limited to these databases"),
     };
 
+    let init_sql: Option<String> =
+        input_optional!("SQL to run after connecting, separated by `;` (leave empty if none)");
+    connection.init_sql = init_sql.map(|init_sql| {
+        sql::split_statements(&init_sql, connection.dialect())
+            .into_iter()
+            .map(|statement| statement.to_string())
+            .collect()
+    });
+
+    let tags: Option<String> = input_optional!("Tags, separated by `,` (leave empty if none)");
+    connection.tags = tags
+        .map(|tags| {
+            tags.split(',')
+                .map(|tag| tag.trim().to_string())
+                .filter(|tag| !tag.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .filter(|tags| !tags.is_empty());
+
     let log_msg = format!("Stored {}: {:?}.", name, connection);
-    get_config()?.add_connection(connection)?;
+    config.add_connection(connection)?;
     logger.standard(&log_msg)?;
 
     Ok(())
@@ -293,21 +1140,1105 @@ fn handle_delete(logger: &Logger, args: &[&str]) -> Result<(), SqlFriendError> {
     Ok(())
 }
 
+/// A connection in a JSON list of connections to import, as written by `/export`.
+#[derive(Serialize, Deserialize)]
+struct ImportedConnection {
+    name: String,
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    init_sql: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    read_only: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+}
+
+fn handle_import(logger: &Logger, args: &[&str]) -> Result<(), SqlFriendError> {
+    let connections = match args {
+        ["--env", name] => {
+            let url = env::var(DATABASE_URL_VAR)
+                .map_err(|_| anyhow!("${DATABASE_URL_VAR} is not set"))?;
+            vec![imported_connection(name, &url)?]
+        }
+        [path] => {
+            let contents =
+                fs::read_to_string(path).with_context(|| format!("failed to read {path}"))?;
+            parse_connection_list(&contents)?
+        }
+        _ => {
+            let cmd = COMMANDS
+                .get("import")
+                .ok_or(anyhow!("internal error: import command doesn't exist"))?;
+
+            return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+        }
+    };
+
+    let mut config = get_config()?;
+    let mut imported = 0;
+    let mut skipped = vec![];
+    let mut invalid = vec![];
+    for connection in connections {
+        if config.has_connection(&connection.name) {
+            skipped.push(connection.name);
+            continue;
+        }
+
+        // Invalid connections are reported at the end, so that the rest are still imported
+        let name = connection.name.clone();
+        match config.add_connection(connection) {
+            Ok(()) => imported += 1,
+            Err(SqlFriendError::InvalidConnectionSettings(reason)) => {
+                invalid.push(format!("{name} ({reason})"))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut msg = format!("Imported {imported} connections.");
+    if !skipped.is_empty() {
+        msg.push_str(&format!(
+            " Skipped {} that already exist: {}.",
+            skipped.len(),
+            skipped.join(", ")
+        ));
+    }
+    if !invalid.is_empty() {
+        msg.push_str(&format!(
+            " Skipped {} that are invalid: {}.",
+            invalid.len(),
+            invalid.join(", ")
+        ));
+    }
+    logger.standard(&msg)?;
+
+    Ok(())
+}
+
+/// Parse a list of connections to import: either lines of `name=url`, ignoring empty lines and
+/// lines starting with `#`, or a JSON array of `{"name": ..., "url": ...}` objects (which can
+/// also have `init_sql`, `read_only` and `tags`).
+fn parse_connection_list(contents: &str) -> Result<Vec<config::Connection>, SqlFriendError> {
+    if contents.trim_start().starts_with('[') {
+        let entries: Vec<ImportedConnection> =
+            serde_json::from_str(contents).context("failed to parse the JSON connection list")?;
+        return entries
+            .into_iter()
+            .map(|entry| {
+                Ok(config::Connection {
+                    init_sql: entry.init_sql,
+                    read_only: entry.read_only,
+                    tags: entry.tags,
+                    ..imported_connection(&entry.name, &entry.url)?
+                })
+            })
+            .collect();
+    }
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| {
+            let (name, url) = line
+                .split_once('=')
+                .ok_or(anyhow!("line {line_number}: expected `name=url`"))?;
+            imported_connection(name.trim(), url.trim())
+        })
+        .collect()
+}
+
+/// Create a connection with the given name from its URL.
+fn imported_connection(name: &str, url: &str) -> Result<config::Connection, SqlFriendError> {
+    if name.is_empty() {
+        return Err(anyhow!("a connection to import has no name").into());
+    }
+
+    let settings = config::ConnectionSettings::from_url(url)
+        .map_err(|e| anyhow!("failed to import {name}: {e}"))?;
+
+    Ok(config::Connection {
+        name: name.to_string(),
+        settings,
+        init_sql: None,
+        read_only: None,
+        tags: None,
+    })
+}
+
+fn handle_export(logger: &Logger, args: &[&str]) -> Result<(), SqlFriendError> {
+    let (path, include_passwords) = match args {
+        [path] => (*path, false),
+        [path, "--passwords"] | ["--passwords", path] => (*path, true),
+        _ => {
+            let cmd = COMMANDS
+                .get("export")
+                .ok_or(anyhow!("internal error: export command doesn't exist"))?;
+
+            return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+        }
+    };
+
+    let config = get_config()?;
+    let entries = config
+        .get_connections()
+        .iter()
+        .map(|connection| ImportedConnection {
+            name: connection.name.clone(),
+            url: connection.settings.to_url(include_passwords),
+            init_sql: connection.init_sql.clone(),
+            read_only: connection.read_only,
+            tags: connection.tags.clone(),
+        })
+        .collect::<Vec<_>>();
+    let contents =
+        serde_json::to_string_pretty(&entries).context("failed to serialize connections")?;
+    if include_passwords {
+        write_private_file(path, &contents)
+    } else {
+        fs::write(path, contents)
+    }
+    .with_context(|| format!("failed to write {path}"))?;
+
+    logger.standard(&format!(
+        "Exported {} connections to {path}.",
+        config.get_connections().len()
+    ))?;
+
+    Ok(())
+}
+
+/// Write a file that only the current user can read, e.g. because it contains passwords.
+#[cfg(unix)]
+fn write_private_file(path: &str, contents: &str) -> std::io::Result<()> {
+    use std::{
+        io::Write,
+        os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    };
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode is only used when creating the file
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private_file(path: &str, contents: &str) -> std::io::Result<()> {
+    fs::write(path, contents)
+}
+
+async fn handle_restart_lsp(
+    task_controller: &TaskController,
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    args: &[&str],
+) -> Result<(), SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("restart_lsp")
+            .ok_or(anyhow!("internal error: restart_lsp command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    let connection = db_client
+        .get_current_connection()
+        .await
+        .clone()
+        .ok_or(SqlFriendError::NoActiveConnection)?;
+    let server_type = get_config()?.get_lsp_server().cloned().unwrap_or_default();
+
+    lsp_client
+        .get_logger()
+        .standard(&format!("Restarting {server_type:?} LSP server..."))?;
+
+    task_controller
+        .execute(task::Command::SpawnLsp(server_type, Box::new(connection)))
+        .await?;
+
+    Ok(())
+}
+
+async fn handle_reload(
+    task_controller: &TaskController,
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    args: &[&str],
+) -> Result<FrontendAction, SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("reload")
+            .ok_or(anyhow!("internal error: reload command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    // Most settings (and the connections) are read from the config when they're used, so only
+    // the ones that are kept around have to be applied
+    let config = get_config()?;
+    let state = lsp_client.get_state();
+    state
+        .history
+        .lock()
+        .await
+        .set_max_size(config.get_history_size());
+    *state.diag_level.lock().await = config.get_diag_level();
+    db_client
+        .trim_warm_pools(config.get_warm_connections())
+        .await;
+    let logger = lsp_client.get_logger();
+    logger.set_log_format(config.get_log_format())?;
+    logger.standard("Reloaded the config.")?;
+    if config.get_lsp_channel_capacity() != lsp_client.get_channel_capacity() {
+        logger.warn("The new lsp_channel_capacity is only used after restarting sqlfriend.")?;
+    }
+
+    let connected = db_client.get_current_connection().await.is_some();
+    if connected {
+        // Restarted with the new interval
+        task_controller
+            .execute(task::Command::StartHeartbeat)
+            .await?;
+
+        // The LSP server type or its command may have changed. The other settings are applied
+        // even if the server can't be restarted.
+        if let Err(e) = handle_restart_lsp(task_controller, db_client, lsp_client, &[]).await {
+            logger.error(&e.to_string())?;
+        }
+    }
+
+    Ok(FrontendAction::ReloadSettings {
+        edit_mode: config.get_edit_mode(),
+        terminator: config.get_terminator(),
+        history_size: config.get_history_size(),
+        completion: CompletionSettings::from_config(&config),
+    })
+}
+
+async fn handle_set(
+    task_controller: &TaskController,
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    args: &[&str],
+) -> Result<Option<FrontendAction>, SqlFriendError> {
+    let (setting, value) = match args {
+        [setting, value] => (*setting, *value),
+        _ => {
+            let cmd = COMMANDS
+                .get("set")
+                .ok_or(anyhow!("internal error: set command doesn't exist"))?;
+
+            return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+        }
+    };
+
+    // Words in the setting may be separated by underscores, like in values
+    let key = setting.replace('_', "");
+    if !SETTINGS.contains_key(key.as_str()) {
+        return Err(SqlFriendError::InvalidSetting(setting.to_string()));
+    }
+
+    let mut config = get_config()?;
+    match key.as_str() {
+        "editmode" => {
+            let edit_mode = parse_setting_value(setting, value, &config::EditMode::VALUES)?;
+            config.set_edit_mode(edit_mode)?;
+            Ok(Some(FrontendAction::SetEditMode(edit_mode)))
+        }
+        "border" => {
+            let border_style = parse_setting_value(setting, value, &config::BorderStyle::VALUES)?;
+            config.set_border_style(border_style)?;
+            Ok(None)
+        }
+        "overflow" => {
+            let overflow = parse_setting_value(setting, value, &config::Overflow::VALUES)?;
+            config.set_overflow(overflow)?;
+            Ok(None)
+        }
+        "maxcolwidth" => {
+            let max_col_width = value.parse().map_err(|_| {
+                SqlFriendError::InvalidSettingValue(
+                    setting.to_string(),
+                    value.to_string(),
+                    "a non-negative number (0 to disable)".to_string(),
+                )
+            })?;
+            config.set_max_col_width(max_col_width)?;
+            Ok(None)
+        }
+        "format" => {
+            let output_format = parse_setting_value(setting, value, &config::OutputFormat::VALUES)?;
+            config.set_output_format(output_format)?;
+            Ok(None)
+        }
+        "binary" => {
+            let binary_format = parse_setting_value(setting, value, &config::BinaryFormat::VALUES)?;
+            config.set_binary_format(binary_format)?;
+            Ok(None)
+        }
+        "bool" => {
+            let bool_format = parse_setting_value(setting, value, &config::BoolFormat::VALUES)?;
+            config.set_bool_format(bool_format)?;
+            Ok(None)
+        }
+        "completiondebounce" => {
+            let completion_debounce_ms = value.parse().map_err(|_| {
+                SqlFriendError::InvalidSettingValue(
+                    setting.to_string(),
+                    value.to_string(),
+                    "a non-negative number of milliseconds (0 to disable)".to_string(),
+                )
+            })?;
+            config.set_completion_debounce_ms(completion_debounce_ms)?;
+            Ok(Some(FrontendAction::SetCompletionSettings(
+                CompletionSettings::from_config(&config),
+            )))
+        }
+        "keywordcase" => {
+            let keyword_case = parse_setting_value(setting, value, &config::KeywordCase::VALUES)?;
+            config.set_completion_keyword_case(keyword_case)?;
+            Ok(Some(FrontendAction::SetCompletionSettings(
+                CompletionSettings::from_config(&config),
+            )))
+        }
+        "diaglevel" => {
+            let diag_level = parse_setting_value(setting, value, &config::DiagnosticLevel::VALUES)?;
+            config.set_diag_level(diag_level)?;
+            *lsp_client.get_state().diag_level.lock().await = diag_level;
+            Ok(None)
+        }
+        "completiontriggers" => {
+            // There is no way to pass an empty value, so `off` turns off the triggers instead
+            let triggers = if value.eq_ignore_ascii_case(config::Config::COMPLETION_TRIGGERS_OFF) {
+                ""
+            } else {
+                value
+            };
+            config.set_completion_triggers(triggers)?;
+            Ok(Some(FrontendAction::SetCompletionSettings(
+                CompletionSettings::from_config(&config),
+            )))
+        }
+        "terminator" => {
+            let terminator = config::Terminator::parse(value).ok_or_else(|| {
+                SqlFriendError::InvalidSettingValue(
+                    setting.to_string(),
+                    value.to_string(),
+                    format!(
+                        "text without whitespace (such as `;`) or `{}`",
+                        config::Terminator::BLANK_LINE
+                    ),
+                )
+            })?;
+            config.set_terminator(&terminator)?;
+            Ok(Some(FrontendAction::SetTerminator(terminator)))
+        }
+        "stripcomments" => {
+            config.set_strip_comments(parse_toggle(setting, value)?)?;
+            Ok(None)
+        }
+        "headertypes" => {
+            config.set_header_types(parse_toggle(setting, value)?)?;
+            Ok(None)
+        }
+        "footer" => {
+            config.set_footer(parse_toggle(setting, value)?)?;
+            Ok(None)
+        }
+        "historysize" => {
+            let history_size = value.parse().map_err(|_| {
+                SqlFriendError::InvalidSettingValue(
+                    setting.to_string(),
+                    value.to_string(),
+                    "a non-negative number of lines (0 to disable)".to_string(),
+                )
+            })?;
+            config.set_history_size(history_size)?;
+            lsp_client
+                .get_state()
+                .history
+                .lock()
+                .await
+                .set_max_size(history_size);
+            Ok(Some(FrontendAction::SetHistorySize(history_size)))
+        }
+        "warmconnections" => {
+            let warm_connections = value.parse().map_err(|_| {
+                SqlFriendError::InvalidSettingValue(
+                    setting.to_string(),
+                    value.to_string(),
+                    "a non-negative number of connections (0 to disable)".to_string(),
+                )
+            })?;
+            config.set_warm_connections(warm_connections)?;
+            db_client.trim_warm_pools(warm_connections).await;
+            Ok(None)
+        }
+        "paging" => {
+            config.set_paging(parse_toggle(setting, value)?)?;
+            Ok(None)
+        }
+        "pagesize" => {
+            let page_size = if value.eq_ignore_ascii_case(config::Config::PAGE_SIZE_AUTO) {
+                None
+            } else {
+                let size = value.parse().ok().filter(|size| *size > 0).ok_or_else(|| {
+                    SqlFriendError::InvalidSettingValue(
+                        setting.to_string(),
+                        value.to_string(),
+                        format!(
+                            "a positive number of rows or `{}`",
+                            config::Config::PAGE_SIZE_AUTO
+                        ),
+                    )
+                })?;
+                Some(size)
+            };
+            config.set_page_size(page_size)?;
+            Ok(None)
+        }
+        "confirmdestructive" => {
+            config.set_confirm_destructive(parse_toggle(setting, value)?)?;
+            Ok(None)
+        }
+        "readonly" => {
+            config.set_read_only(parse_toggle(setting, value)?)?;
+            Ok(None)
+        }
+        "prompt" => {
+            if value.trim().is_empty() {
+                return Err(SqlFriendError::InvalidSettingValue(
+                    setting.to_string(),
+                    value.to_string(),
+                    format!(
+                        "a non-empty prompt, optionally with {}, {} or {}",
+                        config::Config::CONNECTION_PLACEHOLDER,
+                        config::Config::TRANSACTION_PLACEHOLDER,
+                        config::Config::HEALTH_PLACEHOLDER
+                    ),
+                ));
+            }
+
+            config.set_prompt(value)?;
+            Ok(None)
+        }
+        "pager" => {
+            if value.trim().is_empty() {
+                return Err(SqlFriendError::InvalidSettingValue(
+                    setting.to_string(),
+                    value.to_string(),
+                    format!("a command or `{}`", config::Config::PAGER_OFF),
+                ));
+            }
+
+            let pager =
+                Some(value).filter(|value| !value.eq_ignore_ascii_case(config::Config::PAGER_OFF));
+            config.set_pager(pager)?;
+            Ok(None)
+        }
+        "lspserver" => {
+            set_lsp_server(task_controller, db_client, lsp_client, &mut config, value).await?;
+            Ok(None)
+        }
+        _ => Err(anyhow!("internal error: setting {key} isn't handled").into()),
+    }
+}
+
+/// Parse the value of a setting as one of the given variants, compared case-insensitively to
+/// their names.
+fn parse_setting_value<T: Debug + Copy>(
+    setting: &str,
+    value: &str,
+    variants: &[T],
+) -> Result<T, SqlFriendError> {
+    // Words in the value may be separated by underscores, e.g. `true_false` for `TrueFalse`
+    let value_without_underscores = value.replace('_', "");
+    variants
+        .iter()
+        .find(|variant| format!("{variant:?}").eq_ignore_ascii_case(&value_without_underscores))
+        .copied()
+        .ok_or(SqlFriendError::InvalidSettingValue(
+            setting.to_string(),
+            value.to_string(),
+            format!("one of {variants:?}"),
+        ))
+}
+
+/// Parse the value of an on/off setting.
+fn parse_toggle(setting: &str, value: &str) -> Result<bool, SqlFriendError> {
+    match value.to_lowercase().as_str() {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(SqlFriendError::InvalidSettingValue(
+            setting.to_string(),
+            value.to_string(),
+            "`on` or `off`".to_string(),
+        )),
+    }
+}
+
+fn handle_show(logger: &Logger, args: &[&str]) -> Result<(), SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("show")
+            .ok_or(anyhow!("internal error: show command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    let config = get_config()?;
+    let output_lines = SETTINGS
+        .keys()
+        .filter_map(|setting| {
+            let value = current_setting_value(&config, setting)?;
+            Some(format!("\t{setting:20} {value}"))
+        })
+        .collect::<Vec<_>>();
+    logger.result(&output_lines.join("\n"))?;
+
+    Ok(())
+}
+
+async fn handle_i(
+    db_client: &DbClient,
+    logger: &Logger,
+    args: &[&str],
+) -> Result<(), SqlFriendError> {
+    let (path, continue_on_error) = match args {
+        [path] => (*path, false),
+        [path, "--continue-on-error"] | ["--continue-on-error", path] => (*path, true),
+        _ => {
+            let cmd = COMMANDS
+                .get("i")
+                .ok_or(anyhow!("internal error: i command doesn't exist"))?;
+
+            return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+        }
+    };
+
+    if db_client.get_current_connection().await.is_none() {
+        return Err(SqlFriendError::NoActiveConnection);
+    }
+
+    let config = get_config()?;
+    let sql =
+        fs::read_to_string(path).with_context(|| format!("failed to read SQL file {path}"))?;
+    if config.get_confirm_destructive() && !confirm_destructive(&sql, db_client.dialect().await)? {
+        logger.standard("Script not run.")?;
+        return Ok(());
+    }
+
+    script::run_script(
+        db_client,
+        path,
+        &sql,
+        config.get_output_format(),
+        continue_on_error,
+        |output| match output {
+            ScriptOutput::Result(result) => logger.result(&result),
+            ScriptOutput::Error(msg) => logger.error(&msg),
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Ask the user to confirm running the SQL if any of its statements can remove or overwrite
+/// data. Returns true if it should be run.
+pub fn confirm_destructive(sql: &str, dialect: Dialect) -> anyhow::Result<bool> {
+    let Some(description) = sql::split_statements(sql, dialect)
+        .into_iter()
+        .find_map(|statement| sql::describe_destructive(statement, dialect))
+    else {
+        return Ok(true);
+    };
+
+    Ok(confirm!(format!("Run {description}?")))
+}
+
+async fn handle_transaction(
+    db_client: &DbClient,
+    logger: &Logger,
+    name: &str,
+    args: &[&str],
+) -> Result<(), SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get(name)
+            .ok_or(anyhow!("internal error: {name} command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    let msg = match name {
+        "begin" => {
+            db_client.begin().await?;
+            "Transaction started."
+        }
+        "commit" => {
+            db_client.commit().await?;
+            "Transaction committed."
+        }
+        "rollback" => {
+            db_client.rollback().await?;
+            "Transaction rolled back."
+        }
+        _ => return Err(anyhow!("internal error: {name} is not a transaction command").into()),
+    };
+
+    logger.standard(msg)?;
+    Ok(())
+}
+
+fn handle_autocommit(
+    db_client: &DbClient,
+    logger: &Logger,
+    args: &[&str],
+) -> Result<(), SqlFriendError> {
+    match args {
+        [] => {}
+        [value] => db_client.set_autocommit(parse_toggle("autocommit", value)?),
+        _ => {
+            let cmd = COMMANDS
+                .get("autocommit")
+                .ok_or(anyhow!("internal error: autocommit command doesn't exist"))?;
+
+            return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+        }
+    }
+
+    let state = if db_client.is_autocommit() {
+        "on"
+    } else {
+        "off"
+    };
+    logger.standard(&format!("Autocommit is {state}."))?;
+
+    Ok(())
+}
+
+async fn handle_o(lsp_client: &LspClient, args: &[&str]) -> Result<(), SqlFriendError> {
+    let path = match args {
+        [] => None,
+        [path] => Some(PathBuf::from(path)),
+        _ => {
+            let cmd = COMMANDS
+                .get("o")
+                .ok_or(anyhow!("internal error: o command doesn't exist"))?;
+
+            return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+        }
+    };
+
+    let msg = match &path {
+        Some(path) => format!(
+            "The result of the next query will be written to {}.",
+            path.display()
+        ),
+        None => "Output redirect cancelled.".to_string(),
+    };
+
+    *lsp_client.get_state().output_redirect.lock().await = path;
+    lsp_client.get_logger().standard(&msg)?;
+
+    Ok(())
+}
+
+async fn handle_g(lsp_client: &LspClient, args: &[&str]) -> Result<FrontendAction, SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("g")
+            .ok_or(anyhow!("internal error: g command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    let statement = lsp_client
+        .get_state()
+        .last_statement
+        .lock()
+        .await
+        .clone()
+        .ok_or(anyhow!("no statement has been run yet"))?;
+
+    Ok(FrontendAction::RunStatement(statement))
+}
+
+async fn handle_e(lsp_client: &LspClient, args: &[&str]) -> Result<FrontendAction, SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("e")
+            .ok_or(anyhow!("internal error: e command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    // Start with an empty buffer if no statement has been run yet
+    let statement = lsp_client
+        .get_state()
+        .last_statement
+        .lock()
+        .await
+        .clone()
+        .unwrap_or_default();
+
+    Ok(FrontendAction::EditStatement(statement))
+}
+
+/// The query is taken from the line as is, since tokenizing it would remove its quotes.
+fn handle_explain(line: &str, dialect: Dialect) -> Result<FrontendAction, SqlFriendError> {
+    let cmd = COMMANDS
+        .get("explain")
+        .ok_or(anyhow!("internal error: explain command doesn't exist"))?;
+    let usage = || SqlFriendError::InvalidCommandUsage(cmd.usage.to_string());
+
+    let sql = line
+        .trim()
+        .split_once(char::is_whitespace)
+        .map(|(_, sql)| sql.trim_start())
+        .ok_or_else(usage)?;
+    let (keyword, rest) = sql.split_once(char::is_whitespace).unwrap_or((sql, ""));
+    let (sql, analyze) = if keyword.eq_ignore_ascii_case("analyze") {
+        (rest, true)
+    } else {
+        (sql, false)
+    };
+
+    let [query] = sql::split_statements(sql, dialect)[..] else {
+        return Err(usage());
+    };
+
+    Ok(FrontendAction::Explain {
+        query: query.to_string(),
+        analyze,
+    })
+}
+
+async fn handle_save(lsp_client: &LspClient, args: &[&str]) -> Result<(), SqlFriendError> {
+    let [name] = args else {
+        let cmd = COMMANDS
+            .get("save")
+            .ok_or(anyhow!("internal error: save command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    };
+
+    let statement = lsp_client
+        .get_state()
+        .last_statement
+        .lock()
+        .await
+        .clone()
+        .ok_or(anyhow!("no statement has been run yet"))?;
+
+    get_config()?.save_snippet(name, &statement)?;
+    lsp_client
+        .get_logger()
+        .standard(&format!("Saved snippet {name}."))?;
+
+    Ok(())
+}
+
+fn handle_run(args: &[&str]) -> Result<FrontendAction, SqlFriendError> {
+    let [name] = args else {
+        let cmd = COMMANDS
+            .get("run")
+            .ok_or(anyhow!("internal error: run command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    };
+
+    let statement = get_config()?
+        .get_snippets()
+        .get(*name)
+        .cloned()
+        .ok_or(SqlFriendError::InvalidSnippetName(name.to_string()))?;
+
+    Ok(FrontendAction::RunStatement(statement))
+}
+
+fn handle_snippets(logger: &Logger, args: &[&str]) -> Result<(), SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("snippets")
+            .ok_or(anyhow!("internal error: snippets command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    let config = get_config()?;
+    let output = if config.get_snippets().is_empty() {
+        "No saved snippets.".to_string()
+    } else {
+        config
+            .get_snippets()
+            .iter()
+            .map(|(name, sql)| format!("{name}: {sql}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    logger.result(&output)?;
+    Ok(())
+}
+
+fn handle_delete_snippet(logger: &Logger, args: &[&str]) -> Result<(), SqlFriendError> {
+    let [name] = args else {
+        let cmd = COMMANDS.get("delete-snippet").ok_or(anyhow!(
+            "internal error: delete-snippet command doesn't exist"
+        ))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    };
+
+    get_config()?.delete_snippet(name)?;
+    logger.standard(&format!("Deleted snippet {name}."))?;
+
+    Ok(())
+}
+
+async fn handle_ping(
+    db_client: &DbClient,
+    logger: &Logger,
+    args: &[&str],
+) -> Result<(), SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("ping")
+            .ok_or(anyhow!("internal error: ping command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    let name = match db_client.get_current_connection().await.as_ref() {
+        Some(connection) => connection.name.clone(),
+        None => return Err(SqlFriendError::NoActiveConnection),
+    };
+
+    let elapsed = db_client.ping().await?;
+    logger.standard(&format!(
+        "Reply from {name} in {}.",
+        output::millis(elapsed)
+    ))?;
+    Ok(())
+}
+
+async fn handle_lsp(lsp_client: &LspClient, args: &[&str]) -> Result<(), SqlFriendError> {
+    if args != ["log"] {
+        let cmd = COMMANDS
+            .get("lsp")
+            .ok_or(anyhow!("internal error: lsp command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    let lines = lsp_client.get_state().lsp_stderr.lock().await;
+    let msg = if lines.is_empty() {
+        "The LSP server hasn't written anything to stderr.".to_string()
+    } else {
+        lines.iter().cloned().collect::<Vec<_>>().join("\n")
+    };
+    lsp_client.get_logger().result(&msg)?;
+
+    Ok(())
+}
+
+async fn handle_refresh(
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    args: &[&str],
+) -> Result<(), SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("refresh")
+            .ok_or(anyhow!("internal error: refresh command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    if db_client.get_current_connection().await.is_none() {
+        return Err(SqlFriendError::NoActiveConnection);
+    }
+
+    let schema = db_client.fetch_schema().await?;
+    let msg = format!("Loaded {} tables.", schema.table_count());
+    *lsp_client.get_state().schema.lock().await = schema;
+    lsp_client.get_logger().standard(&msg)?;
+
+    Ok(())
+}
+
+async fn handle_copy_result(lsp_client: &LspClient, args: &[&str]) -> Result<(), SqlFriendError> {
+    if !args.is_empty() {
+        let cmd = COMMANDS
+            .get("copy-result")
+            .ok_or(anyhow!("internal error: copy-result command doesn't exist"))?;
+
+        return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+    }
+
+    let format = get_config()?.get_output_format();
+    let output = match lsp_client.get_state().last_result.lock().await.as_ref() {
+        Some(result) => DbClient::render_result(result, format, None)?
+            .ok_or(anyhow!("the last query didn't return any rows"))?,
+        None => return Err(anyhow!("no query has been run yet").into()),
+    };
+
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(output))
+        .map_err(|e| anyhow!("failed to copy the result to the clipboard: {e}"))?;
+    lsp_client.get_logger().standard(&format!(
+        "Copied the result of the last query as {format:?}."
+    ))?;
+
+    Ok(())
+}
+
+async fn handle_profile(
+    task_controller: &TaskController,
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    args: &[&str],
+) -> Result<(), SqlFriendError> {
+    let logger = lsp_client.get_logger();
+    let profile = match args {
+        [] => {
+            let active = config::get_profile();
+            let active = active.as_deref().unwrap_or(config::DEFAULT_PROFILE);
+            let output = config::list_profiles()?
+                .into_iter()
+                .map(|profile| {
+                    let marker = if profile == active { "*" } else { " " };
+                    format!("{marker} {profile}")
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            logger.result(&output)?;
+            return Ok(());
+        }
+        [profile] => *profile,
+        _ => {
+            let cmd = COMMANDS
+                .get("profile")
+                .ok_or(anyhow!("internal error: profile command doesn't exist"))?;
+
+            return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+        }
+    };
+
+    // New profiles are created by starting with `--profile`, so that a typo doesn't switch to an
+    // empty profile
+    let profiles = config::list_profiles()?;
+    if !profiles.iter().any(|existing| existing == profile) {
+        return Err(SqlFriendError::UnknownProfile(
+            profile.to_string(),
+            profiles.join(", "),
+        ));
+    }
+
+    config::set_profile(Some(profile))?;
+    logger.standard(&format!("Switched to profile {profile}."))?;
+
+    // Reload the connection of the new profile
+    let config = get_config()?;
+    match config.get_current_connection() {
+        Some(connection) => {
+            connection
+                .connect(task_controller, db_client, lsp_client)
+                .await?
+        }
+        None => {
+            db_client.disconnect().await;
+            task_controller
+                .execute(task::Command::StopHeartbeat)
+                .await?;
+            task_controller.execute(task::Command::KillLsp).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn handle_history(
+    lsp_client: &LspClient,
+    args: &[&str],
+) -> Result<FrontendAction, SqlFriendError> {
+    // Number of entries to print.
+    const RECENT_ENTRIES: usize = 20;
+
+    let scope = match args {
+        [] | ["all"] => HistoryScope::All,
+        ["sql"] => HistoryScope::Sql,
+        ["commands"] => HistoryScope::Commands,
+        _ => {
+            let cmd = COMMANDS
+                .get("history")
+                .ok_or(anyhow!("internal error: history command doesn't exist"))?;
+
+            return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
+        }
+    };
+
+    let mut history = lsp_client.get_state().history.lock().await;
+    history.set_scope(scope);
+
+    let entries = history.entries_in_scope(scope).collect::<Vec<_>>();
+    let output = entries
+        .iter()
+        .enumerate()
+        .skip(entries.len().saturating_sub(RECENT_ENTRIES))
+        .map(|(i, entry)| format!("{:>5}  {}", i + 1, entry.line))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    if !output.is_empty() {
+        lsp_client.get_logger().result(&output)?;
+    }
+
+    Ok(FrontendAction::SetHistoryScope(scope))
+}
+
 async fn handle_set_lsp_server(
     task_controller: &TaskController,
     db_client: &DbClient,
     lsp_client: &LspClient,
     args: &[&str],
 ) -> Result<(), SqlFriendError> {
-    if args.len() != 1 {
+    let [server] = args else {
         let cmd = COMMANDS.get("set_lsp_server").ok_or(anyhow!(
             "internal error: set_lsp_server command doesn't exist"
         ))?;
 
         return Err(SqlFriendError::InvalidCommandUsage(cmd.usage.to_string()));
-    }
+    };
 
-    let server_arg = args[0];
+    handle_set(
+        task_controller,
+        db_client,
+        lsp_client,
+        &["lsp_server", server],
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Change the LSP server and reconnect to the current connection, if any, to start it.
+async fn set_lsp_server(
+    task_controller: &TaskController,
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    config: &mut config::Config,
+    server_arg: &str,
+) -> Result<(), SqlFriendError> {
     let server_type = match server_arg.to_lowercase().as_str() {
         "sqls" => config::LspServerType::Sqls,
         "sqlls" => config::LspServerType::SqlLs,
@@ -320,7 +2251,6 @@ async fn handle_set_lsp_server(
         }
     };
 
-    let mut config = get_config()?;
     config.set_lsp_server(server_type.clone())?;
 
     if let Some(connection) = config.get_current_connection() {
@@ -331,3 +2261,220 @@ async fn handle_set_lsp_server(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_tokenize_command() {
+        assert_eq!(tokenize("/use my_db").unwrap(), vec!["/use", "my_db"]);
+        assert_eq!(
+            tokenize("  /set   format  csv  ").unwrap(),
+            vec!["/set", "format", "csv"]
+        );
+        assert!(tokenize("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn can_list_setting_values() {
+        assert_eq!(
+            setting_values("bool").unwrap(),
+            ["true_false", "one_zero", "t_f"]
+        );
+        assert_eq!(setting_values("header_types").unwrap(), ["on", "off"]);
+        assert!(setting_values("maxcolwidth").unwrap().is_empty());
+        assert!(setting_values("colour").is_none());
+
+        // Every value that is completed can be set
+        for value in setting_values("bool").unwrap() {
+            assert!(parse_setting_value("bool", value, &config::BoolFormat::VALUES).is_ok());
+        }
+    }
+
+    #[test]
+    fn can_show_every_setting() {
+        let config = config::Config::default();
+        for setting in SETTINGS.keys() {
+            assert!(
+                current_setting_value(&config, setting).is_some(),
+                "{setting} has no value to show"
+            );
+        }
+
+        assert_eq!(
+            current_setting_value(&config, "format").as_deref(),
+            Some("table")
+        );
+        assert_eq!(
+            current_setting_value(&config, "lspserver").as_deref(),
+            Some("sqls")
+        );
+    }
+
+    #[test]
+    fn can_parse_explain() {
+        assert_eq!(
+            handle_explain("/explain SELECT 'a  b' FROM t;", Dialect::Standard).unwrap(),
+            FrontendAction::Explain {
+                query: "SELECT 'a  b' FROM t".to_string(),
+                analyze: false,
+            }
+        );
+        assert_eq!(
+            handle_explain("/ex ANALYZE\nDELETE FROM t", Dialect::Standard).unwrap(),
+            FrontendAction::Explain {
+                query: "DELETE FROM t".to_string(),
+                analyze: true,
+            }
+        );
+        assert!(handle_explain("/explain", Dialect::Standard).is_err());
+        assert!(handle_explain("/explain analyze", Dialect::Standard).is_err());
+        assert!(handle_explain("/explain SELECT 1; SELECT 2", Dialect::Standard).is_err());
+    }
+
+    #[test]
+    fn can_resolve_command() {
+        assert_eq!(resolve_command("list").unwrap(), "list");
+        assert_eq!(resolve_command("l").unwrap(), "list");
+        assert_eq!(resolve_command("q").unwrap(), "quit");
+        assert_eq!(resolve_command("ro").unwrap(), "rollback");
+        assert_eq!(resolve_command("e").unwrap(), "e");
+
+        match resolve_command("de") {
+            Err(SqlFriendError::AmbiguousCommand(name, matching)) => {
+                assert_eq!(name, "/de");
+                assert_eq!(matching, vec!["delete", "delete-snippet"]);
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+        assert!(matches!(
+            resolve_command(""),
+            Err(SqlFriendError::InvalidCommand(_, Some("help")))
+        ));
+        assert!(matches!(
+            resolve_command("lsit"),
+            Err(SqlFriendError::InvalidCommand(_, Some("list")))
+        ));
+    }
+
+    #[test]
+    fn can_detect_commands() {
+        assert!(is_maybe_command("/help"));
+        assert!(is_maybe_command("  /list"));
+        assert!(is_maybe_command("/"));
+        assert!(!is_maybe_command("SELECT 1 / 2;"));
+        assert!(!is_maybe_command("/* comment */ SELECT 1;"));
+    }
+
+    #[test]
+    fn can_suggest_command() {
+        assert_eq!(suggest_command(""), Some("help"));
+        assert_eq!(suggest_command("lst"), Some("list"));
+        assert_eq!(suggest_command("lsit"), Some("list"));
+        assert_eq!(suggest_command("hepl"), Some("help"));
+        assert_eq!(suggest_command("histroy"), Some("history"));
+        assert_eq!(suggest_command("cpyres"), Some("copy-result"));
+        assert_eq!(suggest_command("xyz"), None);
+    }
+
+    #[test]
+    fn can_tokenize_quoted_args() {
+        assert_eq!(tokenize(r#"/use "my db""#).unwrap(), vec!["/use", "my db"]);
+        assert_eq!(
+            tokenize("/i 'dir/my file.sql' --continue-on-error").unwrap(),
+            vec!["/i", "dir/my file.sql", "--continue-on-error"]
+        );
+        assert_eq!(
+            tokenize(r#"/use "say \"hi\"" it's' "" "#).unwrap(),
+            vec!["/use", r#"say "hi""#, "its", ""]
+        );
+        assert_eq!(
+            tokenize(r#"/i "C:\dir\a.sql""#).unwrap(),
+            vec!["/i", r"C:\dir\a.sql"]
+        );
+    }
+
+    #[test]
+    fn can_detect_unclosed_quotes() {
+        assert!(matches!(
+            tokenize(r#"/use "my db"#),
+            Err(SqlFriendError::UnclosedQuote(_))
+        ));
+        assert!(matches!(
+            tokenize("/use 'my db"),
+            Err(SqlFriendError::UnclosedQuote(_))
+        ));
+    }
+
+    #[test]
+    fn can_parse_connection_list() {
+        let names = |connections: Vec<config::Connection>| {
+            connections
+                .into_iter()
+                .map(|connection| connection.name)
+                .collect::<Vec<_>>()
+        };
+
+        let lines = "\
+# local databases
+app = postgres://me@localhost/app
+
+cache=sqlite://cache.sqlite
+";
+        assert_eq!(
+            names(parse_connection_list(lines).unwrap()),
+            vec!["app", "cache"]
+        );
+
+        let json = r#"[{"name": "app", "url": "mysql://me@localhost/app"}]"#;
+        assert_eq!(names(parse_connection_list(json).unwrap()), vec!["app"]);
+
+        let json =
+            r#"[{"name": "a=b", "url": "sqlite://a.sqlite", "read_only": true, "tags": ["dev"]}]"#;
+        let connections = parse_connection_list(json).unwrap();
+        assert_eq!(connections[0].name, "a=b");
+        assert!(connections[0].is_read_only());
+        assert!(connections[0].has_tag("dev"));
+
+        assert!(parse_connection_list("postgres://localhost/app").is_err());
+        assert!(parse_connection_list("app=ftp://localhost").is_err());
+    }
+
+    fn tagged_connection(name: &str, tags: &[&str]) -> config::Connection {
+        config::Connection {
+            tags: (!tags.is_empty()).then(|| tags.iter().map(|tag| tag.to_string()).collect()),
+            ..config::Connection::sqlite_for_test(name, &format!("{name}.sqlite"))
+        }
+    }
+
+    #[test]
+    fn can_group_connections_by_tag() {
+        let connections = [
+            tagged_connection("a", &["staging"]),
+            tagged_connection("b", &[]),
+            tagged_connection("c", &["prod", "staging"]),
+        ];
+        let connections = connections.iter().collect::<Vec<_>>();
+
+        let groups = group_by_tag(&connections)
+            .into_iter()
+            .map(|(tag, connections)| {
+                let names = connections
+                    .iter()
+                    .map(|connection| connection.name.as_str())
+                    .collect::<Vec<_>>();
+                (tag, names)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            groups,
+            vec![
+                (Some("prod"), vec!["c"]),
+                (Some("staging"), vec!["a", "c"]),
+                (None, vec!["b"]),
+            ]
+        );
+    }
+}
@@ -1,21 +1,75 @@
-use std::{ops::Deref, sync::Arc};
+use std::{
+    collections::VecDeque,
+    fs,
+    future::Future,
+    io,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail, Context};
+use ariadne::{Color, ReportKind};
+#[cfg(feature = "decimal")]
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use futures_util::{stream::BoxStream, TryStreamExt};
 use sqlx::{
     any::install_default_drivers,
-    mysql::{MySqlConnectOptions, MySqlRow},
-    postgres::{PgConnectOptions, PgRow},
-    sqlite::{SqliteConnectOptions, SqliteRow},
-    Column, FromRow, MySql, MySqlPool, PgPool, Postgres, Row, Sqlite, SqlitePool, ValueRef,
+    mysql::{MySqlConnectOptions, MySqlQueryResult, MySqlRow},
+    pool::PoolOptions,
+    postgres::{PgConnectOptions, PgDatabaseError, PgErrorPosition, PgQueryResult, PgRow},
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteQueryResult, SqliteRow},
+    Column, ColumnIndex, Connection as _, Database, Decode, Either, Executor, MySql, MySqlPool,
+    PgPool, Pool, Postgres, Row, Sqlite, SqlitePool, Transaction, Type, TypeInfo, ValueRef,
 };
 use sqlx_core::type_checking::TypeChecking;
-use tokio::sync::{Mutex, RwLock, RwLockReadGuard};
+use tokio::{
+    sync::{mpsc, Mutex, RwLock, RwLockReadGuard},
+    task::AbortHandle,
+};
 
 use crate::{
-    config::{self, Connection},
+    config::{self, get_config, Connection, OutputFormat},
+    error::{self, SqlFriendError},
     logging::Logger,
+    output::{
+        self, report,
+        table::TableOptions,
+        value::{self, DateTimeKind, DateTimeValue, Value, ValueOptions},
+    },
+    pgpass,
+    schema::Schema,
+    sql::{self, Dialect, StatementKind},
+    state::State,
 };
 
+/// Longest time to wait before retrying to connect.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Number of lines on the terminal that aren't used for rows when paging: the header and its
+/// separator, the footer and the prompt.
+const PAGE_MARGIN: usize = 4;
+
+/// Heading of errors returned by the database when running a query.
+const SQL_ERROR_KIND: &str = "SQL error";
+
+/// Queries returning the (table, column) pairs of the user's tables and views.
+const SQLITE_SCHEMA_QUERY: &str = "SELECT m.name, p.name FROM sqlite_master m \
+    JOIN pragma_table_info(m.name) p \
+    WHERE m.type IN ('table', 'view') AND m.name NOT LIKE 'sqlite_%' \
+    ORDER BY m.name, p.cid";
+const MYSQL_SCHEMA_QUERY: &str = "SELECT CAST(table_name AS CHAR), CAST(column_name AS CHAR) \
+    FROM information_schema.columns WHERE table_schema = DATABASE() \
+    ORDER BY table_name, ordinal_position";
+const POSTGRES_SCHEMA_QUERY: &str = "SELECT table_name::text, column_name::text \
+    FROM information_schema.columns \
+    WHERE table_schema = ANY(current_schemas(false)) \
+    ORDER BY table_name, ordinal_position";
+
 #[derive(Debug, Clone)]
 enum DbPool {
     Sqlite(SqlitePool),
@@ -23,112 +77,689 @@ enum DbPool {
     Postgres(PgPool),
 }
 
+/// Transaction pinned to a single connection of the pool.
+enum DbTransaction {
+    Sqlite(Transaction<'static, Sqlite>),
+    MySql(Transaction<'static, MySql>),
+    Postgres(Box<Transaction<'static, Postgres>>),
+}
+
 #[derive(Default, Clone)]
 pub struct DbClient {
     /// Database connection pool.
     pool: Arc<Mutex<Option<DbPool>>>,
 
+    /// Open transaction. While set, all queries run in it instead of on the pool.
+    transaction: Arc<Mutex<Option<DbTransaction>>>,
+
+    /// True if autocommit is turned off, in which case a transaction is implicitly started for
+    /// queries run while no transaction is open.
+    manual_commit: Arc<AtomicBool>,
+
     /// Connection that the pool is using.
     current_connection: Arc<RwLock<Option<Connection>>>,
+
+    /// Pools of previously used connections that are kept open, least recently used first. See
+    /// `Config::get_warm_connections`.
+    warm_pools: Arc<Mutex<Vec<(Connection, DbPool)>>>,
+
+    /// Task reading the rows of the paged query from the pool, see `start_paging`.
+    row_stream: Arc<Mutex<Option<AbortHandle>>>,
 }
 
-#[derive(Default)]
-struct DbRow {
-    /// Columns and their values in this row.
-    columns: Vec<(String, String)>,
+/// Rows as returned by the driver.
+enum DriverRows {
+    Sqlite(Vec<SqliteRow>),
+    MySql(Vec<MySqlRow>),
+    Postgres(Vec<PgRow>),
 }
 
-impl FromRow<'_, SqliteRow> for DbRow {
-    fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
-        let mut db_row = DbRow::default();
-        for (index, column) in row.columns().iter().enumerate() {
-            db_row
-                .columns
-                .push((column.name().to_string(), format_sqlite_value(row, index)));
+/// Next page of the results of a query that is shown a page at a time, see `fetch_next_page`.
+/// The query is only run once, and its rows are read as the pages are shown.
+#[derive(Debug)]
+pub struct Page {
+    /// The query, for pointing at the position of an error in it.
+    query: String,
+
+    /// Names of the columns, known once the first row has been read.
+    columns: Vec<String>,
+
+    /// Type of each column, as named by the driver.
+    column_types: Vec<String>,
+
+    /// Rows that were read but haven't been shown yet.
+    rows: VecDeque<Vec<Value>>,
+
+    /// Rows that are still being read from the database, see `stream_rows`. None once all rows
+    /// have been read.
+    stream: Option<mpsc::Receiver<anyhow::Result<Option<QueryResult>>>>,
+
+    /// Index of the first row of the page.
+    offset: usize,
+
+    /// Number of rows on the page.
+    size: usize,
+}
+
+impl Page {
+    /// Read rows until count rows are waiting to be shown, or all rows have been read.
+    async fn read_rows(&mut self, count: usize) -> anyhow::Result<()> {
+        while self.rows.len() < count {
+            let Some(stream) = &mut self.stream else {
+                break;
+            };
+
+            match stream.recv().await {
+                Some(Ok(Some(result))) => {
+                    if self.columns.is_empty() {
+                        self.columns = result.columns;
+                        self.column_types = result.column_types;
+                    }
+                    self.rows.extend(result.rows);
+                }
+                Some(Ok(None)) => self.stream = None,
+                Some(Err(e)) => return Err(e),
+                None => bail!("the rest of the rows can't be read, the connection was closed"),
+            }
         }
 
-        Ok(db_row)
+        Ok(())
     }
 }
 
-fn format_sqlite_value(row: &SqliteRow, index: usize) -> String {
+/// Result of a query, with its values decoded from the database values.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryResult {
+    /// Names of the columns. Empty if no rows were returned, since the columns are only known
+    /// from the rows.
+    pub columns: Vec<String>,
+
+    /// Type of each column, as named by the driver.
+    pub column_types: Vec<String>,
+
+    /// Values of each row, in the same order as the columns.
+    pub rows: Vec<Vec<Value>>,
+
+    /// Number of rows inserted, updated or deleted by the query, if the driver reported it.
+    pub affected: Option<u64>,
+}
+
+impl QueryResult {
+    /// Create a result by decoding each value of the driver rows with decode_value.
+    fn from_rows<R: Row>(
+        rows: &[R],
+        affected: Option<u64>,
+        decode_value: impl Fn(&R, usize) -> Value,
+    ) -> Self {
+        let driver_columns = rows.first().map(Row::columns).unwrap_or_default();
+        let columns = driver_columns
+            .iter()
+            .map(|column| column.name().to_string())
+            .collect();
+        let column_types = driver_columns
+            .iter()
+            .map(|column| column.type_info().name().to_lowercase())
+            .collect();
+        let rows = rows
+            .iter()
+            .map(|row| {
+                (0..row.columns().len())
+                    .map(|index| decode_value(row, index))
+                    .collect()
+            })
+            .collect();
+
+        QueryResult {
+            columns,
+            column_types,
+            rows,
+            affected,
+        }
+    }
+}
+
+fn decode_sqlite_value(row: &SqliteRow, index: usize) -> Value {
     match row.try_get_raw(index) {
         Ok(value) => {
             if ValueRef::is_null(&value) {
-                return "<NULL>".to_string();
+                return Value::Null;
             }
 
-            // fmt_value_debug gives precedence to bool which is compatible with the
-            // INTEGER type in sqlite. We therefore need to try to decode the value
-            // manually as an i64, otherwise all INTEGER values will be decoded as bool.
-            let sqlite_value = value.to_owned();
-            let fmt_value =
-                if <i64 as sqlx_core::types::Type<Sqlite>>::compatible(&value.type_info()) {
-                    sqlx_core::type_checking::FmtValue::debug::<i64>(&sqlite_value)
-                } else {
-                    Sqlite::fmt_value_debug(&sqlite_value)
-                };
+            if value::is_binary_type(value.type_info().name()) {
+                return decode_binary_value(row.try_get(index));
+            }
+            if let Some(decoded) = decode_datetime_value(row, index) {
+                return decoded;
+            }
+            if let Some(decoded) = decode_bool_value(row, index) {
+                return decoded;
+            }
+            if let Some(decoded) = decode_scalar_value(row, index) {
+                return decoded;
+            }
 
-            format!("{fmt_value:?}").trim_matches('"').to_string()
+            let sqlite_value = value.to_owned();
+            let fmt_value = Sqlite::fmt_value_debug(&sqlite_value);
+            Value::Other(format!("{fmt_value:?}").trim_matches('"').to_string())
         }
-        Err(e) => format!("decode error: {e:?}"),
+        Err(e) => Value::Other(format!("decode error: {e:?}")),
     }
 }
 
-impl FromRow<'_, MySqlRow> for DbRow {
-    fn from_row(row: &MySqlRow) -> Result<Self, sqlx::Error> {
-        let mut db_row = DbRow::default();
-        for (index, column) in row.columns().iter().enumerate() {
-            db_row
-                .columns
-                .push((column.name().to_string(), format_mysql_value(row, index)));
-        }
+fn decode_mysql_value(row: &MySqlRow, index: usize) -> Value {
+    match row.try_get_raw(index) {
+        Ok(value) => {
+            if ValueRef::is_null(&value) {
+                return Value::Null;
+            }
+
+            if value::is_binary_type(value.type_info().name()) {
+                return decode_binary_value(row.try_get(index));
+            }
+            if let Some(decoded) = decode_datetime_value(row, index) {
+                return decoded;
+            }
+            if let Some(decoded) = decode_bool_value(row, index) {
+                return decoded;
+            }
+            #[cfg(feature = "decimal")]
+            if let Some(decoded) = decode_decimal_value(row, index) {
+                return decoded;
+            }
+            if let Some(decoded) = decode_scalar_value(row, index) {
+                return decoded;
+            }
+            // Unsigned integers too large for Int are kept as they are
+            if let Ok(value) = row.try_get::<u64, _>(index) {
+                return i64::try_from(value)
+                    .map(Value::Int)
+                    .unwrap_or_else(|_| Value::Other(value.to_string()));
+            }
 
-        Ok(db_row)
+            let mysql_value = ValueRef::to_owned(&value);
+            let fmt_value = MySql::fmt_value_debug(&mysql_value);
+            Value::Other(format!("{fmt_value:?}").trim_matches('"').to_string())
+        }
+        Err(e) => Value::Other(format!("decode error: {e:?}")),
     }
 }
 
-fn format_mysql_value(row: &MySqlRow, index: usize) -> String {
+fn decode_pg_value(row: &PgRow, index: usize) -> Value {
     match row.try_get_raw(index) {
         Ok(value) => {
             if ValueRef::is_null(&value) {
-                return "<NULL>".to_string();
+                return Value::Null;
             }
 
-            let mysql_value = ValueRef::to_owned(&value);
-            let fmt_value = MySql::fmt_value_debug(&mysql_value);
-            format!("{fmt_value:?}").trim_matches('"').to_string()
+            if value::is_binary_type(value.type_info().name()) {
+                return decode_binary_value(row.try_get(index));
+            }
+            if let Some(decoded) = decode_datetime_value(row, index) {
+                return decoded;
+            }
+            if let Some(decoded) = decode_bool_value(row, index) {
+                return decoded;
+            }
+            #[cfg(feature = "decimal")]
+            if let Some(decoded) = decode_decimal_value(row, index) {
+                return decoded;
+            }
+            if let Ok(json) = row.try_get(index) {
+                return Value::Json(json);
+            }
+            if let Some(decoded) = decode_scalar_value(row, index) {
+                return decoded;
+            }
+
+            let pg_value = ValueRef::to_owned(&value);
+            let fmt_value = Postgres::fmt_value_debug(&pg_value);
+            Value::Other(format!("{fmt_value:?}").trim_matches('"').to_string())
         }
-        Err(e) => format!("decode error: {e:?}"),
+        Err(e) => Value::Other(format!("decode error: {e:?}")),
+    }
+}
+
+/// Decode the value if its column has a date/time type. Returns None if it doesn't, or if the
+/// value can't be decoded (e.g. a timestamp stored as an integer in sqlite).
+///
+/// The declared type of the column is used since sqlite values only have a storage class.
+fn decode_datetime_value<R: Row>(row: &R, index: usize) -> Option<Value>
+where
+    usize: ColumnIndex<R>,
+    for<'r> NaiveDate: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> NaiveTime: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> NaiveDateTime: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> DateTime<Utc>: Decode<'r, R::Database> + Type<R::Database>,
+{
+    let type_name = row.try_column(index).ok()?.type_info().name();
+    let value = match value::datetime_kind(type_name)? {
+        DateTimeKind::Date => DateTimeValue::Date(row.try_get(index).ok()?),
+        DateTimeKind::Time => DateTimeValue::Time(row.try_get(index).ok()?),
+        DateTimeKind::Timestamp => DateTimeValue::Timestamp(row.try_get(index).ok()?),
+        DateTimeKind::TimestampTz => DateTimeValue::TimestampTz(row.try_get(index).ok()?),
+    };
+
+    Some(Value::DateTime(value))
+}
+
+/// Decode the value if its column has a boolean type. Returns None if it doesn't, or if the value
+/// can't be decoded.
+///
+/// sqlite has no real boolean, so only values in columns declared as BOOLEAN are booleans there.
+fn decode_bool_value<R: Row>(row: &R, index: usize) -> Option<Value>
+where
+    usize: ColumnIndex<R>,
+    for<'r> bool: Decode<'r, R::Database> + Type<R::Database>,
+{
+    let type_name = row.try_column(index).ok()?.type_info().name();
+    if !value::is_bool_type(type_name) {
+        return None;
     }
+
+    row.try_get(index).ok().map(Value::Bool)
 }
 
-impl FromRow<'_, PgRow> for DbRow {
-    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
-        let mut db_row = DbRow::default();
-        for (index, column) in row.columns().iter().enumerate() {
-            db_row
-                .columns
-                .push((column.name().to_string(), format_pg_value(row, index)));
+/// Decode the value if its column has a decimal type. Returns None if it doesn't, or if the value
+/// can't be decoded (e.g. `NaN` in postgres).
+#[cfg(feature = "decimal")]
+fn decode_decimal_value<R: Row>(row: &R, index: usize) -> Option<Value>
+where
+    usize: ColumnIndex<R>,
+    for<'r> BigDecimal: Decode<'r, R::Database> + Type<R::Database>,
+{
+    let type_name = row.try_column(index).ok()?.type_info().name();
+    if !value::is_decimal_type(type_name) {
+        return None;
+    }
+
+    row.try_get(index).ok().map(Value::Decimal)
+}
+
+/// Decode the value if it's a (signed) integer, a floating point number or text. Returns None if
+/// it isn't.
+///
+/// The integer types are tried first, since sqlx considers some of them compatible with bool
+/// (e.g. INTEGER in sqlite), which would otherwise turn integers into booleans.
+fn decode_scalar_value<R: Row>(row: &R, index: usize) -> Option<Value>
+where
+    usize: ColumnIndex<R>,
+    for<'r> i64: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> i32: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> i16: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> f64: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> f32: Decode<'r, R::Database> + Type<R::Database>,
+    for<'r> String: Decode<'r, R::Database> + Type<R::Database>,
+{
+    if let Ok(value) = row.try_get::<i64, _>(index) {
+        return Some(Value::Int(value));
+    }
+    if let Ok(value) = row.try_get::<i32, _>(index) {
+        return Some(Value::Int(value.into()));
+    }
+    if let Ok(value) = row.try_get::<i16, _>(index) {
+        return Some(Value::Int(value.into()));
+    }
+    // Single precision floats are converted through their shortest representation, so that e.g.
+    // 0.1 stays 0.1 instead of becoming 0.10000000149011612. They're decoded as f32 first since
+    // f64 can decode them as well in mysql.
+    let type_name = row.try_column(index).ok()?.type_info().name();
+    if matches!(type_name, "FLOAT" | "FLOAT4") {
+        if let Ok(value) = row.try_get::<f32, _>(index) {
+            return Some(Value::Float(
+                value.to_string().parse().unwrap_or(value.into()),
+            ));
         }
+    }
+    if let Ok(value) = row.try_get::<f64, _>(index) {
+        return Some(Value::Float(value));
+    }
+    if let Ok(value) = row.try_get::<String, _>(index) {
+        return Some(Value::Text(value));
+    }
+
+    None
+}
 
-        Ok(db_row)
+/// Collect the rows returned by a query, and the total number of rows affected by its statements
+/// (None if the driver reported no results).
+async fn collect_rows<Q, R>(
+    mut stream: BoxStream<'_, Result<Either<Q, R>, sqlx::Error>>,
+    rows_affected: impl Fn(&Q) -> u64,
+) -> Result<(Vec<R>, Option<u64>), sqlx::Error> {
+    let mut rows = vec![];
+    let mut affected = None;
+    while let Some(item) = stream.try_next().await? {
+        match item {
+            Either::Left(result) => *affected.get_or_insert(0) += rows_affected(&result),
+            Either::Right(row) => rows.push(row),
+        }
     }
+
+    Ok((rows, affected))
 }
 
-fn format_pg_value(row: &PgRow, index: usize) -> String {
-    match row.try_get_raw(index) {
-        Ok(value) => {
-            if ValueRef::is_null(&value) {
-                return "<NULL>".to_string();
+/// Read the rows of the query on a connection of the pool as they are received, decoding each row
+/// into a result of its own (so that the columns are known from the first row). None is sent once
+/// all rows have been read. Reading stops early if the receiver is dropped, or if the returned
+/// task is aborted.
+fn stream_rows(
+    pool: DbPool,
+    query: String,
+    buffered_rows: usize,
+) -> (
+    mpsc::Receiver<anyhow::Result<Option<QueryResult>>>,
+    AbortHandle,
+) {
+    let (rows_tx, rows_rx) = mpsc::channel(buffered_rows.max(1));
+    let task = tokio::spawn(async move {
+        match pool {
+            DbPool::Sqlite(p) => {
+                forward_rows(p.fetch(sqlx::query(&query)), decode_sqlite_value, rows_tx).await
+            }
+            DbPool::MySql(p) => {
+                forward_rows(p.fetch(sqlx::query(&query)), decode_mysql_value, rows_tx).await
+            }
+            DbPool::Postgres(p) => {
+                forward_rows(p.fetch(sqlx::query(&query)), decode_pg_value, rows_tx).await
             }
+        }
+    });
 
-            let pg_value = ValueRef::to_owned(&value);
-            let fmt_value = Postgres::fmt_value_debug(&pg_value);
-            format!("{fmt_value:?}").trim_matches('"').to_string()
+    (rows_rx, task.abort_handle())
+}
+
+/// Send the rows of the stream to rows_tx, see `stream_rows`.
+async fn forward_rows<R: Row>(
+    mut rows: BoxStream<'_, Result<R, sqlx::Error>>,
+    decode_value: impl Fn(&R, usize) -> Value,
+    rows_tx: mpsc::Sender<anyhow::Result<Option<QueryResult>>>,
+) {
+    loop {
+        let (row, done) = match rows.try_next().await {
+            Ok(Some(row)) => {
+                let result = QueryResult::from_rows(&[row], None, &decode_value);
+                (Ok(Some(result)), false)
+            }
+            Ok(None) => (Ok(None), true),
+            Err(e) => (Err(e.into()), true),
+        };
+
+        if rows_tx.send(row).await.is_err() || done {
+            return;
+        }
+    }
+}
+
+/// If the error is a database error with a known position in the query, replace it with a report
+/// pointing at the position. Otherwise, the message of the error is returned as is. Either way,
+/// the message is marked as an SQL error.
+fn with_error_position(query: &str, error: anyhow::Error) -> anyhow::Error {
+    let position = match error.downcast_ref::<sqlx::Error>() {
+        Some(sqlx::Error::Database(e)) => {
+            e.try_downcast_ref::<PgDatabaseError>()
+                .and_then(|e| match e.position()? {
+                    PgErrorPosition::Original(position) => Some(position),
+                    // The position is in a query other than the one that was run
+                    PgErrorPosition::Internal { .. } => None,
+                })
+        }
+        _ => None,
+    };
+
+    let Some(position) = position else {
+        return anyhow!("{SQL_ERROR_KIND}: {error}");
+    };
+
+    // Postgres positions are one-indexed
+    let range = report::token_range(query, position.saturating_sub(1));
+    let kind = ReportKind::Custom(SQL_ERROR_KIND, Color::Red);
+    match report::render(kind, query, range, &error.to_string()) {
+        Ok(report) => anyhow!(report),
+        Err(_) => anyhow!("{SQL_ERROR_KIND}: {error}"),
+    }
+}
+
+/// Append the type name to the column name, e.g. `id (int4)`. Types without a name (such as
+/// expressions in sqlite) are left out.
+fn with_type_name(name: &str, type_name: &str) -> String {
+    if type_name.is_empty() || type_name == "null" {
+        name.to_string()
+    } else {
+        format!("{name} ({type_name})")
+    }
+}
+
+fn decode_binary_value(bytes: Result<Vec<u8>, sqlx::Error>) -> Value {
+    match bytes {
+        Ok(bytes) => Value::Bytes(bytes),
+        Err(e) => Value::Other(format!("decode error: {e:?}")),
+    }
+}
+
+/// Create a pool that runs the setup statements on every new connection, so that session
+/// settings such as the default schema apply regardless of which connection a query runs on.
+async fn connect_pool<DB: Database>(
+    connect_options: <DB::Connection as sqlx::Connection>::Options,
+    setup_statements: Vec<String>,
+    logger: Option<&Logger>,
+) -> anyhow::Result<Pool<DB>>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    // The pool keeps retrying connections that fail (including in after_connect) until it times
+    // out, so a separate connection is made first, running the statements once, to fail right
+    // away with the actual error.
+    let mut conn = DB::Connection::connect_with(&connect_options).await?;
+    for statement in &setup_statements {
+        if let Some(logger) = logger {
+            logger.debug(&format!("Running setup statement: {statement}"))?;
         }
-        Err(e) => format!("decode error: {e:?}"),
+
+        conn.execute(statement.as_str())
+            .await
+            .with_context(|| format!("setup statement `{statement}` failed"))?;
     }
+    conn.close().await?;
+
+    let setup_statements = Arc::new(setup_statements);
+    let pool = PoolOptions::new()
+        .after_connect(move |conn: &mut DB::Connection, _| {
+            let setup_statements = setup_statements.clone();
+            Box::pin(async move {
+                for statement in setup_statements.iter() {
+                    conn.execute(statement.as_str()).await?;
+                }
+                Ok(())
+            })
+        })
+        .connect_with(connect_options)
+        .await?;
+
+    Ok(pool)
+}
+
+/// Time to wait before the given (one-indexed) retry of connecting. Doubles with each attempt,
+/// starting at interval, up to `MAX_RETRY_DELAY`.
+fn retry_delay(interval: Duration, attempt: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+    interval.saturating_mul(factor).min(MAX_RETRY_DELAY)
+}
+
+/// Returns true if connecting failed for a reason that may go away by itself, such as the server
+/// not accepting connections yet. Other errors (e.g. wrong credentials or a failing setup
+/// statement) aren't retried.
+fn is_transient(e: &anyhow::Error) -> bool {
+    e.chain()
+        .any(|cause| match cause.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut) => true,
+            Some(_) => false,
+            None => cause
+                .downcast_ref::<io::Error>()
+                .is_some_and(|e| e.kind() == io::ErrorKind::ConnectionRefused),
+        })
+}
+
+/// Look up the password for the postgres connection in the password file (`~/.pgpass`).
+fn pgpass_password(connect_options: &PgConnectOptions) -> Option<String> {
+    let host = match connect_options.get_socket() {
+        Some(_) => pgpass::SOCKET_HOST,
+        None => connect_options.get_host(),
+    };
+    let user = connect_options.get_username();
+    // The database defaults to the user name
+    let database = connect_options.get_database().unwrap_or(user);
+
+    pgpass::find_password(host, connect_options.get_port(), database, user)
+}
+
+/// Convert the configured journal mode to the sqlx equivalent.
+fn to_sqlite_journal_mode(journal_mode: config::JournalMode) -> SqliteJournalMode {
+    match journal_mode {
+        config::JournalMode::Delete => SqliteJournalMode::Delete,
+        config::JournalMode::Truncate => SqliteJournalMode::Truncate,
+        config::JournalMode::Persist => SqliteJournalMode::Persist,
+        config::JournalMode::Memory => SqliteJournalMode::Memory,
+        config::JournalMode::Wal => SqliteJournalMode::Wal,
+        config::JournalMode::Off => SqliteJournalMode::Off,
+    }
+}
+
+async fn close_pool(pool: DbPool) {
+    match pool {
+        DbPool::Sqlite(p) => p.close().await,
+        DbPool::MySql(p) => p.close().await,
+        DbPool::Postgres(p) => p.close().await,
+    }
+}
+
+/// Add the pool as the most recently used warm pool, replacing any pool of a connection with the
+/// same name. Returns the pools that no longer fit, least recently used first.
+fn keep_warm_pool<P>(
+    warm_pools: &mut Vec<(Connection, P)>,
+    connection: Connection,
+    pool: P,
+    warm_connections: usize,
+) -> Vec<P> {
+    let mut evicted = vec![];
+    if let Some(index) = warm_pools
+        .iter()
+        .position(|(warm, _)| warm.name == connection.name)
+    {
+        evicted.push(warm_pools.remove(index).1);
+    }
+
+    warm_pools.push((connection, pool));
+    let excess = warm_pools.len().saturating_sub(warm_connections);
+    evicted.extend(warm_pools.drain(..excess).map(|(_, pool)| pool));
+    evicted
+}
+
+/// Remove the warm pool of the connection with the given name and return it, along with the
+/// connection it was created for.
+fn take_warm_pool<P>(warm_pools: &mut Vec<(Connection, P)>, name: &str) -> Option<(Connection, P)> {
+    let index = warm_pools.iter().position(|(warm, _)| warm.name == name)?;
+    Some(warm_pools.remove(index))
+}
+
+/// Create a connection pool for the connection. Postgres connections identify themselves using
+/// application_name.
+async fn create_pool(
+    connection: &Connection,
+    application_name: &str,
+    logger: Option<&Logger>,
+) -> anyhow::Result<DbPool> {
+    let setup_statements = connection.setup_statements();
+
+    let pool = match &connection.settings {
+        config::ConnectionSettings::Sqlite {
+            filename,
+            read_only,
+            busy_timeout_ms,
+            journal_mode,
+            create_if_missing,
+        } => {
+            let mut connect_options = SqliteConnectOptions::new()
+                .filename(filename)
+                .read_only(read_only.unwrap_or(false))
+                .create_if_missing(create_if_missing.unwrap_or(false));
+            if let Some(busy_timeout_ms) = busy_timeout_ms {
+                connect_options =
+                    connect_options.busy_timeout(Duration::from_millis(*busy_timeout_ms));
+            }
+            if let Some(journal_mode) = journal_mode {
+                connect_options =
+                    connect_options.journal_mode(to_sqlite_journal_mode(*journal_mode));
+            }
+            DbPool::Sqlite(connect_pool(connect_options, setup_statements, logger).await?)
+        }
+        config::ConnectionSettings::MySql {
+            host,
+            port,
+            socket,
+            user,
+            password,
+            database,
+            ..
+        } => {
+            let mut connect_options = MySqlConnectOptions::new().host(host);
+            if let Some(port) = port {
+                connect_options = connect_options.port(port.parse()?);
+            }
+            if let Some(socket) = socket {
+                connect_options = connect_options.socket(socket);
+            }
+            if let Some(user) = user {
+                connect_options = connect_options.username(user);
+            }
+            if let Some(password) = password {
+                connect_options = connect_options.password(password);
+            }
+            if let Some(database) = database {
+                connect_options = connect_options.database(database);
+            }
+            DbPool::MySql(connect_pool(connect_options, setup_statements, logger).await?)
+        }
+        config::ConnectionSettings::Postgres {
+            host,
+            port,
+            socket,
+            user,
+            password,
+            database,
+            ..
+        } => {
+            // The password file is read below, once the parameters to look up are known
+            let mut connect_options = PgConnectOptions::new_without_pgpass()
+                .host(host)
+                .application_name(application_name);
+            if let Some(port) = port {
+                connect_options = connect_options.port(port.parse()?);
+            }
+            if let Some(socket) = socket {
+                connect_options = connect_options.socket(socket);
+            }
+            if let Some(user) = user {
+                connect_options = connect_options.username(user);
+            }
+            if let Some(database) = database {
+                connect_options = connect_options.database(database);
+            }
+            let password = password
+                .clone()
+                .or_else(|| pgpass_password(&connect_options));
+            if let Some(password) = password {
+                connect_options = connect_options.password(&password);
+            }
+            // The server refuses writes as well, in case a statement isn't recognized as one
+            if connection.is_read_only() {
+                connect_options =
+                    connect_options.options([("default_transaction_read_only", "on")]);
+            }
+            DbPool::Postgres(connect_pool(connect_options, setup_statements, logger).await?)
+        }
+    };
+
+    Ok(pool)
 }
 
 impl DbClient {
@@ -137,157 +768,807 @@ impl DbClient {
         install_default_drivers();
     }
 
-    /// Connect to the given DSN and replace the stored pool.
-    pub async fn connect(&self, connection: Connection) -> anyhow::Result<()> {
-        // Close any existing connection pools.
-        self.close().await;
+    /// Connect to the given DSN and replace the stored pool. The setup statements of the
+    /// connection are logged to logger (if any) as they are run. If connecting fails, the current
+    /// pool is kept as it is.
+    pub async fn connect(
+        &self,
+        connection: Connection,
+        logger: Option<&Logger>,
+    ) -> Result<(), SqlFriendError> {
+        let config = get_config()?;
+        let warm_connections = config.get_warm_connections();
 
-        let pool = match &connection.settings {
-            config::ConnectionSettings::Sqlite { filename } => {
-                let connect_options = SqliteConnectOptions::new().filename(filename);
-                DbPool::Sqlite(SqlitePool::connect_with(connect_options).await?)
-            }
-            config::ConnectionSettings::MySql {
-                host,
-                port,
-                user,
-                password,
-                database,
-            } => {
-                let mut connect_options = MySqlConnectOptions::new().host(host);
-                if let Some(port) = port {
-                    connect_options = connect_options.port(port.parse()?);
-                }
-                if let Some(user) = user {
-                    connect_options = connect_options.username(user);
-                }
-                if let Some(password) = password {
-                    connect_options = connect_options.password(password);
-                }
-                if let Some(database) = database {
-                    connect_options = connect_options.database(database);
-                }
-                DbPool::MySql(MySqlPool::connect_with(connect_options).await?)
-            }
-            config::ConnectionSettings::Postgres {
-                host,
-                port,
-                user,
-                password,
-                database,
-            } => {
-                let mut connect_options = PgConnectOptions::new().host(host);
-                if let Some(port) = port {
-                    connect_options = connect_options.port(port.parse()?);
-                }
-                if let Some(user) = user {
-                    connect_options = connect_options.username(user);
-                }
-                if let Some(password) = password {
-                    connect_options = connect_options.password(password);
-                }
-                if let Some(database) = database {
-                    connect_options = connect_options.database(database);
+        // The current pool would be kept warm and then taken again, so it's used as is
+        let is_current = self.current_connection.read().await.as_ref() == Some(&connection);
+        if is_current && warm_connections > 0 && self.pool.lock().await.is_some() {
+            let _ = self.rollback().await;
+            self.stop_row_stream().await;
+            return Ok(());
+        }
+
+        let warm_pool = take_warm_pool(&mut *self.warm_pools.lock().await, &connection.name);
+        let pool = match warm_pool {
+            Some((warm_connection, pool)) if warm_connection == connection => pool,
+            warm_pool => {
+                // The connection was changed since its pool was kept warm
+                if let Some((_, pool)) = warm_pool {
+                    close_pool(pool).await;
                 }
-                DbPool::Postgres(PgPool::connect_with(connect_options).await?)
+                self.create_pool_with_retries(&connection, logger).await?
             }
         };
+
+        // The transaction has to be finished before its pool is closed or kept warm
+        let _ = self.rollback().await;
+        self.release_pool(warm_connections).await;
         self.pool.lock().await.replace(pool);
         self.current_connection.write().await.replace(connection);
 
         Ok(())
     }
 
-    /// Clean up database connections.
-    pub async fn close(&self) {
-        if let Some(pool) = self.pool.lock().await.take() {
-            match pool {
-                DbPool::Sqlite(p) => p.close().await,
-                DbPool::MySql(p) => p.close().await,
-                DbPool::Postgres(p) => p.close().await,
+    /// Create a pool for the connection, retrying as configured.
+    async fn create_pool_with_retries(
+        &self,
+        connection: &Connection,
+        logger: Option<&Logger>,
+    ) -> Result<DbPool, SqlFriendError> {
+        let config = get_config()?;
+        let attempts = config.get_connect_attempts();
+        let interval = Duration::from_millis(config.get_connect_retry_interval_ms());
+        let application_name = config.get_application_name(&connection.name);
+        let mut attempt = 1;
+        loop {
+            match create_pool(connection, &application_name, logger).await {
+                Ok(pool) => return Ok(pool),
+                Err(e) if attempt < attempts && is_transient(&e) => {
+                    let delay = retry_delay(interval, attempt);
+                    if let Some(logger) = logger {
+                        logger.warn(&format!(
+                            "Failed to connect to {} (attempt {attempt} of {attempts}), \
+                            retrying in {delay:.1?}: {}",
+                            connection.name,
+                            error::format_chain(&e)
+                        ))?;
+                    }
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    let e = if attempt > 1 {
+                        e.context(format!("gave up after {attempt} attempts"))
+                    } else {
+                        e
+                    };
+                    return Err(SqlFriendError::ConnectionFailed(connection.name.clone(), e));
+                }
             }
         }
     }
 
-    /// Fetch all results (if any) and output them.
-    pub async fn fetch_all_with_output(&self, query: &str, logger: &Logger) -> anyhow::Result<()> {
-        let rows = self.fetch_all(query).await?;
-        Self::print_table(logger, &rows)?;
-        Ok(())
+    /// Stop using the current pool. It's kept warm if warm_connections is above 0, evicting the
+    /// least recently used pools beyond that, and closed otherwise.
+    async fn release_pool(&self, warm_connections: usize) {
+        self.stop_row_stream().await;
+        let Some(pool) = self.pool.lock().await.take() else {
+            return;
+        };
+
+        match self.current_connection.read().await.clone() {
+            Some(connection) if warm_connections > 0 => {
+                let evicted = keep_warm_pool(
+                    &mut *self.warm_pools.lock().await,
+                    connection,
+                    pool,
+                    warm_connections,
+                );
+                for pool in evicted {
+                    close_pool(pool).await;
+                }
+            }
+            _ => close_pool(pool).await,
+        }
     }
 
-    pub async fn get_current_connection(&self) -> RwLockReadGuard<Option<Connection>> {
-        self.current_connection.read().await
+    /// Close the least recently used warm pools until at most warm_connections are left.
+    pub async fn trim_warm_pools(&self, warm_connections: usize) {
+        let evicted = {
+            let mut warm_pools = self.warm_pools.lock().await;
+            let excess = warm_pools.len().saturating_sub(warm_connections);
+            warm_pools.drain(..excess).collect::<Vec<_>>()
+        };
+        for (_, pool) in evicted {
+            close_pool(pool).await;
+        }
     }
 
-    /// Fetch all results (if any).
-    async fn fetch_all(&self, query: &str) -> anyhow::Result<Vec<DbRow>> {
+    /// Clean up database connections, including the warm pools. Any open transaction is rolled
+    /// back.
+    pub async fn close(&self) {
+        // Closing the pool waits for all connections to be returned, so the transaction has to
+        // be finished first.
+        let _ = self.rollback().await;
+        self.stop_row_stream().await;
+
+        if let Some(pool) = self.pool.lock().await.take() {
+            close_pool(pool).await;
+        }
+        self.trim_warm_pools(0).await;
+    }
+
+    /// Stop reading the rows of the paged query, returning its connection to the pool.
+    async fn stop_row_stream(&self) {
+        if let Some(row_stream) = self.row_stream.lock().await.take() {
+            row_stream.abort();
+        }
+    }
+
+    /// Start a transaction on a single connection that all subsequent queries will use until
+    /// `commit` or `rollback` is called.
+    pub async fn begin(&self) -> anyhow::Result<()> {
+        let mut transaction = self.transaction.lock().await;
+        if transaction.is_some() {
+            bail!("a transaction is already open");
+        }
+
         let lock = self.pool.lock().await;
         let pool = lock
             .deref()
             .as_ref()
-            .ok_or(anyhow!("not connected to any database"))?;
+            .ok_or(SqlFriendError::NoActiveConnection)?;
+
+        let new_transaction = match pool {
+            DbPool::Sqlite(p) => DbTransaction::Sqlite(p.begin().await?),
+            DbPool::MySql(p) => DbTransaction::MySql(p.begin().await?),
+            DbPool::Postgres(p) => DbTransaction::Postgres(Box::new(p.begin().await?)),
+        };
+        transaction.replace(new_transaction);
+
+        Ok(())
+    }
 
+    /// Commit the open transaction.
+    pub async fn commit(&self) -> anyhow::Result<()> {
+        let transaction = self
+            .transaction
+            .lock()
+            .await
+            .take()
+            .ok_or(anyhow!("no open transaction"))?;
+
+        match transaction {
+            DbTransaction::Sqlite(t) => t.commit().await?,
+            DbTransaction::MySql(t) => t.commit().await?,
+            DbTransaction::Postgres(t) => t.commit().await?,
+        }
+
+        Ok(())
+    }
+
+    /// Roll back the open transaction.
+    pub async fn rollback(&self) -> anyhow::Result<()> {
+        let transaction = self
+            .transaction
+            .lock()
+            .await
+            .take()
+            .ok_or(anyhow!("no open transaction"))?;
+
+        match transaction {
+            DbTransaction::Sqlite(t) => t.rollback().await?,
+            DbTransaction::MySql(t) => t.rollback().await?,
+            DbTransaction::Postgres(t) => t.rollback().await?,
+        }
+
+        Ok(())
+    }
+
+    /// Returns true if a transaction is open.
+    pub async fn in_transaction(&self) -> bool {
+        self.transaction.lock().await.is_some()
+    }
+
+    /// Returns true if queries are committed automatically when no transaction is open.
+    pub fn is_autocommit(&self) -> bool {
+        !self.manual_commit.load(Ordering::SeqCst)
+    }
+
+    /// Turn autocommit on or off. An open transaction is left as is and still needs to be
+    /// committed or rolled back.
+    pub fn set_autocommit(&self, autocommit: bool) {
+        self.manual_commit.store(!autocommit, Ordering::SeqCst);
+    }
+
+    /// Close the database connection and forget the current connection.
+    pub async fn disconnect(&self) {
+        self.close().await;
+        self.current_connection.write().await.take();
+    }
+
+    /// Run a trivial query on the pool to check that the database can still be reached, and
+    /// return how long it took. Doesn't use or affect the open transaction, if any.
+    pub async fn ping(&self) -> anyhow::Result<Duration> {
+        // The pool is cloned so that queries aren't blocked while waiting for the database
+        let pool = self
+            .pool
+            .lock()
+            .await
+            .clone()
+            .ok_or(SqlFriendError::NoActiveConnection)?;
+
+        let start = Instant::now();
         match pool {
-            DbPool::Sqlite(p) => Ok(sqlx::query_as(query).fetch_all(p).await?),
-            DbPool::MySql(p) => Ok(sqlx::query_as(query).fetch_all(p).await?),
-            DbPool::Postgres(p) => Ok(sqlx::query_as(query).fetch_all(p).await?),
+            DbPool::Sqlite(p) => p.execute("SELECT 1").await.map(|_| ())?,
+            DbPool::MySql(p) => p.execute("SELECT 1").await.map(|_| ())?,
+            DbPool::Postgres(p) => p.execute("SELECT 1").await.map(|_| ())?,
         }
+
+        Ok(start.elapsed())
     }
 
-    // Print a list of rows using the logger.
-    fn print_table(logger: &Logger, rows: &[DbRow]) -> anyhow::Result<()> {
-        if rows.is_empty() {
+    /// Get the version of the connected database server. Returns None if there is no active
+    /// connection. Doesn't use or affect the open transaction, if any.
+    pub async fn server_version(&self) -> anyhow::Result<Option<String>> {
+        let query = match self.current_connection.read().await.as_ref() {
+            Some(connection) => connection.settings.server_version_query(),
+            None => return Ok(None),
+        };
+        let Some(pool) = self.pool.lock().await.clone() else {
+            return Ok(None);
+        };
+
+        let version = match pool {
+            DbPool::Sqlite(p) => sqlx::query_scalar(query).fetch_one(&p).await?,
+            DbPool::MySql(p) => sqlx::query_scalar(query).fetch_one(&p).await?,
+            DbPool::Postgres(p) => sqlx::query_scalar(query).fetch_one(&p).await?,
+        };
+
+        Ok(Some(version))
+    }
+
+    /// Run a statement that was prefixed with `FORCE` to run it on a read-only connection.
+    /// Read-only postgres connections are read-only on the server as well, so the statement is
+    /// run in a read-write transaction there. Unless a transaction was already open or autocommit
+    /// is off, the transaction is committed if the statement succeeds and rolled back otherwise.
+    pub async fn run_forced<T, E>(&self, run: impl Future<Output = Result<T, E>>) -> Result<T, E>
+    where
+        E: From<SqlFriendError>,
+    {
+        let server_read_only = matches!(*self.pool.lock().await, Some(DbPool::Postgres(_)))
+            && self
+                .current_connection
+                .read()
+                .await
+                .as_ref()
+                .is_some_and(Connection::is_read_only);
+        if !server_read_only {
+            return run.await;
+        }
+
+        let in_transaction = self.in_transaction().await;
+        if !in_transaction {
+            self.begin().await.map_err(SqlFriendError::from)?;
+        }
+        let commit = self.is_autocommit() && !in_transaction;
+        if let Err(e) = self.query("SET TRANSACTION READ WRITE").await {
+            if commit {
+                let _ = self.rollback().await;
+            }
+            return Err(e.into());
+        }
+
+        let result = run.await;
+        if commit {
+            match result {
+                Ok(_) => self.commit().await.map_err(SqlFriendError::from)?,
+                Err(_) => {
+                    let _ = self.rollback().await;
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Fail if the current connection (or all connections) are read-only and the SQL contains
+    /// statements that change data or the schema.
+    pub async fn ensure_writable(&self, sql: &str) -> Result<(), SqlFriendError> {
+        let read_only = get_config()?.get_read_only()
+            || self
+                .current_connection
+                .read()
+                .await
+                .as_ref()
+                .is_some_and(Connection::is_read_only);
+        if !read_only {
             return Ok(());
         }
 
-        let column_names = rows[0]
-            .columns
-            .iter()
-            .map(|(name, _)| name.as_str())
-            .collect::<Vec<_>>();
+        let dialect = self.dialect().await;
+        let write = sql::split_statements(sql, dialect)
+            .into_iter()
+            .map(|statement| sql::classify(statement, dialect))
+            .find(StatementKind::is_write);
+        match write {
+            Some(kind) => Err(SqlFriendError::ReadOnlyConnection(kind.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Fetch all results (if any) in the configured format. If an output redirect is set in
+    /// state, the results are written to that file instead. The redirect is reset before the query
+    /// runs, whether it succeeds or not. Returns the results unless they were written to a file,
+    /// so that the frontend can display them. Tables are followed by a footer with the number of
+    /// rows and the query time, if enabled.
+    ///
+    /// If paging is enabled, only the first page of the results of a single query is fetched and
+    /// the next page is stored in state, see `fetch_next_page`.
+    pub async fn fetch_all_redirected(
+        &self,
+        query: &str,
+        state: &State,
+        logger: &Logger,
+    ) -> Result<Option<String>, SqlFriendError> {
+        let config = get_config()?;
+        let format = config.get_output_format();
+        // A new query replaces the pages of the previous one
+        state.next_page.lock().await.take();
+        // The redirect only applies to this query, even if it fails
+        let redirect = state.output_redirect.lock().await.take();
+
+        let page_size = config
+            .get_page_size()
+            .or_else(|| Some(output::terminal_height()?.saturating_sub(PAGE_MARGIN)))
+            .filter(|size| *size > 0);
+        let paged = config.get_paging() && format == OutputFormat::Table && redirect.is_none();
+        let dialect = self.dialect().await;
+        if let (true, Some(paged_query), Some(size)) =
+            (paged, sql::pageable_query(query, dialect), page_size)
+        {
+            let start = Instant::now();
+            let page = self.start_paging(paged_query, size).await?;
+            return self.fetch_page(page, start, state).await.map(Some);
+        }
 
-        let mut column_widths: Vec<usize> = column_names.iter().map(|col| col.len()).collect();
+        let start = Instant::now();
+        let result = self.query(query).await?;
+        let elapsed = start.elapsed();
+        // Results written to a file don't have to fit in the terminal
+        let width = match redirect {
+            Some(_) => None,
+            None => output::terminal_width(),
+        };
+        let output = Self::render_result(&result, format, width)?;
+        let row_count = result.rows.len();
+        state.last_result.lock().await.replace(result);
 
-        for row in rows {
-            for (i, (_, value)) in row.columns.iter().enumerate() {
-                column_widths[i] = column_widths[i].max(value.len());
+        let Some(path) = redirect else {
+            // The footer would make other formats unparseable
+            if !config.get_footer() || format != OutputFormat::Table {
+                return Ok(output);
             }
+
+            let footer = output::footer(row_count, elapsed);
+            return match output {
+                Some(output) => Ok(Some(format!("{output}\n{footer}"))),
+                None => Ok(Some(footer)),
+            };
+        };
+
+        let mut output = output.unwrap_or_default();
+        output.push('\n');
+        fs::write(&path, output)
+            .with_context(|| format!("failed to write results to {}", path.display()))?;
+        logger.standard(&format!("Wrote results to {}.", path.display()))?;
+
+        Ok(None)
+    }
+
+    /// Run the query to show its results a page at a time. Its rows are read from a connection
+    /// of the pool as the pages are shown. In a transaction (or with autocommit off), all of them
+    /// are read right away instead, since the transaction can't be used by other queries while
+    /// its rows are being read.
+    async fn start_paging(&self, query: &str, size: usize) -> Result<Page, SqlFriendError> {
+        self.stop_row_stream().await;
+
+        let mut page = Page {
+            query: query.to_string(),
+            columns: vec![],
+            column_types: vec![],
+            rows: VecDeque::new(),
+            stream: None,
+            offset: 0,
+            size,
+        };
+
+        if !self.is_autocommit() || self.in_transaction().await {
+            let result = self.query(query).await?;
+            page.columns = result.columns;
+            page.column_types = result.column_types;
+            page.rows = result.rows.into();
+            return Ok(page);
         }
 
-        // Helper function to create a row string
-        let make_row = |values: Vec<&str>| -> String {
-            values
-                .into_iter()
-                .enumerate()
-                .map(|(i, value)| format!(" {:<width$} ", value, width = column_widths[i]))
-                .collect::<Vec<_>>()
-                .join("|")
+        let pool = self
+            .pool
+            .lock()
+            .await
+            .clone()
+            .ok_or(SqlFriendError::NoActiveConnection)?;
+        // The next page is read while the current one is shown
+        let (rows, row_stream) = stream_rows(pool, query.to_string(), size + 1);
+        page.stream = Some(rows);
+        self.row_stream.lock().await.replace(row_stream);
+
+        Ok(page)
+    }
+
+    /// Read the rows of the page (since start) and render them as a table followed by a footer
+    /// with the rows on the page. The next page is stored in state if there are more rows.
+    async fn fetch_page(
+        &self,
+        mut page: Page,
+        start: Instant,
+        state: &State,
+    ) -> Result<String, SqlFriendError> {
+        // One more row than fits on the page is read to find out if there are more
+        page.read_rows(page.size + 1)
+            .await
+            .map_err(|e| SqlFriendError::QueryFailed(with_error_position(&page.query, e)))?;
+        let elapsed = start.elapsed();
+
+        let row_count = page.size.min(page.rows.len());
+        let result = QueryResult {
+            columns: page.columns.clone(),
+            column_types: page.column_types.clone(),
+            rows: page.rows.drain(..row_count).collect(),
+            affected: None,
         };
+        let more = !page.rows.is_empty();
+        let output = Self::render_result(&result, OutputFormat::Table, output::terminal_width())?;
+        let footer = output::page_footer(page.offset, row_count, more, elapsed);
+        state.last_result.lock().await.replace(result);
 
-        let separator: String = column_widths
-            .iter()
-            .map(|&width| format!("{:-<width$}", "", width = width + 2))
-            .collect::<Vec<_>>()
-            .join("+");
+        if more {
+            page.offset += row_count;
+            state.next_page.lock().await.replace(page);
+        }
+
+        match output {
+            Some(output) => Ok(format!("{output}\n{footer}")),
+            None => Ok(footer),
+        }
+    }
+
+    /// Fetch and render the next page of the results of the last query, see
+    /// `fetch_all_redirected`.
+    pub async fn fetch_next_page(&self, state: &State) -> Result<String, SqlFriendError> {
+        let page = state
+            .next_page
+            .lock()
+            .await
+            .take()
+            .ok_or(anyhow!("there are no more rows to show"))?;
+
+        self.fetch_page(page, Instant::now(), state).await
+    }
+
+    /// Explain how the database runs the query, and render the plan. With analyze, the query is
+    /// run to show the actual time and rows as well (which isn't supported by sqlite).
+    pub async fn explain(&self, query: &str, analyze: bool) -> Result<String, SqlFriendError> {
+        let pool = self
+            .pool
+            .lock()
+            .await
+            .clone()
+            .ok_or(SqlFriendError::NoActiveConnection)?;
 
-        let mut lines: Vec<String> = Vec::new();
+        match pool {
+            DbPool::Postgres(_) => {
+                let options = if analyze {
+                    "ANALYZE, FORMAT JSON"
+                } else {
+                    "FORMAT JSON"
+                };
+                let result = self.query(&format!("EXPLAIN ({options}) {query}")).await?;
+                let Some(Value::Json(plan)) = result.rows.first().and_then(|row| row.first())
+                else {
+                    return Err(anyhow!("EXPLAIN didn't return a query plan").into());
+                };
 
-        let header = make_row(column_names);
-        lines.push(header);
-        lines.push(separator);
+                Ok(output::plan::render_pg_plan(plan)?)
+            }
+            DbPool::MySql(_) if analyze => {
+                // The plan is a single text value with a line per node
+                let result = self.query(&format!("EXPLAIN ANALYZE {query}")).await?;
+                let Some(Value::Text(plan)) = result.rows.first().and_then(|row| row.first())
+                else {
+                    return Err(anyhow!("EXPLAIN ANALYZE didn't return a query plan").into());
+                };
 
-        for row in rows {
-            let line = make_row(
-                row.columns
-                    .iter()
-                    .map(|(_, value)| value.as_str())
-                    .collect(),
-            );
-            lines.push(line);
+                Ok(plan.trim_end().to_string())
+            }
+            DbPool::MySql(_) => self.explain_table(&format!("EXPLAIN {query}")).await,
+            DbPool::Sqlite(_) if analyze => {
+                Err(anyhow!("EXPLAIN ANALYZE isn't supported in sqlite").into())
+            }
+            DbPool::Sqlite(_) => {
+                self.explain_table(&format!("EXPLAIN QUERY PLAN {query}"))
+                    .await
+            }
         }
+    }
 
-        logger.standard(&lines.join("\n"))?;
-        Ok(())
+    /// Run the EXPLAIN statement and render its result as a table.
+    async fn explain_table(&self, statement: &str) -> Result<String, SqlFriendError> {
+        let result = self.query(statement).await?;
+        let output = Self::render_result(&result, OutputFormat::Table, output::terminal_width())?;
+        Ok(output.unwrap_or_default())
+    }
+
+    /// Run the query and render its results in the given format, see `query`. Returns None if
+    /// there are no results.
+    pub async fn fetch_all_formatted(
+        &self,
+        query: &str,
+        format: OutputFormat,
+    ) -> Result<Option<String>, SqlFriendError> {
+        let result = self.query(query).await?;
+        Ok(Self::render_result(
+            &result,
+            format,
+            output::terminal_width(),
+        )?)
+    }
+
+    /// Fetch the tables and columns of the connected database. Runs on the pool even if a
+    /// transaction is open, so that it doesn't start or affect one.
+    pub async fn fetch_schema(&self) -> anyhow::Result<Schema> {
+        let lock = self.pool.lock().await;
+        let pool = lock
+            .deref()
+            .as_ref()
+            .ok_or(SqlFriendError::NoActiveConnection)?;
+
+        let columns: Vec<(String, String)> = match pool {
+            DbPool::Sqlite(p) => sqlx::query_as(SQLITE_SCHEMA_QUERY).fetch_all(p).await?,
+            DbPool::MySql(p) => sqlx::query_as(MYSQL_SCHEMA_QUERY).fetch_all(p).await?,
+            DbPool::Postgres(p) => sqlx::query_as(POSTGRES_SCHEMA_QUERY).fetch_all(p).await?,
+        };
+
+        Ok(Schema::from_columns(columns))
+    }
+
+    pub async fn get_current_connection(&self) -> RwLockReadGuard<'_, Option<Connection>> {
+        self.current_connection.read().await
+    }
+
+    /// SQL dialect of the current connection, or the standard dialect if there is none.
+    pub async fn dialect(&self) -> Dialect {
+        self.current_connection
+            .read()
+            .await
+            .as_ref()
+            .map(Connection::dialect)
+            .unwrap_or_default()
+    }
+
+    /// Run the query and return its results (if any) with their values decoded. Uses the open
+    /// transaction if there is one, or starts one if autocommit is off.
+    pub async fn query(&self, query: &str) -> Result<QueryResult, SqlFriendError> {
+        let (rows, affected) = self.fetch_all_driver_rows(query).await.map_err(|e| {
+            match e.downcast::<SqlFriendError>() {
+                Ok(e) => e,
+                Err(e) => SqlFriendError::QueryFailed(with_error_position(query, e)),
+            }
+        })?;
+
+        let result = match rows {
+            DriverRows::Sqlite(rows) => {
+                QueryResult::from_rows(&rows, affected, decode_sqlite_value)
+            }
+            DriverRows::MySql(rows) => QueryResult::from_rows(&rows, affected, decode_mysql_value),
+            DriverRows::Postgres(rows) => QueryResult::from_rows(&rows, affected, decode_pg_value),
+        };
+
+        Ok(result)
+    }
+
+    /// Fetch all results (if any) as returned by the driver, and the number of affected rows.
+    /// Uses the open transaction if there is one, or starts one if autocommit is off.
+    async fn fetch_all_driver_rows(
+        &self,
+        query: &str,
+    ) -> anyhow::Result<(DriverRows, Option<u64>)> {
+        if !self.is_autocommit() && !self.in_transaction().await {
+            self.begin().await?;
+        }
+
+        if let Some(transaction) = self.transaction.lock().await.as_mut() {
+            let rows = match transaction {
+                DbTransaction::Sqlite(t) => {
+                    let stream = (&mut **t).fetch_many(sqlx::query(query));
+                    let (rows, affected) =
+                        collect_rows(stream, SqliteQueryResult::rows_affected).await?;
+                    (DriverRows::Sqlite(rows), affected)
+                }
+                DbTransaction::MySql(t) => {
+                    let stream = (&mut **t).fetch_many(sqlx::query(query));
+                    let (rows, affected) =
+                        collect_rows(stream, MySqlQueryResult::rows_affected).await?;
+                    (DriverRows::MySql(rows), affected)
+                }
+                DbTransaction::Postgres(t) => {
+                    let stream = (&mut ***t).fetch_many(sqlx::query(query));
+                    let (rows, affected) =
+                        collect_rows(stream, PgQueryResult::rows_affected).await?;
+                    (DriverRows::Postgres(rows), affected)
+                }
+            };
+
+            return Ok(rows);
+        }
+
+        let lock = self.pool.lock().await;
+        let pool = lock
+            .deref()
+            .as_ref()
+            .ok_or(SqlFriendError::NoActiveConnection)?;
+
+        let rows = match pool {
+            DbPool::Sqlite(p) => {
+                let stream = p.fetch_many(sqlx::query(query));
+                let (rows, affected) =
+                    collect_rows(stream, SqliteQueryResult::rows_affected).await?;
+                (DriverRows::Sqlite(rows), affected)
+            }
+            DbPool::MySql(p) => {
+                let stream = p.fetch_many(sqlx::query(query));
+                let (rows, affected) =
+                    collect_rows(stream, MySqlQueryResult::rows_affected).await?;
+                (DriverRows::MySql(rows), affected)
+            }
+            DbPool::Postgres(p) => {
+                let stream = p.fetch_many(sqlx::query(query));
+                let (rows, affected) = collect_rows(stream, PgQueryResult::rows_affected).await?;
+                (DriverRows::Postgres(rows), affected)
+            }
+        };
+
+        Ok(rows)
+    }
+
+    /// Render the rows of a result in the given format. Tables wider than width (if given) are
+    /// handled according to the overflow setting. Returns None if there are no rows.
+    pub fn render_result(
+        result: &QueryResult,
+        format: OutputFormat,
+        width: Option<usize>,
+    ) -> anyhow::Result<Option<String>> {
+        if result.rows.is_empty() {
+            return Ok(None);
+        }
+
+        let config = get_config()?;
+        let column_names = result
+            .columns
+            .iter()
+            .zip(&result.column_types)
+            .map(|(name, type_name)| {
+                // Only tables have a header that is meant to be read by the user
+                if config.get_header_types() && format == OutputFormat::Table {
+                    with_type_name(name, type_name)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        let column_names = column_names.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let table_options = TableOptions {
+            border_style: config.get_border_style(),
+            max_col_width: config.get_max_col_width(),
+            overflow: config.get_overflow(),
+            max_width: width,
+        };
+
+        let value_options = ValueOptions::from_config(&config);
+        output::render(
+            format,
+            &column_names,
+            &result.rows,
+            &value_options,
+            &table_options,
+        )
+        .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_evict_least_recently_used_warm_pools() {
+        let connection = |name| Connection::sqlite_for_test(name, "app.sqlite");
+        let mut warm_pools = vec![];
+        assert!(keep_warm_pool(&mut warm_pools, connection("a"), 1, 2).is_empty());
+        assert!(keep_warm_pool(&mut warm_pools, connection("b"), 2, 2).is_empty());
+
+        // Keeping a connection warm again makes it the most recently used
+        assert_eq!(
+            keep_warm_pool(&mut warm_pools, connection("a"), 3, 2),
+            vec![1]
+        );
+        assert_eq!(
+            keep_warm_pool(&mut warm_pools, connection("c"), 4, 2),
+            vec![2]
+        );
+
+        let (warm_connection, pool) = take_warm_pool(&mut warm_pools, "a").unwrap();
+        assert_eq!((warm_connection.name.as_str(), pool), ("a", 3));
+        assert!(take_warm_pool(&mut warm_pools, "a").is_none());
+        assert_eq!(warm_pools.len(), 1);
+    }
+
+    #[test]
+    fn can_detect_transient_connection_errors() {
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert!(is_transient(&anyhow::Error::new(sqlx::Error::Io(refused))));
+        assert!(is_transient(&anyhow::Error::new(sqlx::Error::PoolTimedOut)));
+
+        let setup_failed = anyhow::Error::new(sqlx::Error::RowNotFound).context("setup failed");
+        assert!(!is_transient(&setup_failed));
+        assert!(!is_transient(&anyhow!("invalid port")));
+    }
+
+    #[tokio::test]
+    async fn can_page_rows_of_one_execution() {
+        DbClient::initialize();
+        let db_client = DbClient::default();
+        let memory = Connection::sqlite_for_test("memory", ":memory:");
+        db_client.connect(memory, None).await.unwrap();
+        let state = State::default();
+
+        let query = "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 5) \
+            SELECT i FROM n";
+        let page = db_client.start_paging(query, 2).await.unwrap();
+        let output = db_client
+            .fetch_page(page, Instant::now(), &state)
+            .await
+            .unwrap();
+        assert!(output.contains("(rows 1-2, "));
+
+        let output = db_client.fetch_next_page(&state).await.unwrap();
+        assert!(output.contains("(rows 3-4, "));
+        let output = db_client.fetch_next_page(&state).await.unwrap();
+        assert!(output.contains("(row 5, "));
+        assert!(state.next_page.lock().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn can_keep_connection_when_connecting_fails() {
+        DbClient::initialize();
+        let db_client = DbClient::default();
+        let memory = Connection::sqlite_for_test("memory", ":memory:");
+        db_client.connect(memory, None).await.unwrap();
+
+        let missing = Connection::sqlite_for_test("missing", "missing/does-not-exist.sqlite");
+        let result = db_client.connect(missing, None).await;
+        assert!(matches!(result, Err(SqlFriendError::ConnectionFailed(..))));
+
+        let current = db_client.get_current_connection().await.clone();
+        assert_eq!(current.unwrap().name, "memory");
+        assert!(db_client.ping().await.is_ok());
+    }
+
+    #[test]
+    fn can_back_off_retries() {
+        let interval = Duration::from_millis(500);
+        assert_eq!(retry_delay(interval, 1), Duration::from_millis(500));
+        assert_eq!(retry_delay(interval, 2), Duration::from_secs(1));
+        assert_eq!(retry_delay(interval, 4), Duration::from_secs(4));
+        assert_eq!(retry_delay(interval, 40), MAX_RETRY_DELAY);
     }
 }
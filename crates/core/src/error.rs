@@ -1,22 +1,131 @@
+use std::{io, path::PathBuf};
+
 use thiserror::Error;
 
 use crate::config;
 
-// TODO: Get rid of the map_errs
 #[derive(Error, Debug)]
 pub enum SqlFriendError {
     #[error("invalid connection name: `{0}`")]
     InvalidConnectionName(String),
 
+    #[error("a connection named `{0}` already exists")]
+    DuplicateConnectionName(String),
+
+    /// The URL itself isn't included, since it may contain a password.
+    #[error("invalid connection URL: {0}")]
+    InvalidConnectionUrl(String),
+
+    #[error("invalid connection settings: {0}")]
+    InvalidConnectionSettings(String),
+
+    #[error("invalid snippet name: `{0}`")]
+    InvalidSnippetName(String),
+
     #[error("invalid command usage: `{0}`")]
     InvalidCommandUsage(String),
 
-    #[error("invalid command: `{0}`")]
-    InvalidCommand(String),
+    /// An unknown command, and the command that was most likely meant, if any.
+    #[error(
+        "invalid command: `{0}`{}",
+        .1.map(|command| format!(", did you mean `{}{command}`?", crate::command_prefix!()))
+            .unwrap_or_default()
+    )]
+    InvalidCommand(String, Option<&'static str>),
+
+    /// A prefix of several commands, and the commands it could refer to.
+    #[error(
+        "ambiguous command: `{0}`, did you mean one of {}?",
+        .1.iter()
+            .map(|command| format!("`{}{command}`", crate::command_prefix!()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )]
+    AmbiguousCommand(String, Vec<&'static str>),
+
+    #[error("unclosed quote in command: `{0}`")]
+    UnclosedQuote(String),
 
     #[error("invalid LSP server: `{0}`, expected one of {1:?}")]
     InvalidLspServer(String, Vec<config::LspServerType>),
 
+    #[error("LSP server `{0}` not found, make sure that it is installed and in your $PATH")]
+    LspServerNotFound(String),
+
+    #[error("failed to spawn LSP server `{0}`: {1}")]
+    LspSpawnFailed(String, io::Error),
+
+    #[error("failed to connect to `{0}`: {}", format_chain(.1))]
+    ConnectionFailed(String, anyhow::Error),
+
+    /// The query was sent to the database, but failed. The message is the error reported by the
+    /// database, or a report pointing at the error in the query.
+    #[error(transparent)]
+    QueryFailed(anyhow::Error),
+
+    #[error("failed to parse config file {}: {1}", .0.display())]
+    ConfigParse(PathBuf, toml::de::Error),
+
+    #[error("invalid setting: `{0}`")]
+    InvalidSetting(String),
+
+    #[error("invalid value for setting `{0}`: `{1}`, expected {2}")]
+    InvalidSettingValue(String, String, String),
+
+    #[error("invalid profile name: `{0}`, expected letters, digits, `-` or `_`")]
+    InvalidProfileName(String),
+
+    /// A profile that has no config file yet, and the existing profiles.
+    #[error("profile `{0}` doesn't exist, expected one of: {1}")]
+    UnknownProfile(String, String),
+
+    #[error(
+        "connection is read-only, refusing to run {0} statement (prefix it with `{}` to run it anyway)",
+        crate::sql::FORCE_KEYWORD
+    )]
+    ReadOnlyConnection(String),
+
+    #[error(
+        "not connected to any database, use `{0}list` to see the saved connections and \
+        `{0}use <connection_name>` to connect",
+        crate::command_prefix!()
+    )]
+    NoActiveConnection,
+
     #[error(transparent)]
     Unknown(#[from] anyhow::Error),
 }
+
+/// Format the error followed by its causes, like `{:#}`, but leave out causes that are already
+/// part of the message before them. Some driver errors include their source in their message.
+pub(crate) fn format_chain(error: &anyhow::Error) -> String {
+    let mut messages: Vec<String> = vec![];
+    for cause in error.chain() {
+        let message = cause.to_string();
+        if messages.last().is_some_and(|last| last.ends_with(&message)) {
+            continue;
+        }
+
+        messages.push(message);
+    }
+
+    messages.join(": ")
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    #[test]
+    fn can_format_chain_without_repeated_causes() {
+        let io_error = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        let error = anyhow::Error::new(sqlx::Error::Io(io_error)).context("gave up");
+        assert_eq!(
+            format_chain(&error),
+            "gave up: error communicating with database: refused"
+        );
+        assert_eq!(format_chain(&anyhow!("failed")), "failed");
+    }
+}
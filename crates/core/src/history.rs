@@ -0,0 +1,164 @@
+use std::collections::VecDeque;
+
+use crate::command::is_maybe_command;
+
+/// Kind of line stored in the history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryKind {
+    /// A command (starting with the command prefix).
+    Command,
+    /// An SQL query.
+    Query,
+}
+
+/// Which kinds of history entries that should be available when navigating or searching the
+/// history.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HistoryScope {
+    #[default]
+    All,
+    Sql,
+    Commands,
+}
+
+impl HistoryScope {
+    /// Returns true if entries of the given kind are part of this scope.
+    pub fn includes(&self, kind: HistoryKind) -> bool {
+        match self {
+            Self::All => true,
+            Self::Sql => kind == HistoryKind::Query,
+            Self::Commands => kind == HistoryKind::Command,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub kind: HistoryKind,
+    pub line: String,
+}
+
+/// History keeps track of all submitted lines tagged by their kind, so that the frontend can
+/// scope its history to a subset of them.
+#[derive(Debug, Default)]
+pub struct History {
+    entries: VecDeque<HistoryEntry>,
+    scope: HistoryScope,
+    /// Max number of entries, or None if unlimited.
+    max_size: Option<usize>,
+}
+
+impl History {
+    /// Add a line to the history. Returns the kind of the added entry, or None if the line was
+    /// ignored (empty lines and lines that are the same as the previous line are never stored).
+    pub fn add(&mut self, line: &str) -> Option<HistoryKind> {
+        if line.trim().is_empty() || self.entries.back().is_some_and(|entry| entry.line == line) {
+            return None;
+        }
+        if self.max_size == Some(0) {
+            return None;
+        }
+
+        let kind = if is_maybe_command(line) {
+            HistoryKind::Command
+        } else {
+            HistoryKind::Query
+        };
+
+        self.entries.push_back(HistoryEntry {
+            kind,
+            line: line.to_string(),
+        });
+        self.truncate();
+
+        Some(kind)
+    }
+
+    /// Limit the number of entries, removing the oldest entries if there are more.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = Some(max_size);
+        self.truncate();
+    }
+
+    fn truncate(&mut self) {
+        if let Some(max_size) = self.max_size {
+            let excess = self.entries.len().saturating_sub(max_size);
+            self.entries.drain(..excess);
+        }
+    }
+
+    pub fn get_scope(&self) -> HistoryScope {
+        self.scope
+    }
+
+    pub fn set_scope(&mut self, scope: HistoryScope) {
+        self.scope = scope;
+    }
+
+    /// Iterate over all entries (oldest first) that are part of the given scope.
+    pub fn entries_in_scope(&self, scope: HistoryScope) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| scope.includes(entry.kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_scope_entries_by_kind() {
+        let mut history = History::default();
+        history.add("/help");
+        history.add("SELECT 1;");
+        history.add("   ");
+        history.add("/list");
+
+        let sql = history
+            .entries_in_scope(HistoryScope::Sql)
+            .map(|entry| entry.line.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(sql, vec!["SELECT 1;"]);
+
+        let commands = history
+            .entries_in_scope(HistoryScope::Commands)
+            .map(|entry| entry.line.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(commands, vec!["/help", "/list"]);
+
+        assert_eq!(history.entries_in_scope(HistoryScope::All).count(), 3);
+    }
+
+    #[test]
+    fn can_skip_duplicates() {
+        let mut history = History::default();
+        assert_eq!(history.add("SELECT 1;"), Some(HistoryKind::Query));
+        assert_eq!(history.add("SELECT 1;"), None);
+        assert_eq!(history.add("/help"), Some(HistoryKind::Command));
+        assert_eq!(history.add("SELECT 1;"), Some(HistoryKind::Query));
+        assert_eq!(history.entries_in_scope(HistoryScope::All).count(), 3);
+    }
+
+    #[test]
+    fn can_limit_size() {
+        let mut history = History::default();
+        history.add("/help");
+        history.add("SELECT 1;");
+        history.add("/list");
+        history.set_max_size(2);
+
+        let lines = history
+            .entries_in_scope(HistoryScope::All)
+            .map(|entry| entry.line.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec!["SELECT 1;", "/list"]);
+
+        history.add("SELECT 2;");
+        assert_eq!(history.entries_in_scope(HistoryScope::All).count(), 2);
+
+        history.set_max_size(0);
+        assert_eq!(history.add("SELECT 3;"), None);
+        assert_eq!(history.entries_in_scope(HistoryScope::All).count(), 0);
+    }
+}
@@ -1,9 +1,16 @@
 pub mod command;
 pub mod config;
 pub mod db_client;
+pub mod history;
 pub mod logging;
 pub mod lsp;
+pub mod output;
+pub mod schema;
+pub mod script;
+pub mod sql;
 pub mod state;
 pub mod task;
+pub mod version;
 
 mod error;
+mod pgpass;
@@ -1,6 +1,19 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
-use tokio::sync::mpsc;
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::JoinHandle, time};
+
+/// Frames of the spinner shown by `Spinner`.
+const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Time between the frames of a spinner. The first frame is shown after this long as well, so
+/// that operations that finish quickly don't show a spinner.
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
 
 /// Lower discriminant (higher up in the enum declaration) implies a lower
 /// logging level. Messages for all verbosity levels less or equal to the set level
@@ -21,6 +34,18 @@ impl Verbosity {
     }
 }
 
+impl Verbosity {
+    /// Name of the level in JSON logs.
+    pub fn level(&self) -> &'static str {
+        match *self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Standard => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
 impl Display for Verbosity {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
@@ -32,6 +57,35 @@ impl Display for Verbosity {
     }
 }
 
+/// Format of the messages output by a printer. Results (see `PrintPayload::Result`) aren't
+/// formatted.
+#[derive(Serialize, Deserialize, Default, Debug, Eq, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// The message as is, prefixed by its level unless it has standard verbosity.
+    #[default]
+    Human,
+    /// One JSON object per message, e.g.
+    /// `{"level":"warn","message":"...","timestamp":"2024-01-01T12:00:00.000Z"}`.
+    Json,
+}
+
+impl LogFormat {
+    /// Format a message with the given verbosity as a line, without the trailing newline.
+    pub fn format(&self, verbosity: &Verbosity, message: &str) -> String {
+        match self {
+            Self::Human if *verbosity == Verbosity::Standard => message.to_string(),
+            Self::Human => format!("{verbosity} {message}"),
+            Self::Json => serde_json::json!({
+                "level": verbosity.level(),
+                "message": message,
+                "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            })
+            .to_string(),
+        }
+    }
+}
+
 /// PrintPayload represents the messages that can be sent from a logger.
 #[derive(Debug)]
 pub enum PrintPayload {
@@ -39,6 +93,21 @@ pub enum PrintPayload {
     #[allow(dead_code)]
     SetVerbosity(Verbosity),
     Output(Verbosity, String),
+
+    /// Output of a query or command (such as a table or a listing), which is printed as is
+    /// regardless of the log format.
+    Result(String),
+
+    /// Show the given line as the progress of an ongoing operation, replacing any previous one,
+    /// or remove it if None.
+    Progress(Option<String>),
+
+    /// The line editor started (true) or stopped (false) reading a line. Progress isn't shown
+    /// while it's reading, since it would garble the prompt.
+    SetReading(bool),
+
+    /// Format the following messages using the given format, e.g. after the config is reloaded.
+    SetLogFormat(LogFormat),
 }
 
 /// Logger is used to asynchronously pass messages that should be output by Printer.
@@ -60,6 +129,13 @@ impl Logger {
         Ok(())
     }
 
+    /// Output the result of a query or command, see `PrintPayload::Result`.
+    pub fn result(&self, result: &str) -> anyhow::Result<()> {
+        self.log_tx.send(PrintPayload::Result(result.to_string()))?;
+
+        Ok(())
+    }
+
     /// Output with error verbosity.
     pub fn error(&self, msg: &str) -> anyhow::Result<()> {
         self.log_tx
@@ -83,4 +159,94 @@ impl Logger {
 
         Ok(())
     }
+
+    /// Let the printer know whether the line editor is reading a line.
+    pub fn set_reading(&self, reading: bool) -> anyhow::Result<()> {
+        self.log_tx.send(PrintPayload::SetReading(reading))?;
+
+        Ok(())
+    }
+
+    /// Let the printer know that the log format changed.
+    pub fn set_log_format(&self, log_format: LogFormat) -> anyhow::Result<()> {
+        self.log_tx.send(PrintPayload::SetLogFormat(log_format))?;
+
+        Ok(())
+    }
+
+    /// Show a spinner followed by msg until the returned spinner is dropped.
+    pub fn spinner(&self, msg: &str) -> Spinner {
+        let stopped = Arc::new(Mutex::new(false));
+        let log_tx = self.log_tx.clone();
+        let msg = msg.to_string();
+
+        let task_stopped = stopped.clone();
+        let task_log_tx = log_tx.clone();
+        let task = tokio::spawn(async move {
+            let mut ticker =
+                time::interval_at(time::Instant::now() + SPINNER_INTERVAL, SPINNER_INTERVAL);
+            for frame in SPINNER_FRAMES.iter().cycle() {
+                ticker.tick().await;
+
+                // Checked while holding the lock, so that no frame is sent after the spinner has
+                // been removed
+                let Ok(stopped) = task_stopped.lock() else {
+                    return;
+                };
+                let progress = PrintPayload::Progress(Some(format!("{frame} {msg}")));
+                if *stopped || task_log_tx.send(progress).is_err() {
+                    return;
+                }
+            }
+        });
+
+        Spinner {
+            log_tx,
+            stopped,
+            task,
+        }
+    }
+}
+
+/// A spinner shown while an operation is ongoing, see `Logger::spinner`. It's removed when this
+/// is dropped.
+pub struct Spinner {
+    log_tx: mpsc::UnboundedSender<PrintPayload>,
+    stopped: Arc<Mutex<bool>>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        if let Ok(mut stopped) = self.stopped.lock() {
+            *stopped = true;
+            let _ = self.log_tx.send(PrintPayload::Progress(None));
+        }
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_format_log_lines() {
+        assert_eq!(
+            LogFormat::Human.format(&Verbosity::Standard, "Connected."),
+            "Connected."
+        );
+        assert_eq!(
+            LogFormat::Human.format(&Verbosity::Warn, "Lost the connection."),
+            "WARN: Lost the connection."
+        );
+
+        let line = LogFormat::Json.format(&Verbosity::Error, "failed\nto connect");
+        assert!(!line.contains('\n'));
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["level"], "error");
+        assert_eq!(value["message"], "failed\nto connect");
+        let timestamp = value["timestamp"].as_str().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(timestamp).is_ok());
+    }
 }
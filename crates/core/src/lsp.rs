@@ -4,21 +4,31 @@ use server::LspServer;
 
 use crate::{logging::Logger, state::State, task::Task};
 
+pub mod assistant;
 pub mod client;
 pub mod completer;
 pub mod notification_handler;
 mod payload;
+mod request_handler;
 mod response;
+mod router;
 pub mod server;
 
-/// Create instances of the LspClient and LspServer.
-pub fn build_lsp(state: State, logger: Logger) -> (LspClient, LspServer, NotificationHandler) {
-    let (lsp_server, channels) = LspServer::new(logger.clone());
+/// Create instances of the LspClient and LspServer. channel_capacity is the number of messages
+/// buffered between them.
+pub fn build_lsp(
+    state: State,
+    logger: Logger,
+    channel_capacity: usize,
+) -> (LspClient, LspServer, NotificationHandler) {
+    let (lsp_server, channels) =
+        LspServer::new(logger.clone(), channel_capacity, state.lsp_stderr.clone());
     let lsp_client = LspClient::new(
         channels.req_tx,
-        channels.req_output_tx,
+        channels.router,
         state.clone(),
         logger.clone(),
+        channel_capacity,
     );
 
     let notification_handler = NotificationHandler::new(state, logger, channels.notif_rx);
@@ -0,0 +1,281 @@
+use anyhow::{anyhow, bail};
+use lsp_types::{Documentation, ParameterLabel, SignatureHelp, TextEdit};
+
+use crate::lsp::{
+    client::LspClient,
+    completer::{offset_from_row_and_col, row_and_col_from_offset},
+};
+
+/// LspAssistant provides LSP features other than completion (such as hover information) for the
+/// REPL input.
+#[derive(Clone)]
+pub struct LspAssistant {
+    client: LspClient,
+}
+
+impl LspAssistant {
+    pub fn new(client: LspClient) -> Self {
+        LspAssistant { client }
+    }
+
+    /// Output hover information for the token at pos, handling errors by logging them.
+    /// Returns an error if logging failed.
+    pub async fn hover_with_logging(&self, line: &str, pos: usize) -> anyhow::Result<()> {
+        match self.hover(line, pos).await {
+            Ok(Some(text)) => self.client.get_logger().standard(&text),
+            Ok(None) => self
+                .client
+                .get_logger()
+                .standard("No hover information available."),
+            Err(e) => self.client.get_logger().error(&e.to_string()),
+        }
+    }
+
+    /// Format the line, handling errors by logging them. Returns the formatted line, or None if
+    /// formatting failed.
+    /// Returns an error if logging failed.
+    pub async fn format_with_logging(&self, line: &str) -> anyhow::Result<Option<String>> {
+        match self.format(line).await {
+            Ok(formatted) => Ok(Some(formatted)),
+            Err(e) => {
+                self.client.get_logger().error(&e.to_string())?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Output the active signature at pos, if any. Since this is triggered while typing, errors
+    /// (including the server not being initialized) are only logged at debug level.
+    /// Returns an error if logging failed.
+    pub async fn signature_help_with_logging(&self, line: &str, pos: usize) -> anyhow::Result<()> {
+        if !self.client.is_initialized().await {
+            return Ok(());
+        }
+
+        match self.signature_help(line, pos).await {
+            Ok(Some(text)) => self.client.get_logger().standard(&text),
+            Ok(None) => Ok(()),
+            Err(e) => self
+                .client
+                .get_logger()
+                .debug(&format!("signature help failed: {e}")),
+        }
+    }
+
+    /// Request hover information for the token at pos.
+    async fn hover(&self, line: &str, pos: usize) -> anyhow::Result<Option<String>> {
+        if !self.client.is_initialized().await {
+            return Err(anyhow!("LSP server is not initialized"));
+        }
+
+        // Need the line to at least contain an empty character
+        let line = if line.is_empty() { " " } else { line };
+
+        self.client.on_change(line).await?;
+        let (row, col) = row_and_col_from_offset(line, pos).ok_or(anyhow!("pos out of bounds"))?;
+        self.client
+            .request_hover(row.try_into()?, col.try_into()?)
+            .await
+    }
+
+    /// Request the signature at pos, formatted for output.
+    async fn signature_help(&self, line: &str, pos: usize) -> anyhow::Result<Option<String>> {
+        self.client.on_change(line).await?;
+        let (row, col) = row_and_col_from_offset(line, pos).ok_or(anyhow!("pos out of bounds"))?;
+        let signature_help = self
+            .client
+            .request_signature_help(row.try_into()?, col.try_into()?)
+            .await?;
+
+        Ok(signature_help.and_then(format_signature_help))
+    }
+
+    /// Format the line using the LSP server.
+    async fn format(&self, line: &str) -> anyhow::Result<String> {
+        if !self.client.is_initialized().await {
+            bail!("LSP server is not initialized");
+        }
+
+        self.client.on_change(line).await?;
+        let edits = self.client.request_formatting().await?;
+        apply_text_edits(line, edits)
+    }
+}
+
+/// Format the active signature and parameter. Returns None if there is no signature.
+fn format_signature_help(signature_help: SignatureHelp) -> Option<String> {
+    let active_signature: usize = signature_help
+        .active_signature
+        .unwrap_or(0)
+        .try_into()
+        .ok()?;
+    let signature = signature_help
+        .signatures
+        .get(active_signature)
+        .or(signature_help.signatures.first())?;
+
+    let mut output = signature.label.clone();
+
+    let active_parameter = signature
+        .active_parameter
+        .or(signature_help.active_parameter)
+        .and_then(|index| {
+            let index: usize = index.try_into().ok()?;
+            signature.parameters.as_ref()?.get(index)
+        });
+
+    if let Some(parameter) = active_parameter {
+        let label = match &parameter.label {
+            ParameterLabel::Simple(label) => label.clone(),
+            ParameterLabel::LabelOffsets([start, end]) => signature
+                .label
+                .chars()
+                .skip(*start as usize)
+                .take(end.saturating_sub(*start) as usize)
+                .collect(),
+        };
+
+        output.push_str(&format!("\nparameter: {label}"));
+
+        let documentation = parameter.documentation.as_ref().map(|doc| match doc {
+            Documentation::String(text) => text,
+            Documentation::MarkupContent(markup) => &markup.value,
+        });
+        if let Some(documentation) = documentation {
+            output.push_str(&format!(" - {documentation}"));
+        }
+    }
+
+    Some(output)
+}
+
+/// Apply the edits to text. The edits are expected to not overlap.
+fn apply_text_edits(text: &str, edits: Vec<TextEdit>) -> anyhow::Result<String> {
+    let mut edits = edits
+        .into_iter()
+        .map(|edit| {
+            let start = &edit.range.start;
+            let end = &edit.range.end;
+            let start_offset =
+                offset_from_row_and_col(text, start.line.try_into()?, start.character.try_into()?);
+            let end_offset =
+                offset_from_row_and_col(text, end.line.try_into()?, end.character.try_into()?);
+
+            if start_offset > end_offset
+                || !text.is_char_boundary(start_offset)
+                || !text.is_char_boundary(end_offset)
+            {
+                bail!("invalid text edit range: {:?}", edit.range);
+            }
+
+            Ok((start_offset..end_offset, edit.new_text))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // Apply the edits from the end so that the offsets of the remaining edits stay valid.
+    edits.sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+
+    let mut result = text.to_string();
+    for (range, new_text) in edits {
+        result.replace_range(range, &new_text);
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::{ParameterInformation, Position, Range, SignatureInformation};
+
+    use super::*;
+
+    fn edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range {
+                start: Position::new(start.0, start.1),
+                end: Position::new(end.0, end.1),
+            },
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn can_apply_text_edits() {
+        let edits = vec![
+            edit((0, 0), (0, 6), "SELECT"),
+            edit((0, 8), (1, 4), "\nFROM"),
+        ];
+
+        assert_eq!(
+            apply_text_edits("select 1 \nfrom foo", edits).unwrap(),
+            "SELECT 1\nFROM foo"
+        );
+    }
+
+    #[test]
+    fn can_apply_text_edit_replacing_everything() {
+        let edits = vec![edit((0, 0), (99, 0), "SELECT\n    1;")];
+        assert_eq!(
+            apply_text_edits("select 1;", edits).unwrap(),
+            "SELECT\n    1;"
+        );
+    }
+
+    fn signature_help(parameters: Vec<ParameterLabel>, active: Option<u32>) -> SignatureHelp {
+        SignatureHelp {
+            signatures: vec![SignatureInformation {
+                label: "date_trunc(field text, source timestamp)".to_string(),
+                documentation: None,
+                parameters: Some(
+                    parameters
+                        .into_iter()
+                        .map(|label| ParameterInformation {
+                            label,
+                            documentation: None,
+                        })
+                        .collect(),
+                ),
+                active_parameter: None,
+            }],
+            active_signature: None,
+            active_parameter: active,
+        }
+    }
+
+    #[test]
+    fn can_format_signature_help_with_simple_labels() {
+        let help = signature_help(
+            vec![
+                ParameterLabel::Simple("field text".to_string()),
+                ParameterLabel::Simple("source timestamp".to_string()),
+            ],
+            Some(1),
+        );
+
+        assert_eq!(
+            format_signature_help(help).unwrap(),
+            "date_trunc(field text, source timestamp)\nparameter: source timestamp"
+        );
+    }
+
+    #[test]
+    fn can_format_signature_help_with_label_offsets() {
+        let help = signature_help(vec![ParameterLabel::LabelOffsets([11, 21])], Some(0));
+
+        assert_eq!(
+            format_signature_help(help).unwrap(),
+            "date_trunc(field text, source timestamp)\nparameter: field text"
+        );
+    }
+
+    #[test]
+    fn can_format_signature_help_without_signatures() {
+        let help = SignatureHelp {
+            signatures: vec![],
+            active_signature: None,
+            active_parameter: None,
+        };
+
+        assert_eq!(format_signature_help(help), None);
+    }
+}
@@ -1,31 +1,155 @@
-use std::{str::FromStr, sync::Arc, time::Duration};
+use std::{
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
-use anyhow::{anyhow, Context};
-use jsonrpsee_types::{response::Success, RequestSer, Response};
-use lsp_types::{CompletionResponse, InitializeResult, Url};
+use anyhow::{anyhow, bail, Context};
+use jsonrpsee_types::{
+    error::METHOD_NOT_FOUND_CODE, response::Success, ErrorObjectOwned, RequestSer, Response,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionResponse, CompletionTextEdit, Documentation,
+    Hover, HoverContents, HoverProviderCapability, InitializeResult, MarkedString, OneOf, Range,
+    ServerCapabilities, ServerInfo, SignatureHelp, TextEdit, Url,
+};
 use serde::Deserialize;
 use serde_json::Value;
 use tokio::{
     sync::{broadcast, RwLock},
-    task::JoinHandle,
     time::timeout,
 };
 
 use crate::{
     config::{self, Connection},
     logging::Logger,
-    lsp::payload::{self, LspPayload},
+    lsp::{
+        payload::{self, LspPayload},
+        router::ResponseRouter,
+    },
     state::State,
 };
 
+/// Completion item returned by the LSP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionCandidate {
+    pub label: String,
+
+    /// Additional information about the item, such as the type of a column.
+    pub detail: Option<String>,
+
+    /// Used by the server to decide the order of the items.
+    pub sort_text: Option<String>,
+
+    /// True if the item is an SQL keyword (as opposed to e.g. a table or column name).
+    pub is_keyword: bool,
+
+    /// Text to insert instead of the label and the range it replaces, if given by the server.
+    pub text_edit: Option<CompletionEdit>,
+}
+
+impl CompletionCandidate {
+    /// Text inserted when the candidate is chosen.
+    pub fn insert_text(&self) -> &str {
+        self.text_edit
+            .as_ref()
+            .map_or(&self.label, |text_edit| &text_edit.new_text)
+    }
+}
+
+/// Text inserted by a completion item, replacing the text in range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletionEdit {
+    /// Range of the replaced text, which ends at the cursor.
+    pub range: Range,
+    pub new_text: String,
+}
+
+impl From<CompletionTextEdit> for CompletionEdit {
+    fn from(text_edit: CompletionTextEdit) -> Self {
+        match text_edit {
+            CompletionTextEdit::Edit(edit) => CompletionEdit {
+                range: edit.range,
+                new_text: edit.new_text,
+            },
+            // Inserting replaces the text up to the cursor, like completion in the line editor
+            CompletionTextEdit::InsertAndReplace(edit) => CompletionEdit {
+                range: edit.insert,
+                new_text: edit.new_text,
+            },
+        }
+    }
+}
+
+impl From<CompletionItem> for CompletionCandidate {
+    fn from(item: CompletionItem) -> Self {
+        // Fall back to the first line of the documentation if there is no detail
+        let detail = item.detail.or_else(|| {
+            let documentation = match item.documentation? {
+                Documentation::String(text) => text,
+                Documentation::MarkupContent(markup) => markup.value,
+            };
+            documentation.lines().next().map(|line| line.to_string())
+        });
+
+        CompletionCandidate {
+            label: item.label,
+            detail: detail.filter(|detail| !detail.trim().is_empty()),
+            sort_text: item.sort_text,
+            is_keyword: item.kind == Some(CompletionItemKind::KEYWORD),
+            text_edit: item.text_edit.map(CompletionEdit::from),
+        }
+    }
+}
+
+/// Optional LSP feature, which is only requested if the server advertised it when it was
+/// initialized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LspFeature {
+    Hover,
+    Formatting,
+    SignatureHelp,
+}
+
+impl LspFeature {
+    /// Returns true if the capabilities include the feature.
+    fn is_supported(self, capabilities: &ServerCapabilities) -> bool {
+        match self {
+            LspFeature::Hover => !matches!(
+                capabilities.hover_provider,
+                None | Some(HoverProviderCapability::Simple(false))
+            ),
+            LspFeature::Formatting => !matches!(
+                capabilities.document_formatting_provider,
+                None | Some(OneOf::Left(false))
+            ),
+            LspFeature::SignatureHelp => capabilities.signature_help_provider.is_some(),
+        }
+    }
+}
+
+impl Display for LspFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            LspFeature::Hover => "hover",
+            LspFeature::Formatting => "formatting",
+            LspFeature::SignatureHelp => "signature help",
+        };
+        write!(f, "{name}")
+    }
+}
+
 #[derive(Clone)]
 pub struct LspClient {
     /// Used to send requests to the LSP server.
     req_tx: broadcast::Sender<String>,
 
-    /// Used to receive LSP request responses from the LSP server. Sender is passed so that a
-    /// receiver can be created for each instance of the LspClient.
-    req_output_tx: broadcast::Sender<Vec<u8>>,
+    /// Used to receive LSP request responses from the LSP server.
+    router: ResponseRouter,
 
     /// Document URI placeholder used to identify the REPL input.
     document_uri: &'static Url,
@@ -38,6 +162,24 @@ pub struct LspClient {
 
     /// True if initialized.
     initialized: Arc<RwLock<bool>>,
+
+    /// Incremented every time the client is reset, so that results cached from a previous LSP
+    /// server can be discarded.
+    server_generation: Arc<AtomicU64>,
+
+    /// Capabilities advertised by the LSP server when it was initialized.
+    capabilities: Arc<RwLock<Option<ServerCapabilities>>>,
+
+    /// Characters that the LSP server wants completion to be triggered on, from its
+    /// capabilities. Kept separately so that they can be checked for every key without waiting.
+    completion_trigger_characters: Arc<Mutex<Vec<String>>>,
+
+    /// Name and version of the LSP server, if it sent them when it was initialized.
+    server_info: Arc<RwLock<Option<ServerInfo>>>,
+
+    /// Number of messages buffered in the channels to and from the LSP server, which is fixed
+    /// when they're created.
+    channel_capacity: usize,
 }
 
 impl LspClient {
@@ -46,19 +188,25 @@ impl LspClient {
 
     pub fn new(
         req_tx: broadcast::Sender<String>,
-        req_output_tx: broadcast::Sender<Vec<u8>>,
+        router: ResponseRouter,
         state: State,
         logger: Logger,
+        channel_capacity: usize,
     ) -> Self {
         LspClient {
             req_tx,
-            req_output_tx,
+            router,
             document_uri: Box::leak(Box::new(
                 Url::from_str("repl:///repl").expect("uri should be valid"),
             )),
             state,
             logger,
             initialized: Arc::new(RwLock::new(false)),
+            server_generation: Arc::new(AtomicU64::new(0)),
+            capabilities: Arc::new(RwLock::new(None)),
+            completion_trigger_characters: Arc::new(Mutex::new(vec![])),
+            server_info: Arc::new(RwLock::new(None)),
+            channel_capacity,
         }
     }
 
@@ -67,6 +215,68 @@ impl LspClient {
         *initialized
     }
 
+    /// Mark the client as uninitialized, e.g. when the LSP server is about to be replaced.
+    pub async fn reset(&self) {
+        let mut initialized = self.initialized.write().await;
+        *initialized = false;
+        self.server_generation.fetch_add(1, Ordering::SeqCst);
+        self.capabilities.write().await.take();
+        self.set_completion_trigger_characters(vec![]);
+        self.server_info.write().await.take();
+    }
+
+    pub fn get_server_generation(&self) -> u64 {
+        self.server_generation.load(Ordering::SeqCst)
+    }
+
+    /// Capabilities advertised by the LSP server, or None if it isn't initialized.
+    pub async fn get_capabilities(&self) -> Option<ServerCapabilities> {
+        self.capabilities.read().await.clone()
+    }
+
+    /// Name and version of the LSP server, or None if it isn't initialized or didn't send them.
+    pub async fn get_server_info(&self) -> Option<ServerInfo> {
+        self.server_info.read().await.clone()
+    }
+
+    /// Returns true if the LSP server is initialized and supports the feature.
+    pub async fn supports(&self, feature: LspFeature) -> bool {
+        self.capabilities
+            .read()
+            .await
+            .as_ref()
+            .is_some_and(|capabilities| feature.is_supported(capabilities))
+    }
+
+    /// Fail without making a request if no LSP server is running or it doesn't support the
+    /// feature, instead of waiting for the request to time out.
+    async fn ensure_supported(&self, feature: LspFeature) -> anyhow::Result<()> {
+        // The capabilities are unknown until the server is initialized
+        if !self.is_initialized().await {
+            bail!("no LSP server is running");
+        }
+        if !self.supports(feature).await {
+            bail!("{feature} is unsupported by this server");
+        }
+
+        Ok(())
+    }
+
+    /// Characters that the LSP server wants completion to be triggered on, if it's initialized.
+    pub fn get_completion_trigger_characters(&self) -> Vec<String> {
+        self.completion_trigger_characters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    fn set_completion_trigger_characters(&self, trigger_characters: Vec<String>) {
+        *self
+            .completion_trigger_characters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = trigger_characters;
+    }
+
     /// Inform the LSP server that the text file (REPL input) changed.
     pub async fn on_change(&self, text: &str) -> anyhow::Result<()> {
         // We don't need to change the version number since we sync
@@ -81,7 +291,11 @@ impl LspClient {
     ///
     /// Some LSP servers such as sqls don't seem to conform to the specification
     /// fully, so we need to do some manual parsing.
-    pub async fn request_completion(&self, line: u32, offset: u32) -> anyhow::Result<Vec<String>> {
+    pub async fn request_completion(
+        &self,
+        line: u32,
+        offset: u32,
+    ) -> anyhow::Result<Vec<CompletionCandidate>> {
         let completion_request = payload::completion(self.document_uri.clone(), line, offset)?;
 
         let res = self
@@ -96,27 +310,103 @@ impl LspClient {
                 CompletionResponse::List(list) => list.items,
             };
 
-            return Ok(items.into_iter().map(|item| item.label).collect());
+            return Ok(items.into_iter().map(CompletionCandidate::from).collect());
         }
 
         // Fall back to manual parsing
-        let items: Option<Vec<String>> = (|| {
+        let items: Option<Vec<CompletionCandidate>> = (|| {
             // Assume that it is an array of CompletionItem.
-            let items = res
-                .as_array()?
+            res.as_array()?
                 .iter()
-                .map(|item| item.get("label")?.as_str());
-
-            if items.clone().any(|item| item.is_none()) {
-                return None;
-            }
-
-            Some(items.filter_map(|item| Some(item?.to_string())).collect())
+                .map(|item| {
+                    Some(CompletionCandidate {
+                        label: item.get("label")?.as_str()?.to_string(),
+                        detail: item
+                            .get("detail")
+                            .and_then(|detail| detail.as_str())
+                            .filter(|detail| !detail.trim().is_empty())
+                            .map(|detail| detail.to_string()),
+                        sort_text: item
+                            .get("sortText")
+                            .and_then(|sort_text| sort_text.as_str())
+                            .map(|sort_text| sort_text.to_string()),
+                        is_keyword: item.get("kind").and_then(|kind| kind.as_i64()) == Some(14),
+                        text_edit: item
+                            .get("textEdit")
+                            .and_then(|text_edit| {
+                                serde_json::from_value::<CompletionTextEdit>(text_edit.clone()).ok()
+                            })
+                            .map(CompletionEdit::from),
+                    })
+                })
+                .collect()
         })();
 
         items.ok_or(anyhow!("failed parsing completion response: {:?}", res))
     }
 
+    /// Request the LSP server for hover information at the given cursor position. Returns the
+    /// contents as plain text/markdown, or None if there is nothing to show.
+    pub async fn request_hover(&self, line: u32, offset: u32) -> anyhow::Result<Option<String>> {
+        self.ensure_supported(LspFeature::Hover).await?;
+        let hover_request = payload::hover(self.document_uri.clone(), line, offset)?;
+
+        let res = self
+            .send_blocking_request::<Option<Hover>>(hover_request)
+            .await?;
+
+        let marked_string_to_text = |marked_string: MarkedString| match marked_string {
+            MarkedString::String(text) => text,
+            MarkedString::LanguageString(language_string) => language_string.value,
+        };
+
+        let text = res.map(|hover| match hover.contents {
+            HoverContents::Scalar(marked_string) => marked_string_to_text(marked_string),
+            HoverContents::Array(marked_strings) => marked_strings
+                .into_iter()
+                .map(marked_string_to_text)
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+            HoverContents::Markup(markup) => markup.value,
+        });
+
+        Ok(text.filter(|text| !text.trim().is_empty()))
+    }
+
+    /// Request the LSP server for signature help at the given cursor position. Returns None if
+    /// there is no signature at the position.
+    pub async fn request_signature_help(
+        &self,
+        line: u32,
+        offset: u32,
+    ) -> anyhow::Result<Option<SignatureHelp>> {
+        self.ensure_supported(LspFeature::SignatureHelp).await?;
+        let signature_help_request =
+            payload::signature_help(self.document_uri.clone(), line, offset)?;
+
+        self.send_blocking_request::<Option<SignatureHelp>>(signature_help_request)
+            .await
+    }
+
+    /// Request the LSP server to format the whole document. Returns the edits that should be
+    /// applied to the document.
+    pub async fn request_formatting(&self) -> anyhow::Result<Vec<TextEdit>> {
+        self.ensure_supported(LspFeature::Formatting).await?;
+        let formatting_request = payload::formatting(self.document_uri.clone())?;
+
+        let res = self
+            .send_blocking_request::<Option<Vec<TextEdit>>>(formatting_request)
+            .await
+            .map_err(|e| match e.downcast_ref::<ErrorObjectOwned>() {
+                Some(err) if err.code() == METHOD_NOT_FOUND_CODE => {
+                    anyhow!("{} is unsupported by this server", LspFeature::Formatting)
+                }
+                _ => e,
+            })?;
+
+        Ok(res.unwrap_or_default())
+    }
+
     /// Initialize the LSP server with the given connection.
     pub async fn init_lsp_server(
         &self,
@@ -127,17 +417,32 @@ impl LspClient {
         // continue
         let init_options = server_type.to_initialization_options(connection)?;
         let init_payload = payload::initialize(init_options)?;
-        let _ = self
+        let init_result = self
             .send_blocking_request::<InitializeResult>(init_payload)
             .await?;
+        self.set_completion_trigger_characters(
+            init_result
+                .capabilities
+                .completion_provider
+                .as_ref()
+                .and_then(|provider| provider.trigger_characters.clone())
+                .unwrap_or_default(),
+        );
+        self.capabilities
+            .write()
+            .await
+            .replace(init_result.capabilities);
+        *self.server_info.write().await = init_result.server_info;
 
         // Acknowledge that we've received the initialize response. Used by
         // postgres-language-server to read the configuration file and connect to the database.
         let initialized_payload = payload::initialized()?;
         self.send_payload(initialized_payload).await?;
 
-        // Create our "document" (in reality it's just the current input in the REPL)
-        let open_payload = payload::did_open(self.document_uri.clone(), "")?;
+        // Create our "document" (in reality it's just the current input in the REPL), with the
+        // text the previous server had so that diagnostics refer to the same text
+        let text = self.state.lsp_text.lock().await.clone();
+        let open_payload = payload::did_open(self.document_uri.clone(), &text)?;
         self.send_payload(open_payload).await?;
 
         let mut initialized = self.initialized.write().await;
@@ -146,11 +451,47 @@ impl LspClient {
         Ok(())
     }
 
+    /// Close the document on the LSP server, e.g. before the server is replaced. Does nothing if
+    /// the server isn't initialized.
+    pub async fn close_document(&self) -> anyhow::Result<()> {
+        if !self.is_initialized().await {
+            return Ok(());
+        }
+
+        let close_payload = payload::did_close(self.document_uri.clone())?;
+        self.send_payload(close_payload).await
+    }
+
+    /// Ask the LSP server to shut down and exit. Does nothing if the server isn't initialized.
+    pub async fn shutdown(&self) -> anyhow::Result<()> {
+        if !self.is_initialized().await {
+            return Ok(());
+        }
+
+        self.close_document().await?;
+        self.reset().await;
+        self.send_blocking_request::<Value>(payload::shutdown())
+            .await?;
+        self.send_payload(payload::exit()).await?;
+
+        Ok(())
+    }
+
     /// Shortcut to get the logger.
     pub fn get_logger(&self) -> &Logger {
         &self.logger
     }
 
+    /// Number of messages buffered in each channel to and from the LSP server.
+    pub fn get_channel_capacity(&self) -> usize {
+        self.channel_capacity
+    }
+
+    /// Shortcut to get the shared application state.
+    pub fn get_state(&self) -> &State {
+        &self.state
+    }
+
     /// Helper that makes a async LSP request that resolves when the
     /// response is retrieved, or times out.
     ///
@@ -161,37 +502,28 @@ impl LspClient {
     ) -> anyhow::Result<T> {
         let req_payload = req.to_payload()?;
 
-        // Wait for response
-        let mut output_rx = self.req_output_tx.subscribe();
-        let error_message = format!(
-            "received no response for blocking request: {}",
-            &req_payload
-        );
-        let res_payload: JoinHandle<anyhow::Result<T>> = tokio::spawn(async move {
-            let res = loop {
-                let body = timeout(Self::REQUEST_TIMEOUT, output_rx.recv())
-                    .await
-                    .with_context(|| error_message.clone())??;
-
-                // Find the body with the corresponding ID. We defer the parsing of the payload
-                // until later so that we can return an error if the ID is matching but the payload
-                // has an unexpected structure.
-                if let Ok(res) = serde_json::from_slice::<Response<Value>>(&body) {
-                    if req.id == res.id {
-                        break res.into_owned();
-                    }
-                }
-            };
-
-            let payload = Success::try_from(res)?;
-            serde_json::from_value(payload.result)
-                .map_err(anyhow::Error::from)
-                .with_context(|| "failed to deserialize server response")
-        });
-
-        self.req_tx.send(req_payload)?;
-
-        res_payload.await?
+        // Register before sending so that the response can't arrive before anyone is waiting
+        let id = req.id.clone().into_owned();
+        let res_rx = self.router.register(id.clone());
+        self.req_tx.send(req_payload.clone())?;
+
+        let body = match timeout(Self::REQUEST_TIMEOUT, res_rx).await {
+            Ok(body) => body?,
+            Err(e) => {
+                self.router.unregister(&id);
+                return Err(e).with_context(|| {
+                    format!("received no response for blocking request: {req_payload}")
+                });
+            }
+        };
+
+        // The payload is parsed here so that we can return an error if it has an unexpected
+        // structure.
+        let res = serde_json::from_slice::<Response<Value>>(&body)?.into_owned();
+        let payload = Success::try_from(res)?;
+        serde_json::from_value(payload.result)
+            .map_err(anyhow::Error::from)
+            .with_context(|| "failed to deserialize server response")
     }
 
     /// Helper that sends an LSP payload to the server without waiting for a response.
@@ -201,3 +533,44 @@ impl LspClient {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use lsp_types::SignatureHelpOptions;
+
+    use super::*;
+
+    #[test]
+    fn can_check_feature_support() {
+        let mut capabilities = ServerCapabilities::default();
+        assert!(!LspFeature::Hover.is_supported(&capabilities));
+        assert!(!LspFeature::Formatting.is_supported(&capabilities));
+        assert!(!LspFeature::SignatureHelp.is_supported(&capabilities));
+
+        capabilities.hover_provider = Some(HoverProviderCapability::Simple(false));
+        capabilities.document_formatting_provider = Some(OneOf::Left(true));
+        capabilities.signature_help_provider = Some(SignatureHelpOptions::default());
+        assert!(!LspFeature::Hover.is_supported(&capabilities));
+        assert!(LspFeature::Formatting.is_supported(&capabilities));
+        assert!(LspFeature::SignatureHelp.is_supported(&capabilities));
+    }
+
+    #[tokio::test]
+    async fn can_refuse_requests_without_lsp_server() {
+        let (req_tx, _) = broadcast::channel(1);
+        let (log_tx, _log_rx) = tokio::sync::mpsc::unbounded_channel();
+        let client = LspClient::new(
+            req_tx,
+            ResponseRouter::default(),
+            State::default(),
+            Logger::new(log_tx),
+            1,
+        );
+
+        let err = client
+            .ensure_supported(LspFeature::Hover)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "no LSP server is running");
+    }
+}
@@ -1,13 +1,148 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
 use anyhow::anyhow;
+use tokio::sync::watch;
+
+use unicode_width::UnicodeWidthStr;
 
-use crate::{command::is_maybe_command, config::get_config, lsp::client::LspClient};
+use crate::{
+    command::is_maybe_command,
+    config::{self, get_config, Config, KeywordCase},
+    lsp::client::{CompletionCandidate, LspClient},
+    schema::Schema,
+    sql::{self, Dialect, SegmentKind},
+};
 
-use crate::command;
+use crate::command::{self, ArgCompletion};
 
+#[derive(Clone)]
 pub struct LspCompleter {
     client: LspClient,
+
+    /// Incremented for every completion request so that in-flight requests can tell when they
+    /// have been superseded by a newer one.
+    generation: Arc<watch::Sender<u64>>,
+
+    /// Recent LSP completion results.
+    cache: Arc<Mutex<CompletionCache>>,
+
+    /// Latest LSP completion request, so that completing the same text and position while it's
+    /// running (e.g. pressing Tab before a prefetch finished) waits for it instead of making
+    /// another request.
+    pending: Arc<Mutex<Option<PendingRequest>>>,
+
+    /// Completion settings from the config, see `set_settings`.
+    settings: Arc<Mutex<CompletionSettings>>,
+
+    /// SQL dialect of the current connection, used to tell whether the cursor is in a literal or
+    /// comment.
+    dialect: Arc<Mutex<Dialect>>,
+}
+
+/// Settings that affect completion. They're only read from the config when they change, since
+/// they're used for every key typed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompletionSettings {
+    /// Characters that trigger completion, see `Config::get_completion_triggers`.
+    pub triggers: Vec<String>,
+
+    /// See `Config::get_completion_debounce_ms`.
+    pub debounce_ms: u64,
+
+    pub keyword_case: KeywordCase,
+}
+
+impl CompletionSettings {
+    pub fn from_config(config: &Config) -> Self {
+        CompletionSettings {
+            triggers: config
+                .get_completion_triggers()
+                .chars()
+                .map(String::from)
+                .collect(),
+            debounce_ms: config.get_completion_debounce_ms(),
+            keyword_case: config.get_completion_keyword_case(),
+        }
+    }
+}
+
+/// LSP completion request whose result is added to the cache once it's done.
+struct PendingRequest {
+    server_generation: u64,
+    text: String,
+    pos: usize,
+
+    /// Set to true when the result is cached. Closed without being set if the request failed or
+    /// was cancelled.
+    done: watch::Receiver<bool>,
+}
+
+/// Bounded cache of LSP completion results keyed by the text and cursor position they were
+/// requested for. All entries are dropped when the LSP server changes.
+#[derive(Debug, Default)]
+struct CompletionCache {
+    /// Server generation (see `LspClient::get_server_generation`) the entries belong to.
+    server_generation: u64,
+
+    /// Most recently used entries first.
+    entries: VecDeque<((String, usize), Vec<CompletionCandidate>)>,
+}
+
+impl CompletionCache {
+    const MAX_ENTRIES: usize = 16;
+
+    fn get(
+        &mut self,
+        server_generation: u64,
+        text: &str,
+        pos: usize,
+    ) -> Option<Vec<CompletionCandidate>> {
+        self.invalidate_if_stale(server_generation);
+
+        let index = self
+            .entries
+            .iter()
+            .position(|((entry_text, entry_pos), _)| entry_text == text && *entry_pos == pos)?;
+        let entry = self.entries.remove(index)?;
+        let candidates = entry.1.clone();
+        self.entries.push_front(entry);
+
+        Some(candidates)
+    }
+
+    fn insert(
+        &mut self,
+        server_generation: u64,
+        text: &str,
+        pos: usize,
+        candidates: Vec<CompletionCandidate>,
+    ) {
+        self.invalidate_if_stale(server_generation);
+
+        self.entries
+            .push_front(((text.to_string(), pos), candidates));
+        self.entries.truncate(Self::MAX_ENTRIES);
+    }
+
+    fn invalidate_if_stale(&mut self, server_generation: u64) {
+        if self.server_generation != server_generation {
+            self.entries.clear();
+            self.server_generation = server_generation;
+        }
+    }
 }
 
+/// Keywords that are followed by a table or column name, so that completion is triggered by the
+/// whitespace after them.
+const TRIGGER_KEYWORDS: &[&str] = &[
+    "SELECT", "FROM", "JOIN", "WHERE", "AND", "OR", "ON", "BY", "INTO", "UPDATE", "TABLE", "SET",
+    "HAVING",
+];
+
 /// Completion candidate pair.
 #[derive(Debug, Clone)]
 pub struct CandidatePair {
@@ -15,31 +150,209 @@ pub struct CandidatePair {
     pub display: String,
     /// Text to insert in line.
     pub replacement: String,
+    /// Additional information about the candidate, such as its type.
+    pub detail: Option<String>,
 }
 
 impl LspCompleter {
-    pub fn new(client: LspClient) -> Self {
-        LspCompleter { client }
+    /// Maximum time to wait for a completion that isn't ready yet.
+    const COMPLETION_TIMEOUT: Duration = Duration::from_millis(500);
+
+    /// Maximum number of candidates listed when completion is triggered by a character.
+    const MAX_TRIGGERED_CANDIDATES: usize = 20;
+
+    pub fn new(client: LspClient, settings: CompletionSettings) -> Self {
+        let (generation, _) = watch::channel(0);
+        LspCompleter {
+            client,
+            generation: Arc::new(generation),
+            cache: Arc::new(Mutex::new(CompletionCache::default())),
+            pending: Arc::new(Mutex::new(None)),
+            settings: Arc::new(Mutex::new(settings)),
+            dialect: Arc::new(Mutex::new(Dialect::default())),
+        }
+    }
+
+    /// Replace the completion settings, after they were changed using `/set` or the config was
+    /// reloaded.
+    pub fn set_settings(&self, settings: CompletionSettings) -> anyhow::Result<()> {
+        *self
+            .settings
+            .lock()
+            .map_err(|_| anyhow!("completion settings lock poisoned"))? = settings;
+        Ok(())
+    }
+
+    fn get_settings(&self) -> anyhow::Result<CompletionSettings> {
+        Ok(self
+            .settings
+            .lock()
+            .map_err(|_| anyhow!("completion settings lock poisoned"))?
+            .clone())
+    }
+
+    /// Set the SQL dialect of the current connection.
+    pub fn set_dialect(&self, dialect: Dialect) -> anyhow::Result<()> {
+        *self
+            .dialect
+            .lock()
+            .map_err(|_| anyhow!("completion dialect lock poisoned"))? = dialect;
+        Ok(())
+    }
+
+    /// Start completing the line in the background, so that the result is ready when completion
+    /// is requested for the same line and position. Like `complete_with_logging`, a newer call
+    /// cancels this one.
+    pub fn prefetch(&self, line: &str, pos: usize) {
+        // Command completion doesn't involve the LSP server, so it's always ready
+        if line.trim().is_empty() || is_maybe_command(line) {
+            return;
+        }
+
+        let completer = self.clone();
+        let line = line.to_string();
+        tokio::spawn(async move { completer.complete_with_logging(&line, pos).await });
+    }
+
+    /// Returns true if the character just typed before pos should show the completion
+    /// candidates, i.e. it's one of the configured `completion_triggers` or one of the trigger
+    /// characters of the LSP server. Doesn't wait for anything, since it's checked for every key.
+    pub fn is_triggered(&self, line: &str, pos: usize) -> anyhow::Result<bool> {
+        if is_maybe_command(line) {
+            return Ok(false);
+        }
+
+        let mut triggers = self.get_settings()?.triggers;
+        triggers.extend(self.client.get_completion_trigger_characters());
+        let dialect = *self
+            .dialect
+            .lock()
+            .map_err(|_| anyhow!("completion dialect lock poisoned"))?;
+
+        Ok(line
+            .get(..pos)
+            .is_some_and(|before| ends_with_trigger(before, &triggers, dialect)))
+    }
+
+    /// List the completion candidates at pos, for when completion was triggered by typing a
+    /// character. Since this happens while typing, errors are only logged at debug level.
+    /// Returns an error if logging failed.
+    pub async fn list_with_logging(&self, line: &str, pos: usize) -> anyhow::Result<()> {
+        let logger = self.client.get_logger();
+        let completions =
+            tokio::time::timeout(Self::COMPLETION_TIMEOUT, self.complete(line, pos)).await;
+        let candidates = match completions {
+            Ok(Ok((_, candidates))) => candidates,
+            Ok(Err(e)) => return logger.debug(&format!("triggered completion failed: {e}")),
+            Err(_) => return logger.debug("triggered completion timed out"),
+        };
+
+        if candidates.is_empty() {
+            return Ok(());
+        }
+
+        logger.standard(&format_candidate_list(&candidates))
+    }
+
+    /// Perform completion using only results that are ready, i.e. without waiting for the LSP
+    /// server. Returns None if the line hasn't been completed at this position before.
+    pub fn complete_ready(
+        &self,
+        line: &str,
+        pos: usize,
+    ) -> anyhow::Result<Option<(usize, Vec<CandidatePair>)>> {
+        if is_maybe_command(line) {
+            return self.complete_command(line).map(Some);
+        }
+
+        let line = if line.is_empty() { " " } else { line };
+        let server_generation = self.client.get_server_generation();
+        let keyword_case = self.get_settings()?.keyword_case;
+        self.get_cached(server_generation, line, pos)?
+            .map(|candidates| to_completion(line, pos, candidates, keyword_case))
+            .transpose()
+    }
+
+    /// Perform completion without debouncing, logging errors at debug level. Returns no
+    /// candidates if completion takes longer than `COMPLETION_TIMEOUT`.
+    /// Returns an error if logging failed.
+    pub async fn complete_with_timeout(
+        &self,
+        line: &str,
+        pos: usize,
+    ) -> anyhow::Result<(usize, Vec<CandidatePair>)> {
+        let completions =
+            match tokio::time::timeout(Self::COMPLETION_TIMEOUT, self.complete(line, pos)).await {
+                Ok(completions) => completions,
+                Err(_) => {
+                    self.client
+                        .get_logger()
+                        .debug("completion timed out, returning no candidates")?;
+                    return Ok((0, vec![]));
+                }
+            };
+
+        match completions {
+            // Completion happens while typing (e.g. before the LSP server is initialized), so
+            // failures are only logged at debug level
+            Err(e) => {
+                self.client
+                    .get_logger()
+                    .debug(&format!("completion failed: {e}"))?;
+                Ok((0, vec![]))
+            }
+            _ => completions,
+        }
     }
 
-    /// Perform completion, handling errors by logging them.
+    /// Perform completion, logging errors at debug level. Completion is debounced, so if a
+    /// newer call arrives before this one has finished, this one is cancelled and returns no
+    /// candidates.
     /// Returns an error if logging failed.
     pub async fn complete_with_logging(
         &self,
         line: &str,
         pos: usize,
     ) -> anyhow::Result<(usize, Vec<CandidatePair>)> {
-        let completions = self.complete(line, pos).await;
+        let mut current_generation = 0;
+        self.generation.send_modify(|generation| {
+            *generation += 1;
+            current_generation = *generation;
+        });
+        let mut generation_rx = self.generation.subscribe();
+
+        let completions = tokio::select! {
+            _ = generation_rx.wait_for(|generation| *generation != current_generation) => {
+                return Ok((0, vec![]));
+            }
+            completions = self.complete_debounced(line, pos) => completions,
+        };
 
         match completions {
             Err(e) => {
-                self.client.get_logger().error(&e.to_string())?;
+                self.client
+                    .get_logger()
+                    .debug(&format!("completion failed: {e}"))?;
                 Ok((0, vec![]))
             }
             _ => completions,
         }
     }
 
+    /// Wait for the debounce delay before performing completion.
+    async fn complete_debounced(
+        &self,
+        line: &str,
+        pos: usize,
+    ) -> anyhow::Result<(usize, Vec<CandidatePair>)> {
+        let delay = self.get_settings()?.debounce_ms;
+        if delay > 0 && !is_maybe_command(line) {
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+        }
+
+        self.complete(line, pos).await
+    }
+
     /// Perform completion.
     async fn complete(
         &self,
@@ -54,8 +367,22 @@ impl LspCompleter {
             return self.complete_lsp(line, pos).await;
         }
 
-        // Fall back to command
-        self.complete_command(line)
+        self.complete_schema(line, pos).await
+    }
+
+    /// Perform completion using the tables and columns of the connected database, for when no
+    /// LSP server is available.
+    async fn complete_schema(
+        &self,
+        line: &str,
+        pos: usize,
+    ) -> anyhow::Result<(usize, Vec<CandidatePair>)> {
+        let token_start = find_sql_token_start(line, pos);
+        let schema = self.client.get_state().schema.lock().await;
+        let candidates = schema_candidates(&schema, find_qualifier(line, token_start));
+        drop(schema);
+
+        to_completion(line, pos, candidates, self.get_settings()?.keyword_case)
     }
 
     /// Perform completion using LSP.
@@ -67,22 +394,89 @@ impl LspCompleter {
         // Need the line to at least contain an empty character
         let line = if line.is_empty() { " " } else { line };
 
+        let res = self.request_completion_cached(line, pos).await?;
+        to_completion(line, pos, res, self.get_settings()?.keyword_case)
+    }
+
+    /// Request completion from the LSP server, reusing the previous result if the same text and
+    /// position were completed before.
+    async fn request_completion_cached(
+        &self,
+        line: &str,
+        pos: usize,
+    ) -> anyhow::Result<Vec<CompletionCandidate>> {
+        let server_generation = self.client.get_server_generation();
+        if let Some(candidates) = self.get_cached(server_generation, line, pos)? {
+            return Ok(candidates);
+        }
+
+        if let Some(mut done) = self.get_pending(server_generation, line, pos)? {
+            // If the request failed or was cancelled, it's made again below
+            let _ = done.wait_for(|done| *done).await;
+            if let Some(candidates) = self.get_cached(server_generation, line, pos)? {
+                return Ok(candidates);
+            }
+        }
+
+        let (done_tx, done_rx) = watch::channel(false);
+        self.pending
+            .lock()
+            .map_err(|_| anyhow!("pending completion lock poisoned"))?
+            .replace(PendingRequest {
+                server_generation,
+                text: line.to_string(),
+                pos,
+                done: done_rx,
+            });
+
         self.client.on_change(line).await?;
         let (row, col) = row_and_col_from_offset(line, pos).ok_or(anyhow!("pos out of bounds"))?;
-        let res = self
+        let candidates = self
             .client
             .request_completion(row.try_into()?, col.try_into()?)
             .await?;
 
-        let candidates = res
-            .into_iter()
-            .map(|candidate| CandidatePair {
-                display: candidate.clone(),
-                replacement: candidate,
-            })
-            .collect();
+        self.cache
+            .lock()
+            .map_err(|_| anyhow!("completion cache lock poisoned"))?
+            .insert(server_generation, line, pos, candidates.clone());
+        let _ = done_tx.send(true);
+
+        Ok(candidates)
+    }
+
+    fn get_cached(
+        &self,
+        server_generation: u64,
+        line: &str,
+        pos: usize,
+    ) -> anyhow::Result<Option<Vec<CompletionCandidate>>> {
+        Ok(self
+            .cache
+            .lock()
+            .map_err(|_| anyhow!("completion cache lock poisoned"))?
+            .get(server_generation, line, pos))
+    }
 
-        Ok((find_sql_token_start(line, pos), candidates))
+    /// Returns a receiver that is notified when the running request for the line and position
+    /// is done, if there is one.
+    fn get_pending(
+        &self,
+        server_generation: u64,
+        line: &str,
+        pos: usize,
+    ) -> anyhow::Result<Option<watch::Receiver<bool>>> {
+        Ok(self
+            .pending
+            .lock()
+            .map_err(|_| anyhow!("pending completion lock poisoned"))?
+            .as_ref()
+            .filter(|pending| {
+                pending.server_generation == server_generation
+                    && pending.text == line
+                    && pending.pos == pos
+            })
+            .map(|pending| pending.done.clone()))
     }
 
     /// Perform command completion.
@@ -98,6 +492,7 @@ impl LspCompleter {
                 CandidatePair {
                     display: full_cmd.to_string(),
                     replacement: full_cmd.to_string(),
+                    detail: None,
                 }
             })
             .collect::<Vec<_>>();
@@ -106,17 +501,55 @@ impl LspCompleter {
             return Ok((0, matching));
         }
 
-        let delete_prefix = concat!(command::command_prefix!(), "delete ");
-        if let Some(arg) = line.strip_prefix(delete_prefix) {
-            return self.complete_connection_names(arg, delete_prefix.len());
+        self.complete_command_arg(line)
+    }
+
+    /// Complete the first argument of a command, using the completion registered for it in
+    /// `command::COMMANDS`.
+    fn complete_command_arg(&self, line: &str) -> anyhow::Result<(usize, Vec<CandidatePair>)> {
+        let Some((name, arg)) = line
+            .strip_prefix(command::command_prefix!())
+            .and_then(|rest| rest.split_once(' '))
+        else {
+            return Ok((0, vec![]));
+        };
+
+        // Aliases and prefixes of commands complete the arguments of the command as well
+        let completion = command::resolve_command(name)
+            .ok()
+            .and_then(|name| command::COMMANDS.get(name))
+            .and_then(|cmd| cmd.arg_completion);
+
+        // The value of a setting is completed as well
+        if completion == Some(ArgCompletion::Settings) {
+            return Ok(complete_setting(line, arg));
         }
 
-        let use_prefix = concat!(command::command_prefix!(), "use ");
-        if let Some(arg) = line.strip_prefix(use_prefix) {
-            return self.complete_connection_names(arg, use_prefix.len());
+        // Only the first argument is completed
+        if arg.contains(char::is_whitespace) {
+            return Ok((0, vec![]));
         }
 
-        Ok((0, vec![]))
+        let offset = line.len() - arg.len();
+        match completion {
+            Some(ArgCompletion::ConnectionNames) => self.complete_connection_names(arg, offset),
+            Some(ArgCompletion::Profiles) => {
+                let profiles = config::list_profiles()?;
+                let profiles = profiles.iter().map(|p| p.as_str()).collect::<Vec<_>>();
+                Ok((offset, complete_values(arg, &profiles)))
+            }
+            Some(ArgCompletion::Snippets) => {
+                let config = get_config()?;
+                let names = config
+                    .get_snippets()
+                    .keys()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>();
+                Ok((offset, complete_values(arg, &names)))
+            }
+            Some(ArgCompletion::Values(values)) => Ok((offset, complete_values(arg, values))),
+            Some(ArgCompletion::Settings) | None => Ok((0, vec![])),
+        }
     }
 
     fn complete_connection_names(
@@ -133,35 +566,281 @@ impl LspCompleter {
             .map(|conn| CandidatePair {
                 display: format!("{}: {:?}", conn.name, conn.settings),
                 replacement: conn.name.clone(),
+                detail: None,
             });
 
         Ok((offset, matching.collect()))
     }
 }
 
-/// Find the beginning of the token at pos (even if the word only consists of an
-/// empty string).
-fn find_sql_token_start(line: &str, pos: usize) -> usize {
-    for (i, c) in line
-        .chars()
-        .take(pos)
-        .enumerate()
-        .collect::<Vec<_>>()
+/// Filter, sort and format the candidates returned by the LSP server for the token at pos.
+fn to_completion(
+    line: &str,
+    pos: usize,
+    candidates: Vec<CompletionCandidate>,
+    keyword_case: KeywordCase,
+) -> anyhow::Result<(usize, Vec<CandidatePair>)> {
+    let token_start =
+        edit_start(line, pos, &candidates).unwrap_or_else(|| find_sql_token_start(line, pos));
+    let prefix = line.get(token_start..pos).unwrap_or("");
+    let candidates = filter_and_sort_candidates(candidates, prefix);
+    let candidates = to_candidate_pairs(apply_keyword_case(candidates, prefix, keyword_case));
+
+    Ok((token_start, candidates))
+}
+
+/// Get the byte index where the replacement of the candidates starts, if the server gave one
+/// (using the first candidate with a text edit) and it's before pos.
+fn edit_start(line: &str, pos: usize, candidates: &[CompletionCandidate]) -> Option<usize> {
+    let start = candidates
+        .iter()
+        .find_map(|candidate| candidate.text_edit.as_ref())?
+        .range
+        .start;
+    let start = offset_from_row_and_col(
+        line,
+        start.line.try_into().ok()?,
+        start.character.try_into().ok()?,
+    );
+
+    (start <= pos).then_some(start)
+}
+
+/// Complete arg using a fixed set of values.
+fn complete_values(arg: &str, values: &[&str]) -> Vec<CandidatePair> {
+    values
+        .iter()
+        .filter(|value| value.starts_with(arg))
+        .map(|value| CandidatePair {
+            display: value.to_string(),
+            replacement: value.to_string(),
+            detail: None,
+        })
+        .collect()
+}
+
+/// Complete the arguments of `/set` (args): the name of a setting, or its value if the setting has
+/// been given.
+fn complete_setting(line: &str, args: &str) -> (usize, Vec<CandidatePair>) {
+    match args.split_once(' ') {
+        None => {
+            let settings = command::SETTINGS.keys().copied().collect::<Vec<_>>();
+            (line.len() - args.len(), complete_values(args, &settings))
+        }
+        Some((setting, value)) if !value.contains(char::is_whitespace) => {
+            let values = command::setting_values(setting)
+                .unwrap_or_default()
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            (line.len() - value.len(), complete_values(value, &values))
+        }
+        Some(_) => (0, vec![]),
+    }
+}
+
+/// Get the candidates for a token in the schema. Only the columns of the table are candidates if
+/// the token is qualified by a table name (`table.`), and otherwise all tables and columns are.
+fn schema_candidates(schema: &Schema, qualifier: Option<&str>) -> Vec<CompletionCandidate> {
+    let candidate = |label: &str, detail: &str| CompletionCandidate {
+        label: label.to_string(),
+        detail: Some(detail.to_string()),
+        sort_text: None,
+        is_keyword: false,
+        text_edit: None,
+    };
+
+    if let Some(table) = qualifier {
+        return schema
+            .columns(table)
+            .unwrap_or_default()
+            .iter()
+            .map(|column| candidate(column, "column"))
+            .collect();
+    }
+
+    schema
+        .tables()
+        .map(|table| candidate(table, "table"))
+        .chain(
+            schema
+                .all_columns()
+                .into_iter()
+                .map(|column| candidate(column, "column")),
+        )
+        .collect()
+}
+
+/// Find the identifier qualifying the token starting at token_start (a byte index), e.g. `users`
+/// in `users.na`. Returns None if the token isn't qualified.
+fn find_qualifier(line: &str, token_start: usize) -> Option<&str> {
+    let dot = token_start.checked_sub(1)?;
+    if line.get(dot..token_start)? != "." {
+        return None;
+    }
+
+    let qualifier_start = find_sql_token_start(line, dot);
+    line.get(qualifier_start..dot)
+        .filter(|qualifier| !qualifier.is_empty())
+}
+
+/// Keep the candidates starting with prefix (case-insensitive), sorted by their sort text if
+/// present and otherwise alphabetically.
+fn filter_and_sort_candidates(
+    candidates: Vec<CompletionCandidate>,
+    prefix: &str,
+) -> Vec<CompletionCandidate> {
+    let prefix = prefix.to_lowercase();
+    let mut candidates = candidates
         .into_iter()
-        .rev()
-    {
-        // Space or . to separate schema from identifier
-        if c.is_whitespace() || c == '.' {
-            return i + 1;
+        .filter(|candidate| candidate.insert_text().to_lowercase().starts_with(&prefix))
+        .collect::<Vec<_>>();
+
+    candidates.sort_by_cached_key(|candidate| {
+        let sort_text = candidate
+            .sort_text
+            .clone()
+            .unwrap_or_else(|| candidate.label.to_lowercase());
+        (sort_text, candidate.label.clone())
+    });
+
+    candidates
+}
+
+/// Change the case of keyword candidates. With `KeywordCase::Match`, keywords are uppercased if
+/// the typed prefix is all uppercase and lowercased if it is all lowercase.
+fn apply_keyword_case(
+    candidates: Vec<CompletionCandidate>,
+    prefix: &str,
+    keyword_case: KeywordCase,
+) -> Vec<CompletionCandidate> {
+    let keyword_case = match keyword_case {
+        KeywordCase::Match => {
+            if !prefix.chars().any(char::is_alphabetic) {
+                KeywordCase::Preserve
+            } else if prefix.chars().all(|c| !c.is_lowercase()) {
+                KeywordCase::Upper
+            } else if prefix.chars().all(|c| !c.is_uppercase()) {
+                KeywordCase::Lower
+            } else {
+                KeywordCase::Preserve
+            }
         }
+        keyword_case => keyword_case,
+    };
+
+    candidates
+        .into_iter()
+        .map(|mut candidate| {
+            if candidate.is_keyword {
+                let apply = |text: String| match keyword_case {
+                    KeywordCase::Upper => text.to_uppercase(),
+                    KeywordCase::Lower => text.to_lowercase(),
+                    KeywordCase::Preserve | KeywordCase::Match => text,
+                };
+                candidate.label = apply(candidate.label);
+                if let Some(text_edit) = candidate.text_edit.as_mut() {
+                    text_edit.new_text = apply(std::mem::take(&mut text_edit.new_text));
+                }
+            }
+            candidate
+        })
+        .collect()
+}
+
+/// Convert LSP completion candidates to candidate pairs. The detail of each candidate is shown
+/// after its label, with the details aligned in a column.
+fn to_candidate_pairs(candidates: Vec<CompletionCandidate>) -> Vec<CandidatePair> {
+    let label_width = candidates
+        .iter()
+        .filter(|candidate| candidate.detail.is_some())
+        .map(|candidate| candidate.label.width())
+        .max()
+        .unwrap_or(0);
+
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            let display = match &candidate.detail {
+                Some(detail) => {
+                    let padding = label_width - candidate.label.width();
+                    format!("{}{}    {detail}", candidate.label, " ".repeat(padding))
+                }
+                None => candidate.label.clone(),
+            };
+
+            CandidatePair {
+                display,
+                replacement: candidate.insert_text().to_string(),
+                detail: candidate.detail,
+            }
+        })
+        .collect()
+}
+
+/// Returns true if the text ends with one of the triggers outside of literals and comments. A
+/// whitespace trigger only counts after one of `TRIGGER_KEYWORDS`.
+fn ends_with_trigger(text: &str, triggers: &[String], dialect: Dialect) -> bool {
+    if !sql::scan(text, dialect)
+        .last()
+        .is_some_and(|segment| segment.kind == SegmentKind::Code)
+    {
+        return false;
+    }
+
+    triggers
+        .iter()
+        .filter(|trigger| !trigger.is_empty() && text.ends_with(trigger.as_str()))
+        .any(|trigger| {
+            if !trigger.chars().all(char::is_whitespace) {
+                return true;
+            }
+
+            let before = &text[..text.len() - trigger.len()];
+            let keyword = &before[find_sql_token_start(before, before.len())..];
+            TRIGGER_KEYWORDS
+                .iter()
+                .any(|trigger_keyword| trigger_keyword.eq_ignore_ascii_case(keyword))
+        })
+}
+
+/// Format the candidates on a single line, listing at most `MAX_TRIGGERED_CANDIDATES` of them.
+fn format_candidate_list(candidates: &[CandidatePair]) -> String {
+    let max = LspCompleter::MAX_TRIGGERED_CANDIDATES;
+    let listed = candidates
+        .iter()
+        .take(max)
+        .map(|candidate| candidate.replacement.as_str())
+        .collect::<Vec<_>>()
+        .join("  ");
+
+    if candidates.len() > max {
+        format!("{listed}  (and {} more)", candidates.len() - max)
+    } else {
+        listed
     }
+}
+
+/// Find the byte index of the beginning of the token at pos (a byte index), even if the word only
+/// consists of an empty string.
+fn find_sql_token_start(line: &str, pos: usize) -> usize {
+    let Some(before) = line.get(..pos) else {
+        return 0;
+    };
 
-    0
+    before
+        .char_indices()
+        .rev()
+        // Space or . to separate schema from identifier
+        .find(|(_, c)| c.is_whitespace() || *c == '.')
+        .map(|(i, c)| i + c.len_utf8())
+        .unwrap_or(0)
 }
 
-/// Compute the row and col based on the byte index of text.
-fn row_and_col_from_offset(text: &str, offset: usize) -> Option<(usize, usize)> {
-    if offset > text.len() {
+/// Compute the LSP position (row and UTF-16 column) of the byte index offset in text. Returns
+/// None if offset is out of bounds or not at a char boundary.
+pub(crate) fn row_and_col_from_offset(text: &str, offset: usize) -> Option<(usize, usize)> {
+    if offset > text.len() || !text.is_char_boundary(offset) {
         return None;
     }
 
@@ -172,7 +851,8 @@ fn row_and_col_from_offset(text: &str, offset: usize) -> Option<(usize, usize)>
     for (line_index, line) in text.lines().enumerate() {
         let line_end = line_start + line.len();
         if offset <= line_end {
-            return Some((line_index, offset - line_start));
+            let col = line[..offset - line_start].encode_utf16().count();
+            return Some((line_index, col));
         }
 
         line_start = line_end + line_ending_len
@@ -181,9 +861,37 @@ fn row_and_col_from_offset(text: &str, offset: usize) -> Option<(usize, usize)>
     None
 }
 
+/// Compute the byte index of text based on the LSP position (row and UTF-16 column). Positions
+/// past the end of a line or past the last line are clamped to the end of the line or text
+/// respectively. Columns in the middle of a char are rounded down to the start of the char.
+pub(crate) fn offset_from_row_and_col(text: &str, row: usize, col: usize) -> usize {
+    // Assuming that all line endings are the same
+    let line_ending_len = if text.contains("\r\n") { "\r\n" } else { "\n" }.len();
+
+    let mut line_start = 0;
+    for (line_index, line) in text.lines().enumerate() {
+        if line_index == row {
+            let mut utf16_col = 0;
+            for (byte_col, c) in line.char_indices() {
+                utf16_col += c.len_utf16();
+                if utf16_col > col {
+                    return line_start + byte_col;
+                }
+            }
+
+            return line_start + line.len();
+        }
+
+        line_start += line.len() + line_ending_len;
+    }
+
+    text.len()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lsp::client::CompletionEdit;
 
     #[test]
     fn can_find_word_start_with_empty_line() {
@@ -205,6 +913,280 @@ mod tests {
         assert_eq!(find_sql_token_start("public.", 7), 7);
     }
 
+    fn candidate(
+        label: &str,
+        detail: Option<&str>,
+        sort_text: Option<&str>,
+    ) -> CompletionCandidate {
+        CompletionCandidate {
+            label: label.to_string(),
+            detail: detail.map(|detail| detail.to_string()),
+            sort_text: sort_text.map(|sort_text| sort_text.to_string()),
+            is_keyword: false,
+            text_edit: None,
+        }
+    }
+
+    fn keyword(label: &str) -> CompletionCandidate {
+        CompletionCandidate {
+            is_keyword: true,
+            ..candidate(label, None, None)
+        }
+    }
+
+    fn edited(label: &str, start: u32, end: u32, new_text: &str) -> CompletionCandidate {
+        CompletionCandidate {
+            text_edit: Some(CompletionEdit {
+                range: lsp_types::Range::new(
+                    lsp_types::Position::new(0, start),
+                    lsp_types::Position::new(0, end),
+                ),
+                new_text: new_text.to_string(),
+            }),
+            ..candidate(label, None, None)
+        }
+    }
+
+    fn labels(candidates: &[CompletionCandidate]) -> Vec<&str> {
+        candidates
+            .iter()
+            .map(|candidate| candidate.label.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn can_filter_candidates_by_prefix() {
+        let candidates = vec![
+            candidate("users", None, None),
+            candidate("UPDATE", None, None),
+            candidate("orders", None, None),
+        ];
+
+        let filtered = filter_and_sort_candidates(candidates.clone(), "u");
+        assert_eq!(labels(&filtered), vec!["UPDATE", "users"]);
+
+        let filtered = filter_and_sort_candidates(candidates.clone(), "US");
+        assert_eq!(labels(&filtered), vec!["users"]);
+
+        let filtered = filter_and_sort_candidates(candidates, "");
+        assert_eq!(labels(&filtered), vec!["orders", "UPDATE", "users"]);
+    }
+
+    #[test]
+    fn can_sort_candidates_by_sort_text() {
+        let candidates = vec![
+            candidate("alpha", None, Some("2")),
+            candidate("beta", None, Some("1")),
+            candidate("gamma", None, Some("1")),
+        ];
+
+        let sorted = filter_and_sort_candidates(candidates, "");
+        assert_eq!(labels(&sorted), vec!["beta", "gamma", "alpha"]);
+    }
+
+    #[test]
+    fn can_complete_command_arg_values() {
+        let pairs = complete_values("sq", &["sqls", "sqlls", "pgtools"]);
+        let replacements = pairs
+            .iter()
+            .map(|p| p.replacement.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(replacements, vec!["sqls", "sqlls"]);
+    }
+
+    #[test]
+    fn can_complete_settings() {
+        let replacements = |(offset, pairs): (usize, Vec<CandidatePair>)| {
+            let replacements = pairs.into_iter().map(|p| p.replacement).collect::<Vec<_>>();
+            (offset, replacements)
+        };
+
+        assert_eq!(
+            replacements(complete_setting("/set fo", "fo")),
+            (5, vec!["footer".to_string(), "format".to_string()])
+        );
+        assert_eq!(
+            replacements(complete_setting("/set format c", "format c")),
+            (12, vec!["csv".to_string()])
+        );
+        assert_eq!(
+            replacements(complete_setting("/set header_types ", "header_types ")),
+            (18, vec!["on".to_string(), "off".to_string()])
+        );
+        assert!(complete_setting("/set colour r", "colour r").1.is_empty());
+    }
+
+    #[test]
+    fn can_apply_keyword_case() {
+        let candidates = vec![keyword("SELECT"), candidate("Sales", None, None)];
+
+        let upper = apply_keyword_case(candidates.clone(), "se", KeywordCase::Upper);
+        assert_eq!(labels(&upper), vec!["SELECT", "Sales"]);
+
+        let lower = apply_keyword_case(candidates.clone(), "SE", KeywordCase::Lower);
+        assert_eq!(labels(&lower), vec!["select", "Sales"]);
+
+        let preserve = apply_keyword_case(candidates.clone(), "se", KeywordCase::Preserve);
+        assert_eq!(labels(&preserve), vec!["SELECT", "Sales"]);
+    }
+
+    #[test]
+    fn can_match_keyword_case_of_prefix() {
+        let candidates = vec![keyword("Select"), candidate("sales", None, None)];
+
+        let lower = apply_keyword_case(candidates.clone(), "sel", KeywordCase::Match);
+        assert_eq!(labels(&lower), vec!["select", "sales"]);
+
+        let upper = apply_keyword_case(candidates.clone(), "SEL", KeywordCase::Match);
+        assert_eq!(labels(&upper), vec!["SELECT", "sales"]);
+
+        let mixed = apply_keyword_case(candidates.clone(), "Sel", KeywordCase::Match);
+        assert_eq!(labels(&mixed), vec!["Select", "sales"]);
+
+        let empty = apply_keyword_case(candidates, "", KeywordCase::Match);
+        assert_eq!(labels(&empty), vec!["Select", "sales"]);
+    }
+
+    #[test]
+    fn can_find_qualifier() {
+        assert_eq!(find_qualifier("SELECT users.na", 13), Some("users"));
+        assert_eq!(find_qualifier("SELECT public.users.", 20), Some("users"));
+        assert_eq!(find_qualifier("SELECT na", 7), None);
+        assert_eq!(find_qualifier(".na", 1), None);
+    }
+
+    #[test]
+    fn can_complete_from_schema() {
+        let schema = Schema::from_columns(
+            [("users", "id"), ("users", "name"), ("orders", "id")]
+                .map(|(table, column)| (table.to_string(), column.to_string())),
+        );
+
+        let all = schema_candidates(&schema, None);
+        assert_eq!(labels(&all), vec!["orders", "users", "id", "name"]);
+
+        let qualified = schema_candidates(&schema, Some("Users"));
+        assert_eq!(labels(&qualified), vec!["id", "name"]);
+
+        assert!(schema_candidates(&schema, Some("missing")).is_empty());
+    }
+
+    #[test]
+    fn can_cache_completions() {
+        let mut cache = CompletionCache::default();
+        cache.insert(0, "SELECT ", 7, vec![candidate("id", None, None)]);
+
+        assert_eq!(
+            cache.get(0, "SELECT ", 7),
+            Some(vec![candidate("id", None, None)])
+        );
+        assert_eq!(cache.get(0, "SELECT ", 6), None);
+        assert_eq!(cache.get(0, "SELECT 1", 7), None);
+
+        // A new server invalidates everything
+        assert_eq!(cache.get(1, "SELECT ", 7), None);
+    }
+
+    #[test]
+    fn can_bound_completion_cache() {
+        let mut cache = CompletionCache::default();
+        for pos in 0..CompletionCache::MAX_ENTRIES + 1 {
+            cache.insert(0, "SELECT ", pos, vec![]);
+        }
+
+        assert_eq!(cache.entries.len(), CompletionCache::MAX_ENTRIES);
+        assert_eq!(cache.get(0, "SELECT ", 0), None);
+        assert!(cache.get(0, "SELECT ", 1).is_some());
+    }
+
+    #[test]
+    fn can_show_detail_in_display() {
+        let pairs = to_candidate_pairs(vec![
+            candidate("id", Some("integer"), None),
+            candidate("name", Some("text"), None),
+            candidate("SELECT", None, None),
+        ]);
+
+        let displays = pairs.iter().map(|p| p.display.as_str()).collect::<Vec<_>>();
+        assert_eq!(displays, vec!["id      integer", "name    text", "SELECT"]);
+
+        let replacements = pairs
+            .iter()
+            .map(|p| p.replacement.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(replacements, vec!["id", "name", "SELECT"]);
+    }
+
+    #[test]
+    fn can_detect_completion_triggers() {
+        let triggers = vec![".".to_string(), " ".to_string()];
+        assert!(ends_with_trigger(
+            "SELECT * FROM users.",
+            &triggers,
+            Dialect::Standard
+        ));
+        assert!(ends_with_trigger(
+            "SELECT * from ",
+            &triggers,
+            Dialect::Standard
+        ));
+        assert!(ends_with_trigger("SELECT ", &triggers, Dialect::Standard));
+        // Whitespace after something other than a keyword
+        assert!(!ends_with_trigger(
+            "SELECT id ",
+            &triggers,
+            Dialect::Standard
+        ));
+        // Inside a literal or comment
+        assert!(!ends_with_trigger(
+            "SELECT 'a.",
+            &triggers,
+            Dialect::Standard
+        ));
+        assert!(!ends_with_trigger(
+            "SELECT 1 -- users.",
+            &triggers,
+            Dialect::Standard
+        ));
+        assert!(!ends_with_trigger(
+            "SELECT * FROM users.",
+            &[],
+            Dialect::Standard
+        ));
+    }
+
+    #[test]
+    fn can_find_word_start_after_multibyte_chars() {
+        assert_eq!(find_sql_token_start("SELECT café", 12), 7);
+        assert_eq!(find_sql_token_start("SELECT é.nom", 13), 10);
+        assert_eq!(find_sql_token_start("SELECT\u{3000}x", 10), 9);
+    }
+
+    #[test]
+    fn can_compute_utf16_col_with_multibyte_chars() {
+        // é is 2 bytes but a single UTF-16 code unit
+        assert_eq!(row_and_col_from_offset("SELECT café", 12), Some((0, 11)));
+        // 😀 is 4 bytes and two UTF-16 code units
+        assert_eq!(
+            row_and_col_from_offset("SELECT '😀'\nfoo", 12),
+            Some((0, 10))
+        );
+        assert_eq!(
+            row_and_col_from_offset("SELECT '😀'\nfoo", 14),
+            Some((1, 0))
+        );
+        // Not at a char boundary
+        assert_eq!(row_and_col_from_offset("café", 4), None);
+    }
+
+    #[test]
+    fn can_compute_offset_from_utf16_col_with_multibyte_chars() {
+        assert_eq!(offset_from_row_and_col("SELECT café", 0, 11), 12);
+        assert_eq!(offset_from_row_and_col("SELECT '😀'\nfoo", 0, 10), 12);
+        assert_eq!(offset_from_row_and_col("SELECT '😀'\nfoo", 0, 9), 8);
+        assert_eq!(offset_from_row_and_col("é\nfoo", 1, 1), 4);
+    }
+
     #[test]
     fn can_compute_row_and_col_with_lf() {
         assert_eq!(row_and_col_from_offset("foo\nbar\nbaz", 4), Some((1, 0)));
@@ -228,4 +1210,40 @@ mod tests {
         );
         assert_eq!(row_and_col_from_offset("foo\r\nbar\r\nbaz", 30), None);
     }
+
+    #[test]
+    fn can_compute_offset_from_row_and_col() {
+        assert_eq!(offset_from_row_and_col("foo\nbar\nbaz", 1, 0), 4);
+        assert_eq!(offset_from_row_and_col("foo\r\nbar\r\nbaz", 2, 2), 12);
+        assert_eq!(offset_from_row_and_col("foo\nbar", 0, 10), 3);
+        assert_eq!(offset_from_row_and_col("foo\nbar\n", 2, 0), 8);
+        assert_eq!(offset_from_row_and_col("foo\nbar", 7, 7), 7);
+    }
+
+    #[test]
+    fn can_find_start_of_text_edit() {
+        let line = "SELECT u.na FROM users u";
+        let candidates = vec![
+            candidate("users", None, None),
+            edited("name", 7, 11, "u.name"),
+        ];
+        assert_eq!(edit_start(line, 11, &candidates), Some(7));
+
+        // Without a text edit, the token start is used instead
+        assert_eq!(edit_start(line, 11, &[candidate("name", None, None)]), None);
+
+        // Edits starting after the cursor are ignored
+        assert_eq!(edit_start(line, 5, &candidates), None);
+    }
+
+    #[test]
+    fn can_replace_with_text_edit() {
+        let candidates = vec![edited("name", 7, 11, "u.name"), candidate("id", None, None)];
+        let candidates = filter_and_sort_candidates(candidates, "u.na");
+        assert_eq!(labels(&candidates), vec!["name"]);
+
+        let pairs = to_candidate_pairs(candidates);
+        assert_eq!(pairs[0].display, "name");
+        assert_eq!(pairs[0].replacement, "u.name");
+    }
 }
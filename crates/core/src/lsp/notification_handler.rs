@@ -1,11 +1,14 @@
 use std::future::Future;
 
-use ariadne::{Label, Report, ReportKind, Source};
+use ariadne::{Color, ReportKind};
 use jsonrpsee_types::Notification;
-use lsp_types::{Diagnostic, DiagnosticSeverity, PublishDiagnosticsParams};
-use tokio::sync::broadcast;
+use lsp_types::{Diagnostic, DiagnosticSeverity, NumberOrString, PublishDiagnosticsParams};
+use tokio::sync::broadcast::{self, error::RecvError};
 
-use crate::{logging::Logger, state::State};
+use crate::{config::DiagnosticLevel, logging::Logger, output::report, state::State};
+
+/// Report kind of informational diagnostics, which are less severe than warnings but not hints.
+const INFO_KIND: ReportKind = ReportKind::Custom("Info", Color::Blue);
 
 pub enum HandlerType {
     Logger,
@@ -38,14 +41,24 @@ impl NotificationHandler {
     /// Forward notifications to logger.
     async fn init_logger(mut self) -> anyhow::Result<()> {
         loop {
-            let body = self.notif_rx.recv().await?;
+            let body = match self.notif_rx.recv().await {
+                Ok(body) => body,
+                Err(RecvError::Lagged(skipped)) => {
+                    self.logger.debug(&format!(
+                        "dropped {skipped} notifications from the LSP server"
+                    ))?;
+                    continue;
+                }
+                Err(e) => return Err(e.into()),
+            };
 
             // TODO: Improve this logic to handle different types of notifications
             if let Ok(notification) =
                 serde_json::from_slice::<Notification<PublishDiagnosticsParams>>(&body)
             {
+                let min_severity = min_severity(*self.state.diag_level.lock().await);
                 let text = self.state.lsp_text.lock().await;
-                let msg = handle_diagnostics(&text, &notification)?;
+                let msg = handle_diagnostics(&text, &notification, min_severity)?;
                 if !msg.is_empty() {
                     self.logger.standard(&msg)?;
                 }
@@ -59,56 +72,115 @@ impl NotificationHandler {
     }
 }
 
+/// Render the diagnostics of the notification that are at least as severe as min_severity as a
+/// single report on the text, with a label for each diagnostic. The report is headed by the most
+/// severe diagnostic.
 fn handle_diagnostics(
     text: &str,
     notification: &Notification<PublishDiagnosticsParams>,
+    min_severity: DiagnosticSeverity,
 ) -> anyhow::Result<String> {
-    let report = notification
-        .params
-        .diagnostics
+    let mut diagnostics: Vec<&Diagnostic> = vec![];
+    for diagnostic in &notification.params.diagnostics {
+        // Some servers report the same diagnostic more than once, e.g. once per analyzer pass
+        let duplicate = diagnostics.iter().any(|shown| {
+            shown.range == diagnostic.range
+                && shown.message == diagnostic.message
+                && severity(shown) == severity(diagnostic)
+        });
+        // More severe diagnostics have lower values
+        if severity(diagnostic) <= min_severity && !duplicate {
+            diagnostics.push(diagnostic);
+        }
+    }
+    let Some(severity) = diagnostics
         .iter()
-        .map(|diagnostic| format_diagnostic(text, diagnostic))
-        .collect::<anyhow::Result<Vec<_>>>()?
-        .join("\n");
+        .map(|diagnostic| severity(diagnostic))
+        .min()
+    else {
+        return Ok(String::new());
+    };
+
+    let labels = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic_label(text, diagnostic))
+        .collect::<anyhow::Result<Vec<_>>>()?;
 
-    Ok(report)
+    report::render_labels(report_kind(severity), text, &labels)
 }
 
-fn format_diagnostic(text: &str, diagnostic: &Diagnostic) -> anyhow::Result<String> {
-    let report_kind = if let Some(severity) = diagnostic.severity {
-        match severity {
-            DiagnosticSeverity::ERROR => ReportKind::Error,
-            DiagnosticSeverity::WARNING | DiagnosticSeverity::INFORMATION => ReportKind::Warning,
-            DiagnosticSeverity::HINT => ReportKind::Advice,
-            _ => unreachable!(),
-        }
-    } else {
-        ReportKind::Warning
-    };
+/// Least severe diagnostic severity shown at the level.
+fn min_severity(level: DiagnosticLevel) -> DiagnosticSeverity {
+    match level {
+        DiagnosticLevel::Error => DiagnosticSeverity::ERROR,
+        DiagnosticLevel::Warning => DiagnosticSeverity::WARNING,
+        DiagnosticLevel::Info => DiagnosticSeverity::INFORMATION,
+        DiagnosticLevel::Hint => DiagnosticSeverity::HINT,
+    }
+}
+
+/// Severity of the diagnostic. Diagnostics without one, or with a severity that isn't in the spec,
+/// are shown as warnings.
+fn severity(diagnostic: &Diagnostic) -> DiagnosticSeverity {
+    diagnostic
+        .severity
+        .filter(|severity| {
+            (DiagnosticSeverity::ERROR..=DiagnosticSeverity::HINT).contains(severity)
+        })
+        .unwrap_or(DiagnosticSeverity::WARNING)
+}
+
+fn report_kind(severity: DiagnosticSeverity) -> ReportKind<'static> {
+    match severity {
+        DiagnosticSeverity::ERROR => ReportKind::Error,
+        DiagnosticSeverity::INFORMATION => INFO_KIND,
+        DiagnosticSeverity::HINT => ReportKind::Advice,
+        _ => ReportKind::Warning,
+    }
+}
 
+fn diagnostic_label(text: &str, diagnostic: &Diagnostic) -> anyhow::Result<report::ReportLabel> {
     let start_line: usize = diagnostic.range.start.line.try_into()?;
     let start_offset: usize = diagnostic.range.start.character.try_into()?;
     let end_line: usize = diagnostic.range.end.line.try_into()?;
     let end_offset: usize = diagnostic.range.end.character.try_into()?;
     let start_index = compute_byte_offset(text, start_line, start_offset);
-    let end_index = compute_byte_offset(text, end_line, end_offset);
-
-    let mut buffer = vec![];
-    const SOURCE_ID: &str = "query";
-    Report::build(report_kind, (SOURCE_ID, 0..text.len()))
-        .with_label(
-            Label::new((SOURCE_ID, start_index..end_index))
-                .with_message(diagnostic.message.to_string()),
-        )
-        .finish()
-        .write((SOURCE_ID, Source::from(text)), &mut buffer)?;
-
-    let str = String::from_utf8(buffer)?;
-    Ok(str)
+    let end_index = compute_end_byte_offset(text, end_line, end_offset).max(start_index);
+
+    Ok(report::ReportLabel {
+        kind: report_kind(severity(diagnostic)),
+        range: start_index..end_index,
+        message: label_message(diagnostic),
+    })
+}
+
+/// Message of the diagnostic followed by its source and code, if any, e.g. `unknown table (sqls
+/// E42)`.
+fn label_message(diagnostic: &Diagnostic) -> String {
+    let code = diagnostic.code.as_ref().map(|code| match code {
+        NumberOrString::Number(number) => number.to_string(),
+        NumberOrString::String(string) => string.clone(),
+    });
+
+    let origin = [diagnostic.source.clone(), code]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    if origin.is_empty() {
+        diagnostic.message.clone()
+    } else {
+        format!("{} ({origin})", diagnostic.message)
+    }
 }
 
 /// Get byte offset of the given row and col in text. All values are zero-indexed.
 fn compute_byte_offset(text: &str, row: usize, col: usize) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+
     // Assuming that all line endings are the same
     let line_ending_len = if text.contains("\r\n") { "\r\n" } else { "\n" }.len();
 
@@ -121,7 +193,7 @@ fn compute_byte_offset(text: &str, row: usize, col: usize) -> usize {
                 // If the offset extends outside the line, make it the last character
                 // (zero-indexed) instead.
                 if col >= line.len() {
-                    return acc + line.len() - 1;
+                    return acc + line.len().saturating_sub(1);
                 } else {
                     return acc + col;
                 }
@@ -134,6 +206,18 @@ fn compute_byte_offset(text: &str, row: usize, col: usize) -> usize {
     offset.min(text.len() - 1)
 }
 
+/// Get the byte offset of the exclusive end of a range ending at the given row and col. Unlike
+/// compute_byte_offset, the end of the text is a valid offset, so that a range ending there
+/// includes the last character.
+fn compute_end_byte_offset(text: &str, row: usize, col: usize) -> usize {
+    if col == 0 {
+        return compute_byte_offset(text, row, col);
+    }
+
+    // The character before the end is the last one included in the range
+    (compute_byte_offset(text, row, col - 1) + 1).min(text.len())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -151,4 +235,134 @@ mod tests {
         assert_eq!(compute_byte_offset("foo\r\nbar\r\nbaz", 2, 2), 12);
         assert_eq!(compute_byte_offset("foo\r\nbar\r\nbaz", 7, 7), 12);
     }
+
+    #[test]
+    fn can_compute_byte_offset_with_empty_text() {
+        assert_eq!(compute_byte_offset("", 0, 0), 0);
+        assert_eq!(compute_byte_offset("", 3, 3), 0);
+        assert_eq!(compute_end_byte_offset("", 0, 5), 0);
+    }
+
+    #[test]
+    fn can_compute_byte_offset_on_empty_line() {
+        assert_eq!(compute_byte_offset("foo\n\nbar", 1, 0), 4);
+    }
+
+    #[test]
+    fn can_compute_end_byte_offset_at_end_of_text() {
+        assert_eq!(compute_end_byte_offset("SELECT", 0, 6), 6);
+        assert_eq!(compute_end_byte_offset("SELECT", 0, 9), 6);
+        assert_eq!(compute_end_byte_offset("foo\nbar", 0, 3), 3);
+        assert_eq!(compute_end_byte_offset("foo\nbar", 1, 0), 4);
+    }
+
+    #[test]
+    fn can_add_source_and_code_to_label() {
+        let mut diagnostic = Diagnostic {
+            message: "unknown table".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(label_message(&diagnostic), "unknown table");
+
+        diagnostic.source = Some("sqls".to_string());
+        assert_eq!(label_message(&diagnostic), "unknown table (sqls)");
+
+        diagnostic.code = Some(NumberOrString::Number(42));
+        assert_eq!(label_message(&diagnostic), "unknown table (sqls 42)");
+
+        diagnostic.source = None;
+        diagnostic.code = Some(NumberOrString::String("E42".to_string()));
+        assert_eq!(label_message(&diagnostic), "unknown table (E42)");
+    }
+
+    fn diagnostic(severity: DiagnosticSeverity, start: u32, end: u32, message: &str) -> Diagnostic {
+        Diagnostic {
+            range: lsp_types::Range::new(
+                lsp_types::Position::new(0, start),
+                lsp_types::Position::new(0, end),
+            ),
+            severity: Some(severity),
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn notification(
+        diagnostics: Vec<Diagnostic>,
+    ) -> Notification<'static, PublishDiagnosticsParams> {
+        let params = PublishDiagnosticsParams {
+            uri: "file:///query.sql".parse().unwrap(),
+            diagnostics,
+            version: None,
+        };
+        Notification::new("textDocument/publishDiagnostics".into(), params)
+    }
+
+    #[test]
+    fn can_combine_diagnostics_into_one_report() {
+        let text = "SELEC * FORM t";
+        let notification = notification(vec![
+            diagnostic(DiagnosticSeverity::WARNING, 0, 5, "unknown keyword"),
+            diagnostic(DiagnosticSeverity::ERROR, 8, 12, "expected FROM"),
+        ]);
+
+        let report = handle_diagnostics(text, &notification, DiagnosticSeverity::HINT).unwrap();
+        assert!(report.contains("unknown keyword"));
+        assert!(report.contains("expected FROM"));
+        // The text is only shown once, headed by the most severe diagnostic
+        assert_eq!(report.matches("query:").count(), 1);
+        assert_eq!(report.matches("Error").count(), 1);
+        assert!(!report.contains("Warning"));
+    }
+
+    #[test]
+    fn can_filter_diagnostics_by_severity() {
+        let text = "SELEC * FORM t";
+        let mixed = notification(vec![
+            diagnostic(DiagnosticSeverity::HINT, 0, 5, "did you mean SELECT?"),
+            diagnostic(DiagnosticSeverity::ERROR, 8, 12, "expected FROM"),
+        ]);
+        let report =
+            handle_diagnostics(text, &mixed, min_severity(DiagnosticLevel::Warning)).unwrap();
+        assert!(report.contains("expected FROM"));
+        assert!(!report.contains("did you mean"));
+
+        let hints = notification(vec![diagnostic(DiagnosticSeverity::HINT, 0, 5, "hint")]);
+        let report =
+            handle_diagnostics(text, &hints, min_severity(DiagnosticLevel::Error)).unwrap();
+        assert!(report.is_empty());
+    }
+
+    #[test]
+    fn can_treat_unknown_severity_as_warning() {
+        let unknown = serde_json::from_value::<DiagnosticSeverity>(serde_json::json!(7)).unwrap();
+        let text = "SELEC 1";
+        let notification = notification(vec![diagnostic(unknown, 0, 5, "unknown keyword")]);
+
+        let report = handle_diagnostics(text, &notification, DiagnosticSeverity::HINT).unwrap();
+        assert!(report.contains("unknown keyword"));
+        assert!(report.contains("Warning"));
+    }
+
+    #[test]
+    fn can_dedupe_diagnostics() {
+        let text = "SELEC * FORM t";
+        let notification = notification(vec![
+            diagnostic(DiagnosticSeverity::ERROR, 8, 12, "expected FROM"),
+            diagnostic(DiagnosticSeverity::ERROR, 8, 12, "expected FROM"),
+            diagnostic(DiagnosticSeverity::WARNING, 8, 12, "expected FROM"),
+        ]);
+
+        let report = handle_diagnostics(text, &notification, DiagnosticSeverity::HINT).unwrap();
+        // Once as an error and once as a warning
+        assert_eq!(report.matches("expected FROM").count(), 2);
+    }
+
+    #[test]
+    fn can_handle_no_diagnostics() {
+        let report =
+            handle_diagnostics("SELECT 1", &notification(vec![]), DiagnosticSeverity::HINT)
+                .unwrap();
+        assert!(report.is_empty());
+    }
 }
@@ -1,13 +1,22 @@
 use std::borrow::Cow;
 
-use jsonrpsee_types::{Id, NotificationSer, RequestSer};
+use jsonrpsee_types::{
+    ErrorCode, ErrorObjectOwned, Id, NotificationSer, RequestSer, Response, ResponsePayload,
+};
 use lsp_types::{
-    notification::{DidChangeTextDocument, DidOpenTextDocument, Initialized, Notification},
-    request::{Initialize, Request as RequestTrait},
-    CompletionParams, DidChangeTextDocumentParams, DidOpenTextDocumentParams, InitializeParams,
-    InitializedParams, PartialResultParams, Position, TextDocumentContentChangeEvent,
-    TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams, Url,
-    VersionedTextDocumentIdentifier, WorkDoneProgressParams,
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Exit, Initialized,
+        Notification,
+    },
+    request::{
+        Formatting, HoverRequest, Initialize, Request as RequestTrait, Shutdown,
+        SignatureHelpRequest,
+    },
+    CompletionParams, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentFormattingParams, FormattingOptions, HoverParams,
+    InitializeParams, InitializedParams, PartialResultParams, Position, SignatureHelpParams,
+    TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem,
+    TextDocumentPositionParams, Url, VersionedTextDocumentIdentifier, WorkDoneProgressParams,
 };
 use serde::Serialize;
 use serde_json::value::RawValue;
@@ -33,6 +42,8 @@ impl LspPayload for RequestSer<'_> {}
 
 impl LspPayload for NotificationSer<'_> {}
 
+impl LspPayload for Response<'_, serde_json::Value> {}
+
 /// Create an LSP request.
 /// Note that params is leaked here. Make sure to call to_payload to clean up.
 fn create_request<T: Serialize>(
@@ -60,6 +71,19 @@ fn create_notification<T: Serialize>(
     Ok(notification)
 }
 
+/// Create a successful response to a request sent by the server.
+pub fn response(
+    id: Id<'static>,
+    result: serde_json::Value,
+) -> Response<'static, serde_json::Value> {
+    Response::new(ResponsePayload::success(result), id)
+}
+
+/// Create an error response to a request sent by the server.
+pub fn error_response(id: Id<'static>, code: ErrorCode) -> Response<'static, serde_json::Value> {
+    Response::new(ResponsePayload::error(ErrorObjectOwned::from(code)), id)
+}
+
 /// Create an initialize request.
 pub fn initialize(options: Option<serde_json::Value>) -> anyhow::Result<RequestSer<'static>> {
     let params = InitializeParams {
@@ -76,6 +100,16 @@ pub fn initialized() -> anyhow::Result<NotificationSer<'static>> {
     create_notification(Initialized::METHOD, params)
 }
 
+/// Create a shutdown request.
+pub fn shutdown() -> RequestSer<'static> {
+    RequestSer::owned(generate_uuid(), Cow::from(Shutdown::METHOD), None)
+}
+
+/// Create an exit notification.
+pub fn exit() -> NotificationSer<'static> {
+    NotificationSer::owned(Cow::from(Exit::METHOD), None)
+}
+
 /// Create a textDocument/didOpen notification.
 /// Hardcoded for sql.
 pub fn did_open(uri: Url, text: &str) -> anyhow::Result<NotificationSer<'static>> {
@@ -91,6 +125,15 @@ pub fn did_open(uri: Url, text: &str) -> anyhow::Result<NotificationSer<'static>
     create_notification(DidOpenTextDocument::METHOD, params)
 }
 
+/// Create a textDocument/didClose notification.
+pub fn did_close(uri: Url) -> anyhow::Result<NotificationSer<'static>> {
+    let params = DidCloseTextDocumentParams {
+        text_document: TextDocumentIdentifier { uri },
+    };
+
+    create_notification(DidCloseTextDocument::METHOD, params)
+}
+
 /// Create a textDocument/didChange notification.
 /// Text should be all the text in the REPL (no range changes).
 pub fn did_change(uri: Url, version: i32, text: &str) -> anyhow::Result<NotificationSer<'static>> {
@@ -128,3 +171,59 @@ pub fn completion(uri: Url, line: u32, offset: u32) -> anyhow::Result<RequestSer
 
     create_request("textDocument/completion", params)
 }
+
+/// Create a textDocument/hover request.
+/// Zero-indexed.
+pub fn hover(uri: Url, line: u32, offset: u32) -> anyhow::Result<RequestSer<'static>> {
+    let params = HoverParams {
+        text_document_position_params: TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position: Position {
+                line,
+                character: offset,
+            },
+        },
+        work_done_progress_params: WorkDoneProgressParams {
+            ..Default::default()
+        },
+    };
+
+    create_request(HoverRequest::METHOD, params)
+}
+
+/// Create a textDocument/formatting request.
+pub fn formatting(uri: Url) -> anyhow::Result<RequestSer<'static>> {
+    let params = DocumentFormattingParams {
+        text_document: TextDocumentIdentifier { uri },
+        options: FormattingOptions {
+            tab_size: 4,
+            insert_spaces: true,
+            ..Default::default()
+        },
+        work_done_progress_params: WorkDoneProgressParams {
+            ..Default::default()
+        },
+    };
+
+    create_request(Formatting::METHOD, params)
+}
+
+/// Create a textDocument/signatureHelp request.
+/// Zero-indexed.
+pub fn signature_help(uri: Url, line: u32, offset: u32) -> anyhow::Result<RequestSer<'static>> {
+    let params = SignatureHelpParams {
+        context: None,
+        text_document_position_params: TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position: Position {
+                line,
+                character: offset,
+            },
+        },
+        work_done_progress_params: WorkDoneProgressParams {
+            ..Default::default()
+        },
+    };
+
+    create_request(SignatureHelpRequest::METHOD, params)
+}
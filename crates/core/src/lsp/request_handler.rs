@@ -0,0 +1,83 @@
+use jsonrpsee_types::{ErrorCode, Request};
+use lsp_types::{
+    request::{
+        RegisterCapability, Request as RequestTrait, UnregisterCapability, WorkDoneProgressCreate,
+        WorkspaceConfiguration,
+    },
+    ConfigurationParams,
+};
+use serde_json::Value;
+
+use crate::lsp::payload::{self, LspPayload};
+
+/// Build the response payload for a request sent from the LSP server to the client. Unknown
+/// requests and invalid parameters are answered with an error response.
+pub fn handle_server_request(request: &Request) -> anyhow::Result<String> {
+    let result = match request.method.as_ref() {
+        WorkspaceConfiguration::METHOD => request
+            .params
+            .as_ref()
+            .map(|params| serde_json::from_str::<ConfigurationParams>(params.get()))
+            .transpose()
+            .map(configuration)
+            .map_err(|_| ErrorCode::InvalidParams),
+        RegisterCapability::METHOD
+        | UnregisterCapability::METHOD
+        | WorkDoneProgressCreate::METHOD => Ok(Value::Null),
+        _ => Err(ErrorCode::MethodNotFound),
+    };
+
+    let id = request.id.clone().into_owned();
+    match result {
+        Ok(value) => payload::response(id, value).to_payload(),
+        Err(code) => payload::error_response(id, code).to_payload(),
+    }
+}
+
+/// Settings returned for a workspace/configuration request. There is no client side
+/// configuration, so null is returned for every requested item.
+fn configuration(params: Option<ConfigurationParams>) -> Value {
+    let items = params.map(|params| params.items.len()).unwrap_or(0);
+    Value::Array(vec![Value::Null; items])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_payload(payload: &str) -> Value {
+        let (_, body) = payload.split_once("\r\n\r\n").unwrap();
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn can_reply_to_workspace_configuration() {
+        let body = r#"{"jsonrpc":"2.0","id":1,"method":"workspace/configuration","params":{"items":[{"section":"a"},{"section":"b"}]}}"#;
+        let request = serde_json::from_str::<Request>(body).unwrap();
+
+        let response = parse_payload(&handle_server_request(&request).unwrap());
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["result"], serde_json::json!([null, null]));
+    }
+
+    #[test]
+    fn can_reply_to_unknown_request() {
+        let body = r#"{"jsonrpc":"2.0","id":"abc","method":"foo/bar"}"#;
+        let request = serde_json::from_str::<Request>(body).unwrap();
+
+        let response = parse_payload(&handle_server_request(&request).unwrap());
+        assert_eq!(response["id"], "abc");
+        assert_eq!(response["error"]["code"], -32601);
+    }
+
+    #[test]
+    fn can_reply_to_invalid_params() {
+        let body =
+            r#"{"jsonrpc":"2.0","id":2,"method":"workspace/configuration","params":{"items":1}}"#;
+        let request = serde_json::from_str::<Request>(body).unwrap();
+
+        let response = parse_payload(&handle_server_request(&request).unwrap());
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["error"]["code"], -32602);
+    }
+}
@@ -3,11 +3,17 @@ use std::pin::Pin;
 use anyhow::{anyhow, Context};
 use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
 
-/// Read an LSP response or notification body into a byte slice.
-pub async fn read_body(reader: &mut Pin<Box<dyn AsyncBufRead + Send>>) -> anyhow::Result<Vec<u8>> {
-    let content_length = parse_header(reader)
+/// Read an LSP response or notification body into a byte slice. Returns None if the reader is at
+/// its end, e.g. because the server exited.
+pub async fn read_body(
+    reader: &mut Pin<Box<dyn AsyncBufRead + Send>>,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let Some(content_length) = parse_header(reader)
         .await
-        .with_context(|| "failed to parse response header")?;
+        .with_context(|| "failed to parse response header")?
+    else {
+        return Ok(None);
+    };
 
     let mut bytes = vec![0u8; content_length];
     reader
@@ -15,18 +21,24 @@ pub async fn read_body(reader: &mut Pin<Box<dyn AsyncBufRead + Send>>) -> anyhow
         .await
         .with_context(|| "failed to read bytes")?;
 
-    Ok(bytes)
+    Ok(Some(bytes))
 }
 
 /// Return the response length (only relevant header) and advance the reader
-/// until the body
-async fn parse_header(reader: &mut Pin<Box<dyn AsyncBufRead + Send>>) -> anyhow::Result<usize> {
+/// until the body. Returns None if the reader ends before the header starts.
+async fn parse_header(
+    reader: &mut Pin<Box<dyn AsyncBufRead + Send>>,
+) -> anyhow::Result<Option<usize>> {
     let mut content_length = 0;
 
     // Read header
+    let mut first_line = true;
     loop {
         let mut line = String::new();
-        reader.read_line(&mut line).await?;
+        if reader.read_line(&mut line).await? == 0 && first_line {
+            return Ok(None);
+        }
+        first_line = false;
 
         if line.trim().is_empty() {
             // End of header
@@ -42,5 +54,5 @@ async fn parse_header(reader: &mut Pin<Box<dyn AsyncBufRead + Send>>) -> anyhow:
         }
     }
 
-    Ok(content_length)
+    Ok(Some(content_length))
 }
@@ -0,0 +1,85 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use jsonrpsee_types::Id;
+use tokio::sync::oneshot;
+
+/// ResponseRouter delivers responses from the LSP server to the requests waiting for them, so that
+/// a response can't be missed regardless of how many other messages the server sends.
+#[derive(Clone, Default)]
+pub struct ResponseRouter {
+    /// Senders for the requests that are waiting for a response, by request ID.
+    pending: Arc<Mutex<HashMap<Id<'static>, oneshot::Sender<Vec<u8>>>>>,
+}
+
+impl ResponseRouter {
+    /// Start waiting for the response to the request with the given ID. Should be called before
+    /// the request is sent.
+    pub fn register(&self, id: Id<'static>) -> oneshot::Receiver<Vec<u8>> {
+        let (tx, rx) = oneshot::channel();
+        self.lock().insert(id, tx);
+        rx
+    }
+
+    /// Stop waiting for the response to the request with the given ID, e.g. after a timeout.
+    pub fn unregister(&self, id: &Id<'static>) {
+        self.lock().remove(id);
+    }
+
+    /// Deliver the response body to the request with the given ID. Returns false if no request is
+    /// waiting for it.
+    pub fn route(&self, id: &Id<'static>, body: Vec<u8>) -> bool {
+        match self.lock().remove(id) {
+            // The receiver is gone if the request stopped waiting in the meantime
+            Some(tx) => tx.send(body).is_ok(),
+            None => false,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Id<'static>, oneshot::Sender<Vec<u8>>>> {
+        // The map can't be left in an invalid state, so a poisoned lock is fine to use
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    fn id(id: &str) -> Id<'static> {
+        Id::Str(Cow::Owned(id.to_string()))
+    }
+
+    #[test]
+    fn can_route_interleaved_responses() {
+        let router = ResponseRouter::default();
+        let mut first_rx = router.register(id("first"));
+        let mut second_rx = router.register(id("second"));
+
+        // Unrelated responses and responses in a different order than the requests
+        assert!(!router.route(&id("unknown"), b"unknown".to_vec()));
+        assert!(router.route(&id("second"), b"second".to_vec()));
+        assert!(router.route(&id("first"), b"first".to_vec()));
+
+        assert_eq!(first_rx.try_recv().unwrap(), b"first");
+        assert_eq!(second_rx.try_recv().unwrap(), b"second");
+
+        // Each request only gets one response
+        assert!(!router.route(&id("first"), b"again".to_vec()));
+    }
+
+    #[test]
+    fn can_unregister_request() {
+        let router = ResponseRouter::default();
+        let _rx = router.register(id("request"));
+
+        router.unregister(&id("request"));
+        assert!(!router.route(&id("request"), b"late".to_vec()));
+    }
+}
@@ -1,19 +1,43 @@
-use std::{future::Future, pin::Pin, process::Stdio};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    future::Future,
+    io,
+    pin::Pin,
+    process::Stdio,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use anyhow::Context;
-use jsonrpsee_types::{Notification, Response};
+use jsonrpsee_types::{Notification, Request, Response};
 use serde_json::Value;
 use tokio::{
-    io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader},
-    process::{Child, ChildStderr, ChildStdin, ChildStdout, Command},
-    sync::broadcast,
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    process::{Child, ChildStderr, Command},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc, Notify,
+    },
+    time,
 };
 
-use crate::lsp::response::read_body;
-use crate::{logging::Logger, task};
+use crate::lsp::{
+    request_handler::handle_server_request, response::read_body, router::ResponseRouter,
+};
+use crate::{error::SqlFriendError, logging::Logger, task};
 
 use super::Task;
 
+/// Number of lines of stderr that are shown when the LSP server exits unexpectedly.
+const STDERR_TAIL_LINES: usize = 10;
+
+/// Number of lines of stderr that are kept for `/lsp log`.
+const STDERR_LOG_LINES: usize = 200;
+
+/// Longest time to wait for the rest of stderr to be read after the LSP server exited.
+const STDERR_TAIL_TIMEOUT: Duration = Duration::from_millis(200);
+
 #[derive(Debug, Clone)]
 pub enum CommunicationProtocol {
     Stdio {
@@ -22,6 +46,16 @@ pub enum CommunicationProtocol {
 
         /// Arguments for LSP binary.
         args: Vec<String>,
+
+        /// Environment variables set for the LSP binary, in addition to the inherited ones.
+        env: BTreeMap<String, String>,
+    },
+    Tcp {
+        /// Host the LSP server is listening on.
+        host: String,
+
+        /// Port the LSP server is listening on.
+        port: u16,
     },
 }
 
@@ -32,7 +66,7 @@ pub struct ServerChannels {
     req_tx: broadcast::Sender<String>,
 
     /// Used to return LSP request responses to the LSP client.
-    req_output_tx: broadcast::Sender<Vec<u8>>,
+    router: ResponseRouter,
 
     /// Used to return LSP notifications to notification handler.
     notif_tx: broadcast::Sender<Vec<u8>>,
@@ -43,9 +77,8 @@ pub struct ClientChannels {
     /// Used to send requests to the LSP server.
     pub req_tx: broadcast::Sender<String>,
 
-    /// Used to receive LSP request responses from the LSP server. This is a sender so we can
-    /// create new receivers every time a LspClient is created.
-    pub req_output_tx: broadcast::Sender<Vec<u8>>,
+    /// Used to receive LSP request responses from the LSP server.
+    pub router: ResponseRouter,
 
     /// Used to receive LSP notifications from the LSP server.
     pub notif_rx: broadcast::Receiver<Vec<u8>>,
@@ -57,45 +90,103 @@ pub struct LspServer {
 
     /// All channels used by the server.
     channels: ServerChannels,
+
+    /// Last lines written to stderr by any server, see `State::lsp_stderr`.
+    stderr_log: Arc<tokio::sync::Mutex<VecDeque<String>>>,
+
+    /// Incremented every time a server is started, to tell the exit of a replaced server apart
+    /// from the exit of the running one.
+    generation: u64,
+}
+
+/// Last lines that the LSP server wrote to stderr.
+#[derive(Clone, Default)]
+struct StderrTail {
+    lines: Arc<Mutex<VecDeque<String>>>,
+
+    /// Notified once stderr has been read to the end.
+    closed: Arc<Notify>,
+}
+
+impl StderrTail {
+    fn push(&self, line: String) {
+        if let Ok(mut lines) = self.lines.lock() {
+            push_line(&mut lines, line, STDERR_TAIL_LINES);
+        }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        self.lines
+            .lock()
+            .map(|lines| lines.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Add the line, removing the oldest lines so that there are at most max_lines.
+fn push_line(lines: &mut VecDeque<String>, line: String, max_lines: usize) {
+    while lines.len() >= max_lines {
+        lines.pop_front();
+    }
+    lines.push_back(line);
 }
 
 impl LspServer {
-    pub fn new(logger: Logger) -> (Self, ClientChannels) {
+    pub fn new(
+        logger: Logger,
+        channel_capacity: usize,
+        stderr_log: Arc<tokio::sync::Mutex<VecDeque<String>>>,
+    ) -> (Self, ClientChannels) {
         // The receiver will be created when the LSP server is spawned.
-        let (req_tx, _) = broadcast::channel(10);
+        let (req_tx, _) = broadcast::channel(channel_capacity);
 
-        // The receiver will be created when a LspClient is created.
-        let (req_output_tx, _) = broadcast::channel(10);
+        let router = ResponseRouter::default();
 
-        let (notif_tx, notif_rx) = broadcast::channel(10);
+        let (notif_tx, notif_rx) = broadcast::channel(channel_capacity);
 
         (
             LspServer {
                 logger,
                 channels: ServerChannels {
                     req_tx: req_tx.clone(),
-                    req_output_tx: req_output_tx.clone(),
+                    router: router.clone(),
                     notif_tx,
                 },
+                stderr_log,
+                generation: 0,
             },
             ClientChannels {
                 req_tx,
-                req_output_tx,
+                router,
                 notif_rx,
             },
         )
     }
 
-    pub fn init(
+    /// Start the server. If a spawned server exits without being killed, `Command::LspExited` is
+    /// sent to exit_tx.
+    pub async fn init(
         &mut self,
         protocol: CommunicationProtocol,
         broadcast_tx: &mut broadcast::Sender<task::BroadcastMessage>,
+        exit_tx: mpsc::Sender<task::Command>,
     ) -> anyhow::Result<Vec<Task>> {
+        self.generation += 1;
         match protocol {
-            CommunicationProtocol::Stdio { cmd, args } => self.init_stdio(cmd, args, broadcast_tx),
+            CommunicationProtocol::Stdio { cmd, args, env } => {
+                self.init_stdio(cmd, args, env, broadcast_tx, exit_tx)
+            }
+            CommunicationProtocol::Tcp { host, port } => {
+                self.init_tcp(host, port, broadcast_tx).await
+            }
         }
     }
 
+    /// Identifies the last server that was started, see `task::Command::LspExited`.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     /// Initializes the LSP server using stdio commmunication.
     /// Futures for [stdin, stdout, stderr] tasks are returned.
     /// Will not start until futures are awaited.
@@ -103,15 +194,22 @@ impl LspServer {
         &self,
         cmd: String,
         args: Vec<String>,
+        env: BTreeMap<String, String>,
         broadcast_tx: &mut broadcast::Sender<task::BroadcastMessage>,
+        exit_tx: mpsc::Sender<task::Command>,
     ) -> anyhow::Result<Vec<Task>> {
         let mut child = Command::new(&cmd)
             .args(args)
+            .envs(env)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .spawn()
-            .with_context(|| format!("failed to spawn LSP server `{cmd}`"))?;
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => SqlFriendError::LspServerNotFound(cmd.clone()),
+                _ => SqlFriendError::LspSpawnFailed(cmd.clone(), e),
+            })?;
 
         let child_stdin = child
             .stdin
@@ -129,9 +227,12 @@ impl LspServer {
             .stderr
             .take()
             .expect("stderr shouldn't be taken anywhere else");
-        let stderr_task = self.new_stderr_reader(child_stderr, broadcast_tx.subscribe());
+        let stderr_tail = StderrTail::default();
+        let stderr_task =
+            self.new_stderr_reader(child_stderr, stderr_tail.clone(), broadcast_tx.subscribe());
 
-        let process_task = self.new_process_manager(child, broadcast_tx.subscribe());
+        let process_task =
+            self.new_process_manager(child, stderr_tail, exit_tx, broadcast_tx.subscribe());
 
         Ok(vec![
             Box::pin(stdin_task),
@@ -141,14 +242,36 @@ impl LspServer {
         ])
     }
 
-    /// Task that forwards messages from child_stdout to the output channel.
+    /// Initializes the LSP server by connecting to an already running server over TCP.
+    /// Futures for [writer, reader] tasks are returned. The socket is closed when the tasks are
+    /// killed.
+    /// Will not start until futures are awaited.
+    async fn init_tcp(
+        &self,
+        host: String,
+        port: u16,
+        broadcast_tx: &mut broadcast::Sender<task::BroadcastMessage>,
+    ) -> anyhow::Result<Vec<Task>> {
+        let stream = TcpStream::connect((host.as_str(), port))
+            .await
+            .with_context(|| format!("failed to connect to LSP server at {host}:{port}"))?;
+        let (reader, writer) = stream.into_split();
+
+        let writer_task = self.new_stdin_sender(writer, broadcast_tx.subscribe());
+        let reader_task = self.new_stdout_reader(reader, broadcast_tx.subscribe());
+
+        Ok(vec![Box::pin(writer_task), Box::pin(reader_task)])
+    }
+
+    /// Task that forwards messages from the server output to the output channel.
     fn new_stdout_reader(
         &self,
-        child_stdout: ChildStdout,
+        child_stdout: impl AsyncRead + Send + Unpin + 'static,
         mut broadcast_rx: broadcast::Receiver<task::BroadcastMessage>,
     ) -> impl Future<Output = anyhow::Result<()>> {
         let logger_stdout = self.logger.clone();
-        let req_output_tx = self.channels.req_output_tx.clone();
+        let req_tx = self.channels.req_tx.clone();
+        let router = self.channels.router.clone();
         let notif_tx = self.channels.notif_tx.clone();
 
         async move {
@@ -158,13 +281,38 @@ impl LspServer {
             loop {
                 tokio::select! {
                     body = read_body(&mut stdout) => {
-                        let body = body?;
+                        // The process manager reports if the server exited
+                        let Some(body) = body? else {
+                            logger_stdout.debug("server stdout closed")?;
+                            return Ok(());
+                        };
                         let body_str = String::from_utf8_lossy(&body);
                         logger_stdout.debug(&format!("server stdout: {body_str}"))?;
 
                         // TODO: Figure out why an untagged enum doesn't work here
-                        if serde_json::from_slice::<Response<Value>>(&body).is_ok() {
-                            req_output_tx.send(body)?;
+                        // Requests must be checked first since they also deserialize as
+                        // notifications.
+                        if let Ok(request) = serde_json::from_slice::<Request>(&body) {
+                            // A reply that can't be sent only affects the server, so it
+                            // shouldn't stop the reader
+                            let sent = handle_server_request(&request)
+                                .and_then(|response| Ok(req_tx.send(response)?));
+                            if let Err(e) = sent {
+                                logger_stdout.debug(&format!(
+                                    "failed to reply to server request {}: {e:#}",
+                                    request.method
+                                ))?;
+                            }
+                            continue;
+                        }
+
+                        if let Ok(response) = serde_json::from_slice::<Response<Value>>(&body) {
+                            let id = response.id.into_owned();
+                            if !router.route(&id, body) {
+                                logger_stdout.debug(&format!(
+                                    "no request is waiting for the response with ID {id:?}"
+                                ))?;
+                            }
                             continue;
                         }
 
@@ -181,6 +329,9 @@ impl LspServer {
                             Ok(task::BroadcastMessage::KillLsp) => {
                                 return Ok(());
                             }
+                            Ok(task::BroadcastMessage::StopHeartbeat) => continue,
+                            // Only the oldest messages are dropped, so keep receiving
+                            Err(RecvError::Lagged(_)) => continue,
                             Err(e) => anyhow::bail!(e)
                         }
                     }
@@ -189,10 +340,10 @@ impl LspServer {
         }
     }
 
-    /// Task that forwards messages from the input channel to child_stdin.
+    /// Task that forwards messages from the input channel to the server input.
     fn new_stdin_sender(
         &self,
-        mut child_stdin: ChildStdin,
+        mut child_stdin: impl AsyncWrite + Send + Unpin + 'static,
         mut broadcast_rx: broadcast::Receiver<task::BroadcastMessage>,
     ) -> impl Future<Output = anyhow::Result<()>> {
         let mut input_rx = self.channels.req_tx.subscribe();
@@ -201,16 +352,38 @@ impl LspServer {
         async move {
             loop {
                 tokio::select! {
+                    // Write pending messages (such as didClose) before handling KillLsp
+                    biased;
+
                     input = input_rx.recv() => {
-                        let input = input?;
+                        let input = match input {
+                            Ok(input) => input,
+                            Err(RecvError::Lagged(skipped)) => {
+                                logger_stdin.warn(&format!(
+                                    "dropped {skipped} messages to the LSP server, consider increasing lsp_channel_capacity"
+                                ))?;
+                                continue;
+                            }
+                            Err(e) => anyhow::bail!(e),
+                        };
                         logger_stdin.debug(&format!("server stdin: {input}"))?;
-                        child_stdin.write_all(input.as_bytes()).await?;
+                        // Fails if the server exited, which the process manager reports
+                        if let Err(e) = child_stdin.write_all(input.as_bytes()).await {
+                            logger_stdin.debug(&format!("failed to write to server stdin: {e}"))?;
+                            return Ok(());
+                        }
                     }
                     msg = broadcast_rx.recv() => {
                         match msg {
                             Ok(task::BroadcastMessage::KillLsp) => {
+                                // Closes the socket when using TCP. The process may already be
+                                // gone when using stdio, so errors are ignored.
+                                let _ = child_stdin.shutdown().await;
                                 return Ok(());
                             }
+                            Ok(task::BroadcastMessage::StopHeartbeat) => continue,
+                            // Only the oldest messages are dropped, so keep receiving
+                            Err(RecvError::Lagged(_)) => continue,
                             Err(e) => anyhow::bail!(e)
                         }
                     }
@@ -219,13 +392,16 @@ impl LspServer {
         }
     }
 
-    /// Task that forwards messages from child_stderr to the logger.
+    /// Task that forwards messages from child_stderr to the logger, and keeps the last lines in
+    /// stderr_tail.
     fn new_stderr_reader(
         &self,
         child_stderr: ChildStderr,
+        stderr_tail: StderrTail,
         mut broadcast_rx: broadcast::Receiver<task::BroadcastMessage>,
     ) -> impl Future<Output = anyhow::Result<()>> {
         let logger_stderr = self.logger.clone();
+        let stderr_log = self.stderr_log.clone();
 
         async move {
             let stderr = BufReader::new(child_stderr);
@@ -236,15 +412,23 @@ impl LspServer {
                         let line = line?;
                         let line = match line {
                             Some(l) => l,
-                            None => return Ok(())
+                            None => {
+                                stderr_tail.closed.notify_one();
+                                return Ok(());
+                            }
                         };
                         logger_stderr.debug(&format!("server stderr: {line}"))?;
+                        push_line(&mut *stderr_log.lock().await, line.clone(), STDERR_LOG_LINES);
+                        stderr_tail.push(line);
                     }
                     msg = broadcast_rx.recv() => {
                         match msg {
                             Ok(task::BroadcastMessage::KillLsp) => {
                                 return Ok(());
                             }
+                            Ok(task::BroadcastMessage::StopHeartbeat) => continue,
+                            // Only the oldest messages are dropped, so keep receiving
+                            Err(RecvError::Lagged(_)) => continue,
                             Err(e) => anyhow::bail!(e)
                         }
                     }
@@ -253,23 +437,43 @@ impl LspServer {
         }
     }
 
-    /// Task used to manage LSP server process.
+    /// Task used to manage LSP server process. If the process exits before it's killed, its exit
+    /// status and the last lines of its stderr are sent to exit_tx.
     fn new_process_manager(
         &self,
         mut child: Child,
+        stderr_tail: StderrTail,
+        exit_tx: mpsc::Sender<task::Command>,
         mut broadcast_rx: broadcast::Receiver<task::BroadcastMessage>,
     ) -> impl Future<Output = anyhow::Result<()>> {
+        let generation = self.generation;
+
         async move {
-            // If we add more broadcast messages in the future, this loop is necessary.
-            #[allow(clippy::never_loop)]
             loop {
                 tokio::select! {
+                    status = child.wait() => {
+                        let status = status?;
+                        // The last lines may not have been read yet
+                        let _ = time::timeout(STDERR_TAIL_TIMEOUT, stderr_tail.closed.notified())
+                            .await;
+
+                        exit_tx
+                            .send(task::Command::LspExited {
+                                generation,
+                                status,
+                                stderr_tail: stderr_tail.lines(),
+                            })
+                            .await?;
+                        return Ok(());
+                    }
                     msg = broadcast_rx.recv() => {
                         match msg {
                             Ok(task::BroadcastMessage::KillLsp) => {
                                 child.kill().await?;
                                 return Ok(());
                             }
+                            Ok(task::BroadcastMessage::StopHeartbeat) => continue,
+                            Err(RecvError::Lagged(_)) => continue,
                             Err(e) => anyhow::bail!(e),
                         }
                     }
@@ -278,3 +482,17 @@ impl LspServer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_bound_stderr_lines() {
+        let mut lines = VecDeque::new();
+        for i in 0..5 {
+            push_line(&mut lines, i.to_string(), 3);
+        }
+        assert_eq!(lines, ["2", "3", "4"]);
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    io::{self, IsTerminal},
+    time::Duration,
+};
+
+use table::TableOptions;
+use terminal_size::{terminal_size, Height, Width};
+use value::{Value, ValueOptions};
+
+use crate::config::OutputFormat;
+
+pub mod csv;
+pub mod json;
+pub mod plan;
+pub mod report;
+pub mod table;
+pub mod value;
+
+/// Text used to display NULL values.
+pub const NULL_DISPLAY: &str = "<NULL>";
+
+/// Render the rows in the given format. Values are formatted using value_options, except in JSON
+/// where they keep their type if possible.
+pub fn render(
+    format: OutputFormat,
+    column_names: &[&str],
+    rows: &[Vec<Value>],
+    value_options: &ValueOptions,
+    table_options: &TableOptions,
+) -> anyhow::Result<String> {
+    if format == OutputFormat::Json {
+        return json::render(column_names, rows, value_options);
+    }
+
+    let formatted = rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| value.format(value_options))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let values = formatted
+        .iter()
+        .map(|row| row.iter().map(Option::as_deref).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    match format {
+        OutputFormat::Csv => Ok(csv::render(column_names, &values)),
+        _ => {
+            let values = values
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|value| value.unwrap_or(NULL_DISPLAY))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            Ok(table::render(column_names, &values, table_options))
+        }
+    }
+}
+
+/// Width of the terminal that stdout is written to. Returns None if stdout isn't a terminal.
+pub fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+
+    terminal_size().map(|(Width(width), _)| width.into())
+}
+
+/// Height of the terminal that stdout is written to. Returns None if stdout isn't a terminal.
+pub fn terminal_height() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+
+    terminal_size().map(|(_, Height(height))| height.into())
+}
+
+/// Footer shown after a result, e.g. `(2 rows, 12.4 ms)`.
+pub fn footer(row_count: usize, elapsed: Duration) -> String {
+    let rows = if row_count == 1 { "row" } else { "rows" };
+    format!("({row_count} {rows}, {})", millis(elapsed))
+}
+
+/// Footer shown after a page of a result, e.g. `(rows 21-40, 12.4 ms)`, followed by a hint on
+/// how to show the next page if there is one. first_row is zero-based.
+pub fn page_footer(first_row: usize, row_count: usize, more: bool, elapsed: Duration) -> String {
+    let rows = match row_count {
+        0 => "no more rows".to_string(),
+        1 => format!("row {}", first_row + 1),
+        _ => format!("rows {}-{}", first_row + 1, first_row + row_count),
+    };
+    let footer = format!("({rows}, {})", millis(elapsed));
+
+    if more {
+        format!(
+            "{footer} Press Enter or type `{}more` for the next page.",
+            crate::command_prefix!()
+        )
+    } else {
+        footer
+    }
+}
+
+/// Format a duration in milliseconds, e.g. `12.4 ms`.
+pub fn millis(elapsed: Duration) -> String {
+    format!("{:.1} ms", elapsed.as_secs_f64() * 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_render_raw_values_as_csv() {
+        let rows = vec![vec![
+            Value::Text("say \"hi\"".to_string()),
+            Value::Text("C:\\temp\nnext".to_string()),
+            Value::Null,
+        ]];
+        let output = render(
+            OutputFormat::Csv,
+            &["quoted", "path", "empty"],
+            &rows,
+            &Default::default(),
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            output,
+            "quoted,path,empty\n\"say \"\"hi\"\"\",\"C:\\temp\nnext\","
+        );
+    }
+
+    #[test]
+    fn can_format_footer() {
+        assert_eq!(
+            footer(0, Duration::from_micros(12_400)),
+            "(0 rows, 12.4 ms)"
+        );
+        assert_eq!(footer(1, Duration::from_micros(50)), "(1 row, 0.1 ms)");
+        assert_eq!(footer(25, Duration::from_secs(2)), "(25 rows, 2000.0 ms)");
+    }
+
+    #[test]
+    fn can_format_page_footer() {
+        let elapsed = Duration::from_micros(12_400);
+        assert_eq!(
+            page_footer(0, 20, true, elapsed),
+            "(rows 1-20, 12.4 ms) Press Enter or type `/more` for the next page."
+        );
+        assert_eq!(page_footer(20, 1, false, elapsed), "(row 21, 12.4 ms)");
+        assert_eq!(
+            page_footer(20, 0, false, elapsed),
+            "(no more rows, 12.4 ms)"
+        );
+    }
+}
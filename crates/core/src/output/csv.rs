@@ -0,0 +1,43 @@
+/// Render the rows as CSV (RFC 4180) with a header line. NULL values (None) are written as empty
+/// fields.
+pub fn render(column_names: &[&str], rows: &[Vec<Option<&str>>]) -> String {
+    let mut lines = vec![make_record(column_names.iter().copied())];
+    lines.extend(
+        rows.iter()
+            .map(|row| make_record(row.iter().map(|value| value.unwrap_or("")))),
+    );
+
+    lines.join("\n")
+}
+
+fn make_record<'a>(values: impl Iterator<Item = &'a str>) -> String {
+    values.map(escape).collect::<Vec<_>>().join(",")
+}
+
+/// Quote the value if it contains a delimiter, quote or line break.
+fn escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_render_csv() {
+        let rows = vec![
+            vec![Some("1"), Some("John \"JD\" Doe")],
+            vec![Some("2"), Some("a,b")],
+            vec![Some("3"), None],
+        ];
+
+        assert_eq!(
+            render(&["id", "name"], &rows),
+            "id,name\n1,\"John \"\"JD\"\" Doe\"\n2,\"a,b\"\n3,"
+        );
+    }
+}
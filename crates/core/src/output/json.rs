@@ -0,0 +1,50 @@
+use serde_json::Map;
+
+use crate::output::value::{Value, ValueOptions};
+
+/// Render the rows as a JSON array with an object per row. Values keep their type where JSON has
+/// an equivalent, see `Value::to_json`.
+pub fn render(
+    column_names: &[&str],
+    rows: &[Vec<Value>],
+    options: &ValueOptions,
+) -> anyhow::Result<String> {
+    let rows = rows
+        .iter()
+        .map(|row| {
+            let object = column_names
+                .iter()
+                .zip(row)
+                .map(|(name, value)| (name.to_string(), value.to_json(options)))
+                .collect::<Map<_, _>>();
+
+            serde_json::Value::Object(object)
+        })
+        .collect::<Vec<_>>();
+
+    Ok(serde_json::to_string_pretty(&rows)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_render_json() {
+        let rows = vec![
+            vec![Value::Int(1), Value::Text("John".to_string())],
+            vec![Value::Int(2), Value::Null],
+        ];
+        let output: serde_json::Value =
+            serde_json::from_str(&render(&["id", "name"], &rows, &Default::default()).unwrap())
+                .unwrap();
+
+        assert_eq!(
+            output,
+            serde_json::json!([
+                {"id": 1, "name": "John"},
+                {"id": 2, "name": null},
+            ])
+        );
+    }
+}
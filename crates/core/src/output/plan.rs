@@ -0,0 +1,212 @@
+use anyhow::{anyhow, Context};
+use serde_json::Value;
+
+/// Properties of a plan node that are shown below it, in this order.
+const DETAIL_KEYS: [&str; 8] = [
+    "Hash Cond",
+    "Merge Cond",
+    "Join Filter",
+    "Index Cond",
+    "Recheck Cond",
+    "Filter",
+    "Sort Key",
+    "Group Key",
+];
+
+/// Render a postgres query plan (as returned by `EXPLAIN (FORMAT JSON)`) as a tree, e.g.:
+///
+/// ```text
+/// Hash Join  (cost=1.09..2.21 rows=4 width=12)
+/// │  Hash Cond: (a.id = b.a_id)
+/// ├─ Seq Scan on a  (cost=0.00..1.05 rows=5 width=8)
+/// └─ Hash  (cost=1.04..1.04 rows=4 width=8)
+///    └─ Seq Scan on b  (cost=0.00..1.04 rows=4 width=8)
+/// ```
+///
+/// Plans of `EXPLAIN ANALYZE` include the actual time and rows of each node, followed by the
+/// planning and execution time.
+pub fn render_pg_plan(plan: &Value) -> anyhow::Result<String> {
+    let explained = plan.get(0).ok_or(anyhow!("the query plan is empty"))?;
+    let root = explained
+        .get("Plan")
+        .context("the query plan has no root node")?;
+
+    let mut lines = vec![];
+    render_node(root, "", "", &mut lines);
+
+    for key in ["Planning Time", "Execution Time"] {
+        if let Some(time) = explained.get(key).and_then(Value::as_f64) {
+            lines.push(format!("{key}: {time:.3} ms"));
+        }
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Add the lines of the node and its children. The first line of the node starts with
+/// first_prefix, and all other lines with rest_prefix.
+fn render_node(node: &Value, first_prefix: &str, rest_prefix: &str, lines: &mut Vec<String>) {
+    lines.push(format!("{first_prefix}{}", node_label(node)));
+
+    let children = node
+        .get("Plans")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let detail_prefix = if children.is_empty() { "   " } else { "│  " };
+    for key in DETAIL_KEYS {
+        if let Some(value) = node.get(key) {
+            lines.push(format!(
+                "{rest_prefix}{detail_prefix}{key}: {}",
+                display(value)
+            ));
+        }
+    }
+
+    for (i, child) in children.iter().enumerate() {
+        let (first, rest) = if i == children.len() - 1 {
+            ("└─ ", "   ")
+        } else {
+            ("├─ ", "│  ")
+        };
+        render_node(
+            child,
+            &format!("{rest_prefix}{first}"),
+            &format!("{rest_prefix}{rest}"),
+            lines,
+        );
+    }
+}
+
+/// Describe the node like psql does, e.g. `Index Scan using users_pkey on users u  (cost=...)`.
+fn node_label(node: &Value) -> String {
+    let text = |key: &str| node.get(key).and_then(Value::as_str);
+    let mut label = text("Node Type").unwrap_or("Unknown").to_string();
+
+    if let Some(join_type) = text("Join Type").filter(|join_type| *join_type != "Inner") {
+        label = match label.strip_suffix(" Join") {
+            Some(method) => format!("{method} {join_type} Join"),
+            None => format!("{label} {join_type} Join"),
+        };
+    }
+    if let Some(index) = text("Index Name") {
+        // Bitmap index scans only read the index, not the table
+        let preposition = if label == "Bitmap Index Scan" {
+            "on"
+        } else {
+            "using"
+        };
+        label.push_str(&format!(" {preposition} {index}"));
+    }
+    if let Some(relation) = text("Relation Name") {
+        label.push_str(&format!(" on {relation}"));
+        if let Some(alias) = text("Alias").filter(|alias| *alias != relation) {
+            label.push_str(&format!(" {alias}"));
+        }
+    }
+
+    let number = |key: &str| node.get(key).and_then(Value::as_f64).unwrap_or_default();
+    label.push_str(&format!(
+        "  (cost={:.2}..{:.2} rows={} width={})",
+        number("Startup Cost"),
+        number("Total Cost"),
+        number("Plan Rows"),
+        number("Plan Width"),
+    ));
+    if node.get("Actual Total Time").is_some() {
+        label.push_str(&format!(
+            " (actual time={:.3}..{:.3} rows={} loops={})",
+            number("Actual Startup Time"),
+            number("Actual Total Time"),
+            number("Actual Rows"),
+            number("Actual Loops"),
+        ));
+    }
+
+    label
+}
+
+/// Display a property of a node. Lists (such as sort keys) are separated by commas.
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(values) => values.iter().map(display).collect::<Vec<_>>().join(", "),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_render_pg_plan() {
+        let plan = serde_json::json!([{
+            "Plan": {
+                "Node Type": "Hash Join",
+                "Join Type": "Left",
+                "Startup Cost": 1.09,
+                "Total Cost": 2.21,
+                "Plan Rows": 4,
+                "Plan Width": 12,
+                "Hash Cond": "(a.id = b.a_id)",
+                "Plans": [
+                    {
+                        "Node Type": "Seq Scan",
+                        "Relation Name": "a",
+                        "Alias": "a",
+                        "Startup Cost": 0.0,
+                        "Total Cost": 1.05,
+                        "Plan Rows": 5,
+                        "Plan Width": 8,
+                        "Filter": "(id > 1)"
+                    },
+                    {
+                        "Node Type": "Hash",
+                        "Startup Cost": 1.04,
+                        "Total Cost": 1.04,
+                        "Plan Rows": 4,
+                        "Plan Width": 8,
+                        "Plans": [{
+                            "Node Type": "Index Scan",
+                            "Index Name": "b_pkey",
+                            "Relation Name": "b",
+                            "Alias": "bb",
+                            "Startup Cost": 0.0,
+                            "Total Cost": 1.04,
+                            "Plan Rows": 4,
+                            "Plan Width": 8,
+                            "Actual Startup Time": 0.01,
+                            "Actual Total Time": 0.02,
+                            "Actual Rows": 4,
+                            "Actual Loops": 1
+                        }]
+                    }
+                ]
+            },
+            "Planning Time": 0.1,
+            "Execution Time": 0.25
+        }]);
+
+        assert_eq!(
+            render_pg_plan(&plan).unwrap(),
+            [
+                "Hash Left Join  (cost=1.09..2.21 rows=4 width=12)",
+                "│  Hash Cond: (a.id = b.a_id)",
+                "├─ Seq Scan on a  (cost=0.00..1.05 rows=5 width=8)",
+                "│     Filter: (id > 1)",
+                "└─ Hash  (cost=1.04..1.04 rows=4 width=8)",
+                "   └─ Index Scan using b_pkey on b bb  (cost=0.00..1.04 rows=4 width=8) \
+                 (actual time=0.010..0.020 rows=4 loops=1)",
+                "Planning Time: 0.100 ms",
+                "Execution Time: 0.250 ms",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn can_reject_empty_plan() {
+        assert!(render_pg_plan(&serde_json::json!([])).is_err());
+    }
+}
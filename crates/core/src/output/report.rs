@@ -0,0 +1,128 @@
+use std::{
+    env,
+    io::{self, IsTerminal},
+    ops::Range,
+};
+
+use ariadne::{Color, Config, Label, Report, ReportKind, Source};
+
+const SOURCE_ID: &str = "query";
+
+/// Message pointing at a byte range of the text of a report.
+#[derive(Debug, Clone)]
+pub struct ReportLabel {
+    /// Kind of the message, which decides the color of the label.
+    pub kind: ReportKind<'static>,
+    pub range: Range<usize>,
+    pub message: String,
+}
+
+/// Render a report with the message pointing at the given byte range of the text. The label is
+/// colored like the heading of the report, unless colors are turned off.
+pub fn render(
+    kind: ReportKind<'static>,
+    text: &str,
+    range: Range<usize>,
+    message: &str,
+) -> anyhow::Result<String> {
+    let label = ReportLabel {
+        kind,
+        range,
+        message: message.to_string(),
+    };
+    render_labels(kind, text, &[label])
+}
+
+/// Render a single report with all labels on the text, so that the text is only shown once.
+pub fn render_labels(
+    kind: ReportKind,
+    text: &str,
+    labels: &[ReportLabel],
+) -> anyhow::Result<String> {
+    let config = Config::default().with_color(use_color());
+
+    let labels = labels.iter().map(|label| {
+        // The reports are indexed by character
+        let range = char_index(text, label.range.start)..char_index(text, label.range.end);
+        Label::new((SOURCE_ID, range))
+            .with_message(&label.message)
+            .with_color(kind_color(label.kind))
+    });
+
+    let mut buffer = vec![];
+    Report::build(kind, (SOURCE_ID, 0..text.chars().count()))
+        .with_config(config)
+        .with_labels(labels)
+        .finish()
+        .write((SOURCE_ID, Source::from(text)), &mut buffer)?;
+
+    let str = String::from_utf8(buffer)?;
+    Ok(str)
+}
+
+/// Get the index of the character at the given byte offset of the text.
+fn char_index(text: &str, offset: usize) -> usize {
+    text.char_indices()
+        .take_while(|(index, _)| *index < offset)
+        .count()
+}
+
+/// Color of the heading of reports of the given kind.
+fn kind_color(kind: ReportKind) -> Color {
+    match kind {
+        ReportKind::Error => Color::Red,
+        ReportKind::Warning => Color::Yellow,
+        ReportKind::Advice => Color::Fixed(147),
+        ReportKind::Custom(_, color) => color,
+    }
+}
+
+/// Returns true if output should be colored, i.e. stdout is a terminal and `NO_COLOR` isn't set.
+pub fn use_color() -> bool {
+    io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// Get the byte range of the token starting at the given zero-indexed character position, i.e.
+/// up to the next whitespace. A position past the end of the text gives the last character.
+pub fn token_range(text: &str, position: usize) -> Range<usize> {
+    let start = text
+        .char_indices()
+        .nth(position)
+        .or_else(|| text.char_indices().last())
+        .map(|(index, _)| index)
+        .unwrap_or(0);
+
+    let end = text[start..]
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| c.is_whitespace())
+        .map(|(index, _)| start + index)
+        .unwrap_or(text.len());
+
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_get_token_range() {
+        assert_eq!(token_range("SELEC 1", 0), 0..5);
+        assert_eq!(token_range("SELECT * FORM t", 9), 9..13);
+        assert_eq!(token_range("SELECT 'ä' frm t", 11), 12..15);
+    }
+
+    #[test]
+    fn can_get_char_index() {
+        assert_eq!(char_index("SELECT 'ä' frm t", 12), 11);
+        assert_eq!(char_index("SELECT 'ä' frm t", 17), 16);
+        assert_eq!(char_index("SELECT", 3), 3);
+    }
+
+    #[test]
+    fn can_get_token_range_out_of_bounds() {
+        assert_eq!(token_range("SELECT", 10), 5..6);
+        assert_eq!(token_range("", 3), 0..0);
+    }
+}
@@ -0,0 +1,527 @@
+use std::{borrow::Cow, ops::Range};
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{
+    config::{BorderStyle, Overflow},
+    output::NULL_DISPLAY,
+};
+
+/// Options used when rendering a table.
+#[derive(Debug, Clone, Default)]
+pub struct TableOptions {
+    pub border_style: BorderStyle,
+
+    /// Values wider than this (in terminal columns) are truncated. 0 disables truncation.
+    pub max_col_width: usize,
+
+    /// What is done with tables wider than max_width.
+    pub overflow: Overflow,
+
+    /// Width (in terminal columns) that the table should fit in, if any. Usually the width of the
+    /// terminal.
+    pub max_width: Option<usize>,
+}
+
+/// Horizontal alignment of the values in a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alignment {
+    Left,
+    Right,
+}
+
+/// Glyphs used to draw the borders of a table.
+struct Borders {
+    /// Line drawn between columns.
+    vertical: &'static str,
+
+    /// Character used for horizontal lines.
+    horizontal: char,
+
+    /// Left, middle and right junctions of the line above the header.
+    top: Option<[char; 3]>,
+
+    /// Left, middle and right junctions of the line between the header and the rows.
+    separator: Option<[char; 3]>,
+
+    /// Left, middle and right junctions of the line below the last row.
+    bottom: Option<[char; 3]>,
+
+    /// True if the left and right edges of the table should be drawn.
+    outer: bool,
+}
+
+impl Borders {
+    fn from_style(style: BorderStyle) -> Self {
+        match style {
+            BorderStyle::Ascii => Self {
+                vertical: "|",
+                horizontal: '-',
+                top: None,
+                separator: Some(['-', '+', '-']),
+                bottom: None,
+                outer: false,
+            },
+            BorderStyle::Unicode => Self {
+                vertical: "│",
+                horizontal: '─',
+                top: Some(['┌', '┬', '┐']),
+                separator: Some(['├', '┼', '┤']),
+                bottom: Some(['└', '┴', '┘']),
+                outer: true,
+            },
+            BorderStyle::None => Self {
+                vertical: "",
+                horizontal: ' ',
+                top: None,
+                separator: None,
+                bottom: None,
+                outer: false,
+            },
+        }
+    }
+
+    /// Create a row of cells padded to the given widths.
+    fn make_row(&self, values: &[Cow<str>], widths: &[usize], alignments: &[Alignment]) -> String {
+        let cells = values
+            .iter()
+            .zip(widths)
+            .zip(alignments)
+            .map(|((value, &width), alignment)| {
+                // The std formatting width counts chars, which doesn't match the display width of
+                // wide characters, so we need to pad manually.
+                let padding = " ".repeat(width.saturating_sub(value.width()));
+                match alignment {
+                    Alignment::Left => format!(" {value}{padding} "),
+                    Alignment::Right => format!(" {padding}{value} "),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        self.join(cells, self.vertical, self.vertical, self.vertical)
+    }
+
+    /// Create a horizontal line using the given junctions.
+    fn make_line(&self, junctions: [char; 3], widths: &[usize]) -> String {
+        let [left, middle, right] = junctions.map(String::from);
+        let segments = widths
+            .iter()
+            .map(|&width| self.horizontal.to_string().repeat(width + 2))
+            .collect::<Vec<_>>();
+
+        self.join(segments, &left, &middle, &right)
+    }
+
+    /// Width of a table with columns of the given widths.
+    fn table_width(&self, widths: &[usize]) -> usize {
+        let separator_count = match self.outer {
+            true => widths.len() + 1,
+            false => widths.len().saturating_sub(1),
+        };
+
+        widths.iter().map(|width| width + 2).sum::<usize>()
+            + separator_count * self.vertical.width()
+    }
+
+    fn join(&self, parts: Vec<String>, left: &str, middle: &str, right: &str) -> String {
+        let inner = parts.join(middle);
+        if self.outer {
+            format!("{left}{inner}{right}")
+        } else {
+            inner
+        }
+    }
+}
+
+/// Render the given columns and rows as a table. All rows are expected to contain a value for
+/// each column.
+pub fn render<'a>(
+    column_names: &[&'a str],
+    rows: &[Vec<&'a str>],
+    options: &TableOptions,
+) -> String {
+    let borders = Borders::from_style(options.border_style);
+
+    let truncate_all = |values: &[&'a str]| {
+        values
+            .iter()
+            .map(|value| match escape_control(value) {
+                Cow::Borrowed(value) => truncate(value, options.max_col_width),
+                Cow::Owned(value) => {
+                    Cow::Owned(truncate(&value, options.max_col_width).into_owned())
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    let column_names = truncate_all(column_names);
+    let rows = rows.iter().map(|row| truncate_all(row)).collect::<Vec<_>>();
+
+    let mut column_widths: Vec<usize> = column_names.iter().map(|col| col.width()).collect();
+    for row in &rows {
+        for (i, value) in row.iter().enumerate() {
+            column_widths[i] = column_widths[i].max(value.width());
+        }
+    }
+
+    let alignments = column_alignments(&rows, column_names.len());
+    let table = Table {
+        column_names,
+        rows,
+        column_widths,
+        alignments,
+    };
+
+    let all_columns = 0..table.column_names.len();
+    let max_width = match options.max_width {
+        Some(max_width) if borders.table_width(&table.column_widths) > max_width => max_width,
+        _ => return table.render(&borders, all_columns),
+    };
+
+    match options.overflow {
+        Overflow::None => table.render(&borders, all_columns),
+        Overflow::Wrap => table.render_wrapped(&borders, max_width),
+        Overflow::Expand => table.render_expanded(&borders),
+        Overflow::Truncate => table
+            .render(&borders, all_columns)
+            .lines()
+            .map(|line| truncate(line, max_width))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Truncated values of a table, and what is needed to lay them out.
+struct Table<'a> {
+    column_names: Vec<Cow<'a, str>>,
+    rows: Vec<Vec<Cow<'a, str>>>,
+    column_widths: Vec<usize>,
+    alignments: Vec<Alignment>,
+}
+
+impl Table<'_> {
+    /// Render the given range of columns as a table.
+    fn render(&self, borders: &Borders, columns: Range<usize>) -> String {
+        let column_names = &self.column_names[columns.clone()];
+        let column_widths = &self.column_widths[columns.clone()];
+        let alignments = &self.alignments[columns.clone()];
+        let header_alignments = vec![Alignment::Left; column_names.len()];
+
+        let mut lines: Vec<String> = Vec::new();
+
+        if let Some(junctions) = borders.top {
+            lines.push(borders.make_line(junctions, column_widths));
+        }
+
+        lines.push(borders.make_row(column_names, column_widths, &header_alignments));
+
+        if let Some(junctions) = borders.separator {
+            lines.push(borders.make_line(junctions, column_widths));
+        }
+
+        for row in &self.rows {
+            lines.push(borders.make_row(&row[columns.clone()], column_widths, alignments));
+        }
+
+        if let Some(junctions) = borders.bottom {
+            lines.push(borders.make_line(junctions, column_widths));
+        }
+
+        lines.join("\n")
+    }
+
+    /// Split the columns into tables that are at most max_width wide, separated by empty lines.
+    /// A column that is wider than max_width on its own gets a table of its own.
+    fn render_wrapped(&self, borders: &Borders, max_width: usize) -> String {
+        let mut tables = vec![];
+        let mut start = 0;
+        for end in 1..=self.column_widths.len() {
+            let next_fits = end < self.column_widths.len()
+                && borders.table_width(&self.column_widths[start..=end]) <= max_width;
+            if !next_fits {
+                tables.push(self.render(borders, start..end));
+                start = end;
+            }
+        }
+
+        tables.join("\n\n")
+    }
+
+    /// Render each row as a record of `column | value` lines, like the expanded display of psql.
+    fn render_expanded(&self, borders: &Borders) -> String {
+        let name_width = self
+            .column_names
+            .iter()
+            .map(|name| name.width())
+            .max()
+            .unwrap_or(0);
+        let vertical = match borders.vertical {
+            "" => " ",
+            vertical => vertical,
+        };
+
+        let mut lines: Vec<String> = Vec::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            lines.push(format!("-[ RECORD {} ]", i + 1));
+            for (name, value) in self.column_names.iter().zip(row) {
+                let padding = " ".repeat(name_width.saturating_sub(name.width()));
+                lines.push(format!("{name}{padding} {vertical} {value}"));
+            }
+        }
+
+        lines.join("\n")
+    }
+}
+
+/// Decide the alignment of each column. Columns where all non-null values are numbers are
+/// right-aligned, all other columns are left-aligned.
+fn column_alignments(rows: &[Vec<Cow<str>>], column_count: usize) -> Vec<Alignment> {
+    (0..column_count)
+        .map(|i| {
+            let mut values = rows
+                .iter()
+                .map(|row| &row[i])
+                .filter(|value| value.as_ref() != NULL_DISPLAY)
+                .peekable();
+
+            if values.peek().is_some() && values.all(|value| is_number(value)) {
+                Alignment::Right
+            } else {
+                Alignment::Left
+            }
+        })
+        .collect()
+}
+
+/// Returns true if the value looks like a number. Special floating point values such as NaN and
+/// inf are not considered numbers since they are more likely to be text.
+fn is_number(value: &str) -> bool {
+    value.parse::<f64>().is_ok() && value.chars().any(|c| c.is_ascii_digit())
+}
+
+/// Escape control characters (such as line breaks) in the value so that it stays on one line,
+/// e.g. `\n`. Other characters, including quotes and backslashes, are kept as they are.
+fn escape_control(value: &str) -> Cow<'_, str> {
+    if !value.chars().any(char::is_control) {
+        return Cow::Borrowed(value);
+    }
+
+    let escaped = value
+        .chars()
+        .map(|c| match c.is_control() {
+            true => c.escape_debug().to_string(),
+            false => c.to_string(),
+        })
+        .collect();
+
+    Cow::Owned(escaped)
+}
+
+/// Truncate the value to a display width of at most max_width, replacing the end with an
+/// ellipsis. A max_width of 0 disables truncation.
+fn truncate(value: &str, max_width: usize) -> Cow<'_, str> {
+    if max_width == 0 || value.width() <= max_width {
+        return Cow::Borrowed(value);
+    }
+
+    // Leave room for the ellipsis
+    let mut remaining = max_width - 1;
+    let mut truncated = value
+        .chars()
+        .take_while(|c| {
+            let char_width = c.width().unwrap_or(0);
+            if char_width > remaining {
+                return false;
+            }
+
+            remaining -= char_width;
+            true
+        })
+        .collect::<String>();
+    truncated.push('…');
+
+    Cow::Owned(truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_with_style(border_style: BorderStyle) -> String {
+        let rows = vec![vec!["John Doe", "30"]];
+        let options = TableOptions {
+            border_style,
+            ..Default::default()
+        };
+        render(&["name", "age"], &rows, &options)
+    }
+
+    #[test]
+    fn can_render_ascii_table() {
+        assert_eq!(
+            render_with_style(BorderStyle::Ascii),
+            [" name     | age ", "----------+-----", " John Doe |  30 "].join("\n")
+        );
+    }
+
+    #[test]
+    fn can_render_unicode_table() {
+        assert_eq!(
+            render_with_style(BorderStyle::Unicode),
+            [
+                "┌──────────┬─────┐",
+                "│ name     │ age │",
+                "├──────────┼─────┤",
+                "│ John Doe │  30 │",
+                "└──────────┴─────┘",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn can_render_table_without_borders() {
+        assert_eq!(
+            render_with_style(BorderStyle::None),
+            [" name      age ", " John Doe   30 "].join("\n")
+        );
+    }
+
+    fn render_overflowing(overflow: Overflow, max_width: usize) -> String {
+        let rows = vec![vec!["1", "John Doe", "john@example.com"]];
+        let options = TableOptions {
+            overflow,
+            max_width: Some(max_width),
+            ..Default::default()
+        };
+        render(&["id", "name", "email"], &rows, &options)
+    }
+
+    #[test]
+    fn can_render_table_that_fits() {
+        assert_eq!(
+            render_overflowing(Overflow::Expand, 40),
+            render_overflowing(Overflow::None, 40)
+        );
+    }
+
+    #[test]
+    fn can_wrap_wide_table() {
+        assert_eq!(
+            render_overflowing(Overflow::Wrap, 20),
+            [
+                " id | name     ",
+                "----+----------",
+                "  1 | John Doe ",
+                "",
+                " email            ",
+                "------------------",
+                " john@example.com ",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn can_expand_wide_table() {
+        assert_eq!(
+            render_overflowing(Overflow::Expand, 20),
+            [
+                "-[ RECORD 1 ]",
+                "id    | 1",
+                "name  | John Doe",
+                "email | john@example.com",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn can_truncate_wide_table() {
+        assert_eq!(
+            render_overflowing(Overflow::Truncate, 20),
+            [
+                " id | name     | em…",
+                "----+----------+---…",
+                "  1 | John Doe | jo…",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn can_truncate_on_char_boundaries() {
+        assert_eq!(truncate("åäöåäö", 4), "åäö…");
+        assert_eq!(truncate("åäö", 3), "åäö");
+        assert_eq!(truncate("åäöåäö", 0), "åäöåäö");
+    }
+
+    #[test]
+    fn can_right_align_numeric_columns() {
+        let rows = vec![
+            vec!["1", "apple", "1.5", "10"],
+            vec!["200", "banana", NULL_DISPLAY, "x"],
+            vec!["-3", "42", "1e3", "20"],
+        ];
+        let output = render(
+            &["id", "name", "price", "mixed"],
+            &rows,
+            &Default::default(),
+        );
+
+        assert_eq!(
+            output,
+            [
+                " id  | name   | price  | mixed ",
+                "-----+--------+--------+-------",
+                "   1 | apple  |    1.5 | 10    ",
+                " 200 | banana | <NULL> | x     ",
+                "  -3 | 42     |    1e3 | 20    ",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn can_escape_control_characters() {
+        let rows = vec![vec!["a\nb\t\"c\" \\d"]];
+        assert_eq!(
+            render(&["text"], &rows, &Default::default()),
+            [" text         ", "--------------", " a\\nb\\t\"c\" \\d "].join("\n")
+        );
+    }
+
+    #[test]
+    fn doesnt_treat_special_floats_as_numbers() {
+        assert!(is_number("-1.5"));
+        assert!(!is_number("NaN"));
+        assert!(!is_number("inf"));
+    }
+
+    #[test]
+    fn can_truncate_wide_characters() {
+        assert_eq!(truncate("東京都庁", 4), "東…");
+        assert_eq!(truncate("東京", 4), "東京");
+    }
+
+    #[test]
+    fn can_align_wide_characters() {
+        let rows = vec![vec!["東京", "🦀"], vec!["Oslo", "x"]];
+        let options = TableOptions {
+            border_style: BorderStyle::Unicode,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render(&["city", "icon"], &rows, &options),
+            [
+                "┌──────┬──────┐",
+                "│ city │ icon │",
+                "├──────┼──────┤",
+                "│ 東京 │ 🦀   │",
+                "│ Oslo │ x    │",
+                "└──────┴──────┘",
+            ]
+            .join("\n")
+        );
+    }
+}
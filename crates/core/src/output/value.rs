@@ -0,0 +1,371 @@
+use std::fmt::Write;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+#[cfg(feature = "decimal")]
+use bigdecimal::BigDecimal;
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, Utc,
+};
+
+use crate::config::{BinaryFormat, BoolFormat, Config};
+
+/// Binary values longer than this (in bytes) are truncated.
+const MAX_BINARY_DISPLAY_BYTES: usize = 32;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const TIME_FORMAT: &str = "%H:%M:%S%.f";
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+/// Options used when formatting the values of query results.
+#[derive(Debug, Clone, Default)]
+pub struct ValueOptions {
+    pub binary_format: BinaryFormat,
+
+    /// chrono format string used for timestamps. None means ISO-8601.
+    pub timestamp_format: Option<String>,
+
+    pub bool_format: BoolFormat,
+}
+
+impl ValueOptions {
+    pub fn from_config(config: &Config) -> Self {
+        ValueOptions {
+            binary_format: config.get_binary_format(),
+            timestamp_format: config
+                .get_timestamp_format()
+                .map(|format| format.to_string()),
+            bool_format: config.get_bool_format(),
+        }
+    }
+}
+
+/// Kind of a date/time database type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateTimeKind {
+    Date,
+    Time,
+    Timestamp,
+    TimestampTz,
+}
+
+/// Date/time value decoded from a database value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateTimeValue {
+    Date(NaiveDate),
+    Time(NaiveTime),
+    Timestamp(NaiveDateTime),
+    TimestampTz(DateTime<Utc>),
+}
+
+/// Value of a query result, decoded from the database value. It's formatted for display using
+/// `Value::format`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Float(f64),
+    #[cfg(feature = "decimal")]
+    Decimal(BigDecimal),
+    Bool(bool),
+    Text(String),
+    Bytes(Vec<u8>),
+    DateTime(DateTimeValue),
+    Json(serde_json::Value),
+    /// A value of a type without its own variant (such as UUID or INTERVAL), as formatted by the
+    /// driver. Also used for values that couldn't be decoded.
+    Other(String),
+}
+
+impl Value {
+    /// Format the value for display. Returns None for NULL, which is displayed differently
+    /// depending on the output format. Text is returned as it is, so it's up to the output format
+    /// to escape it.
+    pub fn format(&self, options: &ValueOptions) -> Option<String> {
+        let formatted = match self {
+            Self::Null => return None,
+            Self::Int(value) => value.to_string(),
+            Self::Float(value) => format!("{value:?}"),
+            #[cfg(feature = "decimal")]
+            Self::Decimal(value) => format_decimal(value),
+            Self::Bool(value) => format_bool(*value, options.bool_format),
+            Self::Text(value) => value.clone(),
+            Self::Bytes(bytes) => format_binary(bytes, options.binary_format),
+            Self::DateTime(value) => format_datetime(value, options.timestamp_format.as_deref()),
+            Self::Json(value) => value.to_string(),
+            Self::Other(value) => value.clone(),
+        };
+
+        Some(formatted)
+    }
+
+    /// Convert into a JSON value. Numbers, booleans, text and JSON keep their type, decimals are
+    /// strings so that no precision is lost, and other values are formatted like for display.
+    pub fn to_json(&self, options: &ValueOptions) -> serde_json::Value {
+        match self {
+            Self::Null => serde_json::Value::Null,
+            Self::Int(value) => (*value).into(),
+            // NaN and infinity have no JSON representation
+            Self::Float(value) => serde_json::Number::from_f64(*value)
+                .map(serde_json::Value::Number)
+                .unwrap_or_else(|| format!("{value:?}").into()),
+            Self::Bool(value) => (*value).into(),
+            Self::Text(value) => value.as_str().into(),
+            Self::Json(value) => value.clone(),
+            _ => self.format(options).unwrap_or_default().into(),
+        }
+    }
+}
+
+/// Returns true if values of the database type (as named by the driver) are raw bytes.
+pub fn is_binary_type(type_name: &str) -> bool {
+    matches!(
+        type_name.to_uppercase().as_str(),
+        "BYTEA" | "BLOB" | "TINYBLOB" | "MEDIUMBLOB" | "LONGBLOB" | "BINARY" | "VARBINARY"
+    )
+}
+
+/// Returns true if values of the database type (as named by the driver) are booleans.
+pub fn is_bool_type(type_name: &str) -> bool {
+    matches!(type_name.to_uppercase().as_str(), "BOOL" | "BOOLEAN")
+}
+
+/// Format a boolean value.
+pub fn format_bool(value: bool, format: BoolFormat) -> String {
+    let (true_text, false_text) = match format {
+        BoolFormat::TrueFalse => ("true", "false"),
+        BoolFormat::OneZero => ("1", "0"),
+        BoolFormat::TF => ("t", "f"),
+    };
+
+    if value { true_text } else { false_text }.to_string()
+}
+
+/// Returns true if values of the database type (as named by the driver) are exact decimals.
+pub fn is_decimal_type(type_name: &str) -> bool {
+    matches!(type_name.to_uppercase().as_str(), "NUMERIC" | "DECIMAL")
+}
+
+/// Format a decimal with all of its digits, without using exponential notation.
+#[cfg(feature = "decimal")]
+pub fn format_decimal(value: &BigDecimal) -> String {
+    value.to_plain_string()
+}
+
+/// Returns the kind of date/time the database type (as named by the driver) holds, or None if it
+/// isn't a date/time type.
+pub fn datetime_kind(type_name: &str) -> Option<DateTimeKind> {
+    match type_name.to_uppercase().as_str() {
+        "DATE" => Some(DateTimeKind::Date),
+        "TIME" => Some(DateTimeKind::Time),
+        "TIMESTAMP" | "DATETIME" => Some(DateTimeKind::Timestamp),
+        "TIMESTAMPTZ" => Some(DateTimeKind::TimestampTz),
+        _ => None,
+    }
+}
+
+/// Format a date/time value as ISO-8601, or using timestamp_format if the value is a timestamp.
+/// Falls back to ISO-8601 if timestamp_format is invalid or can't be applied to the value (such
+/// as `%z` for a timestamp without time zone).
+pub fn format_datetime(value: &DateTimeValue, timestamp_format: Option<&str>) -> String {
+    let custom = timestamp_format.and_then(|format| {
+        let items = StrftimeItems::new(format).collect::<Vec<_>>();
+        if items.iter().any(|item| matches!(item, Item::Error)) {
+            return None;
+        }
+
+        let mut output = String::new();
+        let result = match value {
+            DateTimeValue::Timestamp(timestamp) => {
+                write!(output, "{}", timestamp.format_with_items(items.iter()))
+            }
+            DateTimeValue::TimestampTz(timestamp) => {
+                write!(output, "{}", timestamp.format_with_items(items.iter()))
+            }
+            DateTimeValue::Date(_) | DateTimeValue::Time(_) => return None,
+        };
+        result.ok().map(|_| output)
+    });
+
+    custom.unwrap_or_else(|| match value {
+        DateTimeValue::Date(date) => date.format(DATE_FORMAT).to_string(),
+        DateTimeValue::Time(time) => time.format(TIME_FORMAT).to_string(),
+        DateTimeValue::Timestamp(timestamp) => timestamp.format(TIMESTAMP_FORMAT).to_string(),
+        DateTimeValue::TimestampTz(timestamp) => {
+            timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+        }
+    })
+}
+
+/// Format a binary value. Values longer than `MAX_BINARY_DISPLAY_BYTES` are truncated and
+/// followed by their length.
+pub fn format_binary(bytes: &[u8], format: BinaryFormat) -> String {
+    let len_suffix = format!("({} bytes)", bytes.len());
+    let (shown, truncated) = if bytes.len() > MAX_BINARY_DISPLAY_BYTES {
+        (&bytes[..MAX_BINARY_DISPLAY_BYTES], true)
+    } else {
+        (bytes, false)
+    };
+
+    let encoded = match format {
+        BinaryFormat::Hex => {
+            let hex = shown
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect::<String>();
+            format!("\\x{hex}")
+        }
+        BinaryFormat::Base64 => STANDARD.encode(shown),
+        BinaryFormat::Len => return len_suffix,
+    };
+
+    if truncated {
+        format!("{encoded}... {len_suffix}")
+    } else {
+        encoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_format_binary() {
+        let bytes = [0xde, 0xad, 0xbe, 0xef];
+        assert_eq!(format_binary(&bytes, BinaryFormat::Hex), "\\xdeadbeef");
+        assert_eq!(format_binary(&bytes, BinaryFormat::Base64), "3q2+7w==");
+        assert_eq!(format_binary(&bytes, BinaryFormat::Len), "(4 bytes)");
+        assert_eq!(format_binary(&[], BinaryFormat::Hex), "\\x");
+    }
+
+    #[test]
+    fn can_truncate_long_binary() {
+        let bytes = [0xab; 40];
+        assert_eq!(
+            format_binary(&bytes, BinaryFormat::Hex),
+            format!("\\x{}... (40 bytes)", "ab".repeat(32))
+        );
+    }
+
+    fn timestamp() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, 1)
+            .unwrap()
+            .and_hms_milli_opt(13, 5, 9, 250)
+            .unwrap()
+    }
+
+    #[test]
+    fn can_format_datetime_as_iso_8601() {
+        let timestamp = timestamp();
+        assert_eq!(
+            format_datetime(&DateTimeValue::Date(timestamp.date()), None),
+            "2024-03-01"
+        );
+        assert_eq!(
+            format_datetime(&DateTimeValue::Time(timestamp.time()), None),
+            "13:05:09.250"
+        );
+        assert_eq!(
+            format_datetime(&DateTimeValue::Timestamp(timestamp), None),
+            "2024-03-01T13:05:09.250"
+        );
+        assert_eq!(
+            format_datetime(&DateTimeValue::TimestampTz(timestamp.and_utc()), None),
+            "2024-03-01T13:05:09.250Z"
+        );
+    }
+
+    #[test]
+    fn can_format_timestamp_with_custom_format() {
+        let timestamp = timestamp();
+        assert_eq!(
+            format_datetime(&DateTimeValue::Timestamp(timestamp), Some("%d/%m/%Y %H:%M")),
+            "01/03/2024 13:05"
+        );
+        // Time zone on a timestamp without one
+        assert_eq!(
+            format_datetime(&DateTimeValue::Timestamp(timestamp), Some("%H:%M %z")),
+            "2024-03-01T13:05:09.250"
+        );
+        // Invalid format
+        assert_eq!(
+            format_datetime(&DateTimeValue::Timestamp(timestamp), Some("%Q")),
+            "2024-03-01T13:05:09.250"
+        );
+    }
+
+    #[test]
+    fn can_format_bool_as_true_false() {
+        assert_eq!(format_bool(true, BoolFormat::TrueFalse), "true");
+        assert_eq!(format_bool(false, BoolFormat::TrueFalse), "false");
+    }
+
+    #[test]
+    fn can_format_bool_as_one_zero() {
+        assert_eq!(format_bool(true, BoolFormat::OneZero), "1");
+        assert_eq!(format_bool(false, BoolFormat::OneZero), "0");
+    }
+
+    #[test]
+    fn can_format_bool_as_t_f() {
+        assert_eq!(format_bool(true, BoolFormat::TF), "t");
+        assert_eq!(format_bool(false, BoolFormat::TF), "f");
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn can_format_decimal_without_precision_loss() {
+        use std::str::FromStr;
+
+        for text in [
+            "0.10",
+            "0.0000001",
+            "-42.000",
+            "123456789012345678901234567890.123456789012345678900",
+        ] {
+            let value = BigDecimal::from_str(text).unwrap();
+            assert_eq!(format_decimal(&value), text);
+        }
+    }
+
+    #[test]
+    fn can_format_values() {
+        let options = ValueOptions::default();
+        assert_eq!(Value::Null.format(&options), None);
+        assert_eq!(Value::Int(-3).format(&options).unwrap(), "-3");
+        assert_eq!(Value::Float(1.0).format(&options).unwrap(), "1.0");
+        assert_eq!(Value::Bool(false).format(&options).unwrap(), "false");
+        assert_eq!(
+            Value::Text("a\n\"b\\".to_string())
+                .format(&options)
+                .unwrap(),
+            "a\n\"b\\"
+        );
+        assert_eq!(Value::Bytes(vec![0xff]).format(&options).unwrap(), "\\xff");
+    }
+
+    #[test]
+    fn can_convert_values_to_json() {
+        let options = ValueOptions::default();
+        assert_eq!(Value::Null.to_json(&options), serde_json::Value::Null);
+        assert_eq!(Value::Int(3).to_json(&options), serde_json::json!(3));
+        assert_eq!(Value::Float(1.5).to_json(&options), serde_json::json!(1.5));
+        assert_eq!(Value::Float(f64::NAN).to_json(&options), "NaN");
+        assert_eq!(Value::Bool(true).to_json(&options), serde_json::json!(true));
+        assert_eq!(Value::Text("a\nb".to_string()).to_json(&options), "a\nb");
+        assert_eq!(Value::Bytes(vec![0xff]).to_json(&options), "\\xff");
+        assert_eq!(
+            Value::Json(serde_json::json!({"a": [1]})).to_json(&options),
+            serde_json::json!({"a": [1]})
+        );
+    }
+
+    #[test]
+    fn can_detect_binary_types() {
+        assert!(is_binary_type("BYTEA"));
+        assert!(is_binary_type("blob"));
+        assert!(is_binary_type("VARBINARY"));
+        assert!(!is_binary_type("TEXT"));
+    }
+}
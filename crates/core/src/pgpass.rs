@@ -0,0 +1,95 @@
+use std::{env, fs, path::PathBuf};
+
+/// Placeholder matching any value in a password file field.
+const WILDCARD: &str = "*";
+
+/// Host that matches connections over a Unix socket, like in libpq.
+pub const SOCKET_HOST: &str = "localhost";
+
+/// Find the password for the connection in the password file given by `PGPASSFILE`, or
+/// `~/.pgpass` if it isn't set. Returns None if there is no password file or no line in it
+/// matches. See https://www.postgresql.org/docs/current/libpq-pgpass.html for the format.
+pub fn find_password(host: &str, port: u16, database: &str, user: &str) -> Option<String> {
+    let contents = fs::read_to_string(password_file()?).ok()?;
+    find_in(&contents, host, port, database, user)
+}
+
+fn password_file() -> Option<PathBuf> {
+    match env::var_os("PGPASSFILE") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(dirs::home_dir()?.join(".pgpass")),
+    }
+}
+
+/// Find the password of the first line matching the connection.
+fn find_in(contents: &str, host: &str, port: u16, database: &str, user: &str) -> Option<String> {
+    let port = port.to_string();
+    let wanted = [host, port.as_str(), database, user];
+
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(parse_line)
+        .find(|fields| {
+            fields
+                .iter()
+                .zip(wanted)
+                .all(|(field, wanted)| field == WILDCARD || field == wanted)
+        })
+        .map(|[_, _, _, _, password]| password)
+}
+
+/// Split a line into its host, port, database, user and password fields. Backslashes escape
+/// `:` and `\`. Returns None if the line doesn't have enough fields.
+fn parse_line(line: &str) -> Option<[String; 5]> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => fields.last_mut()?.extend(chars.next()),
+            // Any colons in the password are part of it
+            ':' if fields.len() < 5 => fields.push(String::new()),
+            c => fields.last_mut()?.push(c),
+        }
+    }
+
+    fields.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_parse_escaped_fields() {
+        assert_eq!(
+            parse_line(r"db\:1:5432:*:me:pass\\word").unwrap(),
+            ["db:1", "5432", "*", "me", r"pass\word"]
+        );
+        assert_eq!(parse_line("host:5432:db:me:a:b").unwrap()[4], "a:b");
+        assert!(parse_line("host:5432:db").is_none());
+    }
+
+    #[test]
+    fn can_find_first_matching_password() {
+        let contents = "\
+# comment
+prod:5432:app:me:prod_password
+*:5432:*:me:fallback_password
+localhost:*:app:*:local_password
+";
+        assert_eq!(
+            find_in(contents, "prod", 5432, "app", "me").as_deref(),
+            Some("prod_password")
+        );
+        assert_eq!(
+            find_in(contents, "staging", 5432, "app", "me").as_deref(),
+            Some("fallback_password")
+        );
+        assert_eq!(
+            find_in(contents, "localhost", 5433, "app", "you").as_deref(),
+            Some("local_password")
+        );
+        assert_eq!(find_in(contents, "staging", 5433, "app", "me"), None);
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Tables and columns of the connected database, used for completion while no LSP server is
+/// available.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schema {
+    /// Columns of each table (and view), in the order they were defined.
+    tables: BTreeMap<String, Vec<String>>,
+}
+
+impl Schema {
+    /// Create a schema from (table, column) pairs.
+    pub fn from_columns(columns: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut tables = BTreeMap::<String, Vec<String>>::new();
+        for (table, column) in columns {
+            tables.entry(table).or_default().push(column);
+        }
+
+        Schema { tables }
+    }
+
+    pub fn tables(&self) -> impl Iterator<Item = &str> {
+        self.tables.keys().map(String::as_str)
+    }
+
+    /// Columns of the table, which is matched case-insensitively like unquoted identifiers.
+    pub fn columns(&self, table: &str) -> Option<&[String]> {
+        self.tables
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(table))
+            .map(|(_, columns)| columns.as_slice())
+    }
+
+    /// Distinct names of the columns of all tables.
+    pub fn all_columns(&self) -> BTreeSet<&str> {
+        self.tables.values().flatten().map(String::as_str).collect()
+    }
+
+    pub fn table_count(&self) -> usize {
+        self.tables.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Schema {
+        Schema::from_columns(
+            [
+                ("users", "id"),
+                ("users", "name"),
+                ("orders", "id"),
+                ("orders", "user_id"),
+            ]
+            .map(|(table, column)| (table.to_string(), column.to_string())),
+        )
+    }
+
+    #[test]
+    fn can_group_columns_by_table() {
+        let schema = schema();
+        assert_eq!(schema.tables().collect::<Vec<_>>(), vec!["orders", "users"]);
+        assert_eq!(
+            schema.columns("USERS"),
+            Some(["id".to_string(), "name".to_string()].as_slice())
+        );
+        assert_eq!(schema.columns("missing"), None);
+        assert_eq!(
+            schema.all_columns().into_iter().collect::<Vec<_>>(),
+            vec!["id", "name", "user_id"]
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::{fs, path::Path};
+
+use anyhow::{anyhow, Context};
+
+use crate::{config::OutputFormat, db_client::DbClient, error::SqlFriendError, sql};
+
+/// Output produced while running a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptOutput {
+    /// Formatted result of a statement.
+    Result(String),
+
+    /// A statement failed but the script continues.
+    Error(String),
+}
+
+/// Read the SQL file at path (relative to the current working directory) and run it. See
+/// `run_script`.
+pub async fn run_file(
+    db_client: &DbClient,
+    path: &Path,
+    format: OutputFormat,
+    continue_on_error: bool,
+    output: impl FnMut(ScriptOutput) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let sql = fs::read_to_string(path)
+        .with_context(|| format!("failed to read SQL file {}", path.display()))?;
+
+    run_script(
+        db_client,
+        &path.display().to_string(),
+        &sql,
+        format,
+        continue_on_error,
+        output,
+    )
+    .await
+}
+
+/// Split the SQL into statements and execute them in order, passing the results to output. The
+/// source is used to identify the SQL in error messages. Statements that change data fail on
+/// read-only connections unless they start with `FORCE`, like in the REPL.
+///
+/// Execution stops at the first failing statement unless continue_on_error is set, in which case
+/// the failure is passed to output and an error is returned after all statements have run.
+pub async fn run_script(
+    db_client: &DbClient,
+    source: &str,
+    sql: &str,
+    format: OutputFormat,
+    continue_on_error: bool,
+    mut output: impl FnMut(ScriptOutput) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut failures = 0;
+
+    let dialect = db_client.dialect().await;
+    for (index, statement) in sql::split_statements(sql, dialect).into_iter().enumerate() {
+        match run_statement(db_client, statement, format).await {
+            Ok(Some(result)) => output(ScriptOutput::Result(result))?,
+            Ok(None) => {}
+            Err(e) => {
+                let msg = format!(
+                    "{source}: statement {} failed: {e:#}\n{statement}",
+                    index + 1
+                );
+                if !continue_on_error {
+                    return Err(anyhow!(msg));
+                }
+
+                failures += 1;
+                output(ScriptOutput::Error(msg))?;
+            }
+        }
+    }
+
+    if failures > 0 {
+        return Err(anyhow!("{source}: {failures} statement(s) failed"));
+    }
+
+    Ok(())
+}
+
+/// Run the statement, unless it changes data on a read-only connection and doesn't start with
+/// `FORCE`.
+async fn run_statement(
+    db_client: &DbClient,
+    statement: &str,
+    format: OutputFormat,
+) -> Result<Option<String>, SqlFriendError> {
+    let (statement, forced) = sql::strip_force_keyword(statement);
+    if forced {
+        return db_client
+            .run_forced(db_client.fetch_all_formatted(statement, format))
+            .await;
+    }
+
+    db_client.ensure_writable(statement).await?;
+    db_client.fetch_all_formatted(statement, format).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Connection;
+
+    #[tokio::test]
+    async fn can_refuse_writes_on_read_only_connection() {
+        DbClient::initialize();
+        let db_client = DbClient::default();
+        let connection = Connection {
+            read_only: Some(true),
+            ..Connection::sqlite_for_test("read_only", ":memory:")
+        };
+        db_client.connect(connection, None).await.unwrap();
+
+        let run = |sql| {
+            run_script(
+                &db_client,
+                "test",
+                sql,
+                OutputFormat::Csv,
+                false,
+                |_| Ok(()),
+            )
+        };
+        let err = run("SELECT 1; CREATE TABLE t (id INTEGER)")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("connection is read-only"));
+        run("FORCE CREATE TABLE t (id INTEGER)").await.unwrap();
+
+        // FORCE only applies to the statement it precedes
+        let err = run("FORCE SELECT 1; DROP TABLE t").await.unwrap_err();
+        assert!(err.to_string().contains("statement 2 failed"));
+        assert!(err.to_string().contains("connection is read-only"));
+    }
+}
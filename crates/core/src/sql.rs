@@ -0,0 +1,741 @@
+use std::fmt::Display;
+
+/// Kind of a segment of SQL text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// Anything outside of literals and comments.
+    Code,
+    /// A single-quoted string, double-quoted identifier or dollar-quoted string (postgres),
+    /// including the quotes.
+    Quoted,
+    /// A `--` line comment (without the line ending) or a `/* */` block comment.
+    Comment,
+}
+
+/// A part of SQL text, as split by `scan`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment<'a> {
+    pub kind: SegmentKind,
+    pub text: &'a str,
+
+    /// False if the text ended before the closing quote or `*/` was found.
+    pub closed: bool,
+}
+
+/// SQL dialect of a database, as far as it affects how literals are scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dialect {
+    /// Literals as in standard SQL, used by sqlite.
+    #[default]
+    Standard,
+    /// Backslashes escape the next character in quoted strings.
+    MySql,
+    /// Dollar-quoted strings (`$$...$$` or `$tag$...$tag$`) are literals as well, and backslashes
+    /// escape the next character in `E'...'` strings.
+    Postgres,
+}
+
+/// Split SQL text into code, quoted literals and comments.
+pub fn scan(sql: &str, dialect: Dialect) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut code_start = 0;
+    let mut chars = sql.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let next = chars.peek().map(|(_, next)| *next);
+        let (kind, closing) = match (c, next) {
+            ('\'', _) => (SegmentKind::Quoted, "'"),
+            ('"', _) => (SegmentKind::Quoted, "\""),
+            ('-', Some('-')) => (SegmentKind::Comment, "\n"),
+            ('/', Some('*')) => (SegmentKind::Comment, "*/"),
+            ('$', _) if dialect == Dialect::Postgres => match dollar_quote_tag(sql, start) {
+                Some(tag) => (SegmentKind::Quoted, tag),
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        if code_start < start {
+            segments.push(Segment {
+                kind: SegmentKind::Code,
+                text: &sql[code_start..start],
+                closed: true,
+            });
+        }
+
+        // Skip the opening characters so that e.g. `/*/` isn't a complete comment
+        let opening_len = if kind == SegmentKind::Comment {
+            2
+        } else {
+            closing.len()
+        };
+        let body_start = start + opening_len;
+        let backslash_escapes = match dialect {
+            Dialect::Standard => false,
+            Dialect::MySql => matches!(c, '\'' | '"'),
+            Dialect::Postgres => c == '\'' && is_escape_string(&sql[..start]),
+        };
+        let (end, closed) = match find_closing(&sql[body_start..], closing, backslash_escapes) {
+            // Line comments don't include the line ending
+            Some(i) if closing == "\n" => (body_start + i, true),
+            Some(i) => (body_start + i + closing.len(), true),
+            // Line comments are also ended by the end of the text
+            None => (sql.len(), closing == "\n"),
+        };
+
+        segments.push(Segment {
+            kind,
+            text: &sql[start..end],
+            closed,
+        });
+
+        code_start = end;
+        while chars.peek().is_some_and(|(i, _)| *i < end) {
+            chars.next();
+        }
+    }
+
+    if code_start < sql.len() {
+        segments.push(Segment {
+            kind: SegmentKind::Code,
+            text: &sql[code_start..],
+            closed: true,
+        });
+    }
+
+    segments
+}
+
+/// Get the opening tag (e.g. `$$` or `$body$`) if a dollar-quoted string starts at start. `$`
+/// in an identifier or a parameter such as `$1` doesn't start one.
+fn dollar_quote_tag(sql: &str, start: usize) -> Option<&str> {
+    if sql[..start].ends_with(is_identifier_char) {
+        return None;
+    }
+
+    let tag_len = sql[start + 1..].find(|c| !is_identifier_char(c) || c == '$')?;
+    let tag = &sql[start + 1..start + 1 + tag_len];
+    if tag.starts_with(|c: char| c.is_ascii_digit()) || !sql[start + 1 + tag_len..].starts_with('$')
+    {
+        return None;
+    }
+
+    Some(&sql[start..start + tag_len + 2])
+}
+
+/// Returns true if the text before a `'` ends with the `E` prefix of a postgres string with
+/// backslash escapes.
+fn is_escape_string(before: &str) -> bool {
+    before
+        .strip_suffix(['E', 'e'])
+        .is_some_and(|before| !before.ends_with(is_identifier_char))
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+/// Find the closing text in the body of a literal or comment. If backslash_escapes is true, a
+/// backslash escapes the character after it.
+fn find_closing(body: &str, closing: &str, backslash_escapes: bool) -> Option<usize> {
+    if !backslash_escapes {
+        return body.find(closing);
+    }
+
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if body[i..].starts_with(closing) {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
+/// Returns true if the SQL ends inside a quoted literal or block comment.
+pub fn is_unclosed(sql: &str, dialect: Dialect) -> bool {
+    scan(sql, dialect)
+        .last()
+        .is_some_and(|segment| !segment.closed)
+}
+
+/// Returns true if the last code of the SQL (ignoring trailing whitespace and comments) ends
+/// with terminator. A terminator inside a literal or comment doesn't count.
+pub fn ends_with_terminator(sql: &str, terminator: &str, dialect: Dialect) -> bool {
+    let segments = scan(sql, dialect);
+    if segments.last().is_some_and(|segment| !segment.closed) {
+        return false;
+    }
+
+    segments
+        .iter()
+        .rev()
+        .filter(|segment| segment.kind != SegmentKind::Comment)
+        .find(|segment| !segment.text.trim().is_empty())
+        .is_some_and(|segment| {
+            segment.kind == SegmentKind::Code && segment.text.trim_end().ends_with(terminator)
+        })
+}
+
+/// Remove `--` line comments and `/* */` block comments from the SQL. Block comments are
+/// replaced with a space so that the surrounding tokens stay separate.
+pub fn strip_comments(sql: &str, dialect: Dialect) -> String {
+    let stripped = scan(sql, dialect)
+        .into_iter()
+        .map(|segment| match segment.kind {
+            SegmentKind::Comment if segment.text.starts_with("/*") => " ",
+            SegmentKind::Comment => "",
+            SegmentKind::Code | SegmentKind::Quoted => segment.text,
+        })
+        .collect::<String>();
+
+    stripped.trim().to_string()
+}
+
+/// Split the SQL into statements at top-level `;`. The statements are trimmed and don't include
+/// the `;`. Statements that only consist of whitespace and comments are skipped.
+pub fn split_statements(sql: &str, dialect: Dialect) -> Vec<&str> {
+    let mut ranges = Vec::new();
+    let mut statement_start = 0;
+    let mut offset = 0;
+
+    for segment in scan(sql, dialect) {
+        if segment.kind == SegmentKind::Code {
+            for (i, _) in segment.text.match_indices(';') {
+                ranges.push(statement_start..offset + i);
+                statement_start = offset + i + 1;
+            }
+        }
+        offset += segment.text.len();
+    }
+    ranges.push(statement_start..sql.len());
+
+    ranges
+        .into_iter()
+        .map(|range| &sql[range])
+        .filter(|statement| !is_blank(statement, dialect))
+        .map(|statement| statement.trim())
+        .collect()
+}
+
+/// Keyword that a statement can be prefixed with to run it even if the connection is read-only.
+pub const FORCE_KEYWORD: &str = "FORCE";
+
+/// Kind of an SQL statement, as far as it matters for guarding against changes to the database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatementKind {
+    Insert,
+    Update,
+    Delete,
+    Drop,
+    Truncate,
+    Alter,
+    Create,
+    Merge,
+    Replace,
+    Grant,
+    Revoke,
+    Copy,
+    /// Calls a procedure, which may change data.
+    Call,
+    /// Runs an anonymous code block (postgres), which may change data.
+    Do,
+    Vacuum,
+    Comment,
+    /// Anything else, such as `SELECT`.
+    Other,
+}
+
+impl StatementKind {
+    /// Returns true if statements of this kind change data or the schema.
+    pub fn is_write(&self) -> bool {
+        *self != Self::Other
+    }
+
+    /// Returns true if statements of this kind can remove or overwrite data.
+    pub fn is_destructive(&self) -> bool {
+        matches!(
+            self,
+            Self::Update | Self::Delete | Self::Drop | Self::Truncate
+        )
+    }
+}
+
+impl Display for StatementKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keyword = match self {
+            Self::Insert => "INSERT",
+            Self::Update => "UPDATE",
+            Self::Delete => "DELETE",
+            Self::Drop => "DROP",
+            Self::Truncate => "TRUNCATE",
+            Self::Alter => "ALTER",
+            Self::Create => "CREATE",
+            Self::Merge => "MERGE",
+            Self::Replace => "REPLACE",
+            Self::Grant => "GRANT",
+            Self::Revoke => "REVOKE",
+            Self::Copy => "COPY",
+            Self::Call => "CALL",
+            Self::Do => "DO",
+            Self::Vacuum => "VACUUM",
+            Self::Comment => "COMMENT",
+            Self::Other => "other",
+        };
+        write!(f, "{keyword}")
+    }
+}
+
+/// Classify the statement by its first keyword. Statements starting with `WITH` are classified
+/// by the first data-modifying keyword in them, if any.
+pub fn classify(statement: &str, dialect: Dialect) -> StatementKind {
+    let words = code_words(statement, dialect);
+    let Some(first) = words.first() else {
+        return StatementKind::Other;
+    };
+
+    if first.eq_ignore_ascii_case("WITH") {
+        return words
+            .iter()
+            .map(|word| keyword_kind(word))
+            .find(|kind| {
+                matches!(
+                    kind,
+                    StatementKind::Insert
+                        | StatementKind::Update
+                        | StatementKind::Delete
+                        | StatementKind::Merge
+                )
+            })
+            .unwrap_or(StatementKind::Other);
+    }
+
+    keyword_kind(first)
+}
+
+fn keyword_kind(word: &str) -> StatementKind {
+    match word.to_uppercase().as_str() {
+        "INSERT" => StatementKind::Insert,
+        "UPDATE" => StatementKind::Update,
+        "DELETE" => StatementKind::Delete,
+        "DROP" => StatementKind::Drop,
+        "TRUNCATE" => StatementKind::Truncate,
+        "ALTER" => StatementKind::Alter,
+        "CREATE" => StatementKind::Create,
+        "MERGE" => StatementKind::Merge,
+        "REPLACE" => StatementKind::Replace,
+        "GRANT" => StatementKind::Grant,
+        "REVOKE" => StatementKind::Revoke,
+        "COPY" => StatementKind::Copy,
+        "CALL" => StatementKind::Call,
+        "DO" => StatementKind::Do,
+        "VACUUM" => StatementKind::Vacuum,
+        "COMMENT" => StatementKind::Comment,
+        _ => StatementKind::Other,
+    }
+}
+
+/// Describe the statement (e.g. `DELETE statement without a WHERE clause`) if it can remove or
+/// overwrite data. Returns None if it can't.
+pub fn describe_destructive(statement: &str, dialect: Dialect) -> Option<String> {
+    let kind = classify(statement, dialect);
+    if !kind.is_destructive() {
+        return None;
+    }
+
+    // A WHERE in a subquery doesn't limit the rows of the statement itself
+    let has_where = top_level_code_words(statement, dialect)
+        .iter()
+        .any(|word| word.eq_ignore_ascii_case("WHERE"));
+    match kind {
+        StatementKind::Update | StatementKind::Delete if !has_where => {
+            Some(format!("{kind} statement without a WHERE clause"))
+        }
+        _ => Some(format!("{kind} statement")),
+    }
+}
+
+/// Words in the code of the SQL, i.e. outside of literals and comments.
+fn code_words(sql: &str, dialect: Dialect) -> Vec<&str> {
+    scan(sql, dialect)
+        .into_iter()
+        .filter(|segment| segment.kind == SegmentKind::Code)
+        .flat_map(|segment| {
+            segment
+                .text
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .filter(|word| !word.is_empty())
+        })
+        .collect()
+}
+
+/// Words in the code of the SQL that aren't inside parentheses (such as subqueries).
+fn top_level_code_words(sql: &str, dialect: Dialect) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut depth = 0usize;
+
+    for segment in scan(sql, dialect) {
+        if segment.kind != SegmentKind::Code {
+            continue;
+        }
+
+        let text = segment.text;
+        let mut word_start = None;
+        for (i, c) in text.char_indices() {
+            if c.is_alphanumeric() || c == '_' {
+                word_start.get_or_insert(i);
+                continue;
+            }
+
+            if let Some(start) = word_start.take() {
+                if depth == 0 {
+                    words.push(&text[start..i]);
+                }
+            }
+            match c {
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                _ => (),
+            }
+        }
+        if let (Some(start), 0) = (word_start, depth) {
+            words.push(&text[start..]);
+        }
+    }
+
+    words
+}
+
+/// Remove `FORCE_KEYWORD` from the start of the SQL. Returns the remaining SQL and true if the
+/// keyword was removed.
+pub fn strip_force_keyword(sql: &str) -> (&str, bool) {
+    let trimmed = sql.trim_start();
+    let Some((keyword, rest)) = trimmed.split_once(char::is_whitespace) else {
+        return (sql, false);
+    };
+
+    if keyword.eq_ignore_ascii_case(FORCE_KEYWORD) {
+        (rest.trim_start(), true)
+    } else {
+        (sql, false)
+    }
+}
+
+/// Get the query if the SQL is a single query that only reads data (`SELECT`, `VALUES`, or `WITH`
+/// followed by one of them), so that its results can be shown a page at a time. Returns None
+/// otherwise.
+pub fn pageable_query(sql: &str, dialect: Dialect) -> Option<&str> {
+    let [statement] = split_statements(sql, dialect)[..] else {
+        return None;
+    };
+
+    let first = code_words(statement, dialect).first()?.to_uppercase();
+    let reads = matches!(first.as_str(), "SELECT" | "VALUES")
+        || (first == "WITH" && classify(statement, dialect) == StatementKind::Other);
+    reads.then_some(statement)
+}
+
+/// Returns true if the SQL only consists of whitespace and comments.
+fn is_blank(sql: &str, dialect: Dialect) -> bool {
+    scan(sql, dialect)
+        .iter()
+        .all(|segment| segment.kind == SegmentKind::Comment || segment.text.trim().is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(sql: &str, dialect: Dialect) -> Vec<(SegmentKind, &str)> {
+        scan(sql, dialect)
+            .into_iter()
+            .map(|segment| (segment.kind, segment.text))
+            .collect()
+    }
+
+    #[test]
+    fn can_scan_literals_and_comments() {
+        assert_eq!(
+            kinds(
+                "SELECT 'a;b', \"id;\" -- x;\nFROM t /* y; */;",
+                Dialect::Standard
+            ),
+            vec![
+                (SegmentKind::Code, "SELECT "),
+                (SegmentKind::Quoted, "'a;b'"),
+                (SegmentKind::Code, ", "),
+                (SegmentKind::Quoted, "\"id;\""),
+                (SegmentKind::Code, " "),
+                (SegmentKind::Comment, "-- x;"),
+                (SegmentKind::Code, "\nFROM t "),
+                (SegmentKind::Comment, "/* y; */"),
+                (SegmentKind::Code, ";"),
+            ]
+        );
+    }
+
+    #[test]
+    fn can_detect_unclosed_segments() {
+        assert!(is_unclosed("SELECT 'abc", Dialect::Standard));
+        assert!(is_unclosed("SELECT 1 /* abc", Dialect::Standard));
+        assert!(is_unclosed("SELECT 1 /*/", Dialect::Standard));
+        assert!(!is_unclosed("SELECT 1 -- abc", Dialect::Standard));
+        assert!(!is_unclosed("SELECT 'it''s'", Dialect::Standard));
+    }
+
+    #[test]
+    fn can_strip_comments() {
+        assert_eq!(
+            strip_comments(
+                "-- header\nSELECT 1/*a*/+ 2 -- trailing\nFROM t;",
+                Dialect::Standard
+            ),
+            "SELECT 1 + 2 \nFROM t;"
+        );
+        assert_eq!(
+            strip_comments(
+                "SELECT '-- not a comment', \"/* id */\";",
+                Dialect::Standard
+            ),
+            "SELECT '-- not a comment', \"/* id */\";"
+        );
+    }
+
+    #[test]
+    fn can_split_statements() {
+        assert_eq!(
+            split_statements(
+                "SELECT 1;\n-- comment;\nSELECT ';';  ;\n/* only a comment */",
+                Dialect::Standard
+            ),
+            vec!["SELECT 1", "-- comment;\nSELECT ';'"]
+        );
+        assert_eq!(
+            split_statements("SELECT 1", Dialect::Standard),
+            vec!["SELECT 1"]
+        );
+        assert!(split_statements("  ", Dialect::Standard).is_empty());
+    }
+
+    #[test]
+    fn can_split_statements_with_dollar_quotes() {
+        let sql = "DO $$ BEGIN; END $$; SELECT $body$ a; $$ $body$, $1; SELECT a$b$ FROM t";
+        assert_eq!(
+            split_statements(sql, Dialect::Postgres),
+            vec![
+                "DO $$ BEGIN; END $$",
+                "SELECT $body$ a; $$ $body$, $1",
+                "SELECT a$b$ FROM t"
+            ]
+        );
+        assert!(is_unclosed("SELECT $x$ a; $$", Dialect::Postgres));
+        assert!(!is_unclosed("SELECT $$ a; $$", Dialect::Standard));
+    }
+
+    #[test]
+    fn can_split_statements_with_backslash_escapes() {
+        assert_eq!(
+            split_statements(r#"SELECT 'it\'s; \\', "a\";"; SELECT 2"#, Dialect::MySql),
+            vec![r#"SELECT 'it\'s; \\', "a\";""#, "SELECT 2"]
+        );
+        assert_eq!(
+            split_statements(r"SELECT 'C:\'; SELECT E'it\'s;'", Dialect::Postgres),
+            vec![r"SELECT 'C:\'", r"SELECT E'it\'s;'"]
+        );
+        assert!(is_unclosed(r"SELECT 'it\'s", Dialect::MySql));
+        assert!(!is_unclosed(r"SELECT 'C:\'", Dialect::Standard));
+    }
+
+    #[test]
+    fn can_classify_statements() {
+        assert_eq!(
+            classify("SELECT * FROM t", Dialect::Standard),
+            StatementKind::Other
+        );
+        assert_eq!(
+            classify("  delete from t", Dialect::Standard),
+            StatementKind::Delete
+        );
+        assert_eq!(
+            classify("-- DROP\n/* x */ (SELECT 'DELETE')", Dialect::Standard),
+            StatementKind::Other
+        );
+        assert_eq!(
+            classify("DROP TABLE t", Dialect::Standard),
+            StatementKind::Drop
+        );
+        assert_eq!(
+            classify(
+                "WITH old AS (SELECT 1) UPDATE t SET a = 1",
+                Dialect::Standard
+            ),
+            StatementKind::Update
+        );
+        assert_eq!(
+            classify("WITH x AS (SELECT 1) SELECT * FROM x", Dialect::Standard),
+            StatementKind::Other
+        );
+        assert_eq!(classify("", Dialect::Standard), StatementKind::Other);
+        assert_eq!(
+            classify(
+                "MERGE INTO t USING u ON t.id = u.id WHEN MATCHED THEN DELETE",
+                Dialect::Standard
+            ),
+            StatementKind::Merge
+        );
+        assert_eq!(
+            classify("REPLACE INTO t VALUES (1)", Dialect::Standard),
+            StatementKind::Replace
+        );
+        assert_eq!(
+            classify("GRANT ALL ON t TO me", Dialect::Standard),
+            StatementKind::Grant
+        );
+        assert_eq!(
+            classify("COPY t FROM '/tmp/t.csv'", Dialect::Standard),
+            StatementKind::Copy
+        );
+        assert_eq!(
+            classify("DO $$ BEGIN END $$", Dialect::Standard),
+            StatementKind::Do
+        );
+        assert_eq!(
+            classify("COMMENT ON TABLE t IS 'x'", Dialect::Standard),
+            StatementKind::Comment
+        );
+        assert!(classify("VACUUM", Dialect::Standard).is_write());
+    }
+
+    #[test]
+    fn can_describe_destructive_statements() {
+        assert_eq!(
+            describe_destructive("DELETE FROM t", Dialect::Standard).as_deref(),
+            Some("DELETE statement without a WHERE clause")
+        );
+        assert_eq!(
+            describe_destructive("update t set a = 'where'", Dialect::Standard).as_deref(),
+            Some("UPDATE statement without a WHERE clause")
+        );
+        assert_eq!(
+            describe_destructive("UPDATE t SET a = 1 WHERE id = 2", Dialect::Standard).as_deref(),
+            Some("UPDATE statement")
+        );
+        assert_eq!(
+            describe_destructive("TRUNCATE t", Dialect::Standard).as_deref(),
+            Some("TRUNCATE statement")
+        );
+        assert_eq!(
+            describe_destructive(
+                "DELETE FROM t USING (SELECT id FROM u WHERE x) AS u",
+                Dialect::Standard
+            )
+            .as_deref(),
+            Some("DELETE statement without a WHERE clause")
+        );
+        assert_eq!(
+            describe_destructive(
+                "UPDATE t SET a = (SELECT b FROM u WHERE u.id = t.id)",
+                Dialect::Standard
+            )
+            .as_deref(),
+            Some("UPDATE statement without a WHERE clause")
+        );
+        assert_eq!(
+            describe_destructive(
+                "WITH x AS (SELECT 1 WHERE true) DELETE FROM t WHERE id IN (1)",
+                Dialect::Standard
+            )
+            .as_deref(),
+            Some("DELETE statement")
+        );
+        assert_eq!(
+            describe_destructive("INSERT INTO t VALUES (1)", Dialect::Standard),
+            None
+        );
+        assert_eq!(
+            describe_destructive("SELECT * FROM t", Dialect::Standard),
+            None
+        );
+    }
+
+    #[test]
+    fn can_strip_force_keyword() {
+        assert_eq!(
+            strip_force_keyword("force DELETE FROM t"),
+            ("DELETE FROM t", true)
+        );
+        assert_eq!(
+            strip_force_keyword("DELETE FROM t"),
+            ("DELETE FROM t", false)
+        );
+        assert_eq!(strip_force_keyword("forced"), ("forced", false));
+    }
+
+    #[test]
+    fn can_find_top_level_terminator() {
+        assert!(ends_with_terminator("SELECT 1;", ";", Dialect::Standard));
+        assert!(ends_with_terminator(
+            "SELECT 1; -- trailing",
+            ";",
+            Dialect::Standard
+        ));
+        assert!(ends_with_terminator(
+            "SELECT 1; /* trailing */ ",
+            ";",
+            Dialect::Standard
+        ));
+        assert!(!ends_with_terminator(
+            "SELECT 'a;b'",
+            ";",
+            Dialect::Standard
+        ));
+        assert!(!ends_with_terminator("SELECT 'a;", ";", Dialect::Standard));
+        assert!(!ends_with_terminator(
+            "SELECT \"id;\"",
+            ";",
+            Dialect::Standard
+        ));
+        assert!(!ends_with_terminator(
+            "SELECT 1 -- trailing;",
+            ";",
+            Dialect::Standard
+        ));
+        assert!(!ends_with_terminator(
+            "SELECT 1 /* ; */",
+            ";",
+            Dialect::Standard
+        ));
+    }
+
+    #[test]
+    fn can_find_pageable_queries() {
+        assert_eq!(
+            pageable_query("SELECT * FROM t;", Dialect::Standard),
+            Some("SELECT * FROM t")
+        );
+        assert_eq!(
+            pageable_query(
+                "-- users\nwith x AS (SELECT 1) SELECT * FROM x",
+                Dialect::Standard
+            ),
+            Some("-- users\nwith x AS (SELECT 1) SELECT * FROM x")
+        );
+        assert_eq!(
+            pageable_query("VALUES (1), (2)", Dialect::Standard),
+            Some("VALUES (1), (2)")
+        );
+        assert_eq!(
+            pageable_query("SELECT 1; SELECT 2", Dialect::Standard),
+            None
+        );
+        assert_eq!(pageable_query("DELETE FROM t", Dialect::Standard), None);
+        assert_eq!(
+            pageable_query("WITH x AS (SELECT 1) DELETE FROM t", Dialect::Standard),
+            None
+        );
+        assert_eq!(pageable_query("EXPLAIN SELECT 1", Dialect::Standard), None);
+        assert_eq!(pageable_query("", Dialect::Standard), None);
+    }
+}
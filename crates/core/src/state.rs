@@ -1,10 +1,42 @@
-use std::sync::Arc;
+use std::{collections::VecDeque, path::PathBuf, sync::Arc};
 
 use tokio::sync::Mutex;
 
+use crate::{
+    config::DiagnosticLevel,
+    db_client::{Page, QueryResult},
+    history::History,
+    schema::Schema,
+};
+
 /// State contains shared application state.
 #[derive(Debug, Clone, Default)]
 pub struct State {
     /// Current text reported to LSP
     pub lsp_text: Arc<Mutex<String>>,
+
+    /// All lines submitted in the REPL.
+    pub history: Arc<Mutex<History>>,
+
+    /// File that the result of the next query should be written to instead of being output.
+    pub output_redirect: Arc<Mutex<Option<PathBuf>>>,
+
+    /// Last SQL statement that was run successfully in the REPL.
+    pub last_statement: Arc<Mutex<Option<String>>>,
+
+    /// Result of the last query run in the REPL. None if no query has been run.
+    pub last_result: Arc<Mutex<Option<QueryResult>>>,
+
+    /// Next page of the results of the last query, if it was paged and has more rows.
+    pub next_page: Arc<Mutex<Option<Page>>>,
+
+    /// Least severe LSP diagnostics that are shown.
+    pub diag_level: Arc<Mutex<DiagnosticLevel>>,
+
+    /// Last lines that LSP servers wrote to stderr, oldest first. Kept across restarts so that
+    /// the output of a crashed server can still be seen, see `/lsp log`.
+    pub lsp_stderr: Arc<Mutex<VecDeque<String>>>,
+
+    /// Tables and columns of the connected database, used for completion without an LSP server.
+    pub schema: Arc<Mutex<Schema>>,
 }
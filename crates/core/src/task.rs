@@ -1,29 +1,64 @@
-use std::{future::Future, pin::Pin};
+use std::{future::Future, ops::ControlFlow, pin::Pin, process::ExitStatus, time::Duration};
 
 use anyhow::bail;
+use tempfile::TempDir;
 use tokio::{
-    sync::{broadcast, mpsc},
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc,
+    },
     task::JoinSet,
+    time::{self, MissedTickBehavior},
 };
 
 use crate::{
-    config::{self, Connection},
+    config::{self, get_config, Connection},
+    db_client::DbClient,
+    error,
     logging::Logger,
     lsp::{client::LspClient, server::LspServer},
 };
 
 pub type Task = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
 
+/// Number of times in a row that an LSP server that exited is restarted, if enabled in the
+/// config. Counted from the last time a server was started by a command.
+const MAX_LSP_RESTARTS: u32 = 3;
+
 #[derive(Debug, Clone)]
 pub enum Command {
     /// Start the LSP server with the given settings and connection. Any existing server is killed.
-    SpawnLsp(config::LspServerType, Connection),
+    SpawnLsp(config::LspServerType, Box<Connection>),
+
+    /// Kill the LSP server without starting a new one.
+    KillLsp,
+
+    /// Periodically check that the database connection is still alive, if enabled in the config.
+    /// Any existing check is stopped.
+    StartHeartbeat,
+
+    /// Stop checking the database connection.
+    StopHeartbeat,
+
+    /// The LSP server exited without being killed. generation identifies the server, see
+    /// `LspServer::generation`.
+    LspExited {
+        generation: u64,
+        status: ExitStatus,
+        stderr_tail: Vec<String>,
+    },
+
+    /// Kill the LSP server, clean up and stop the task manager.
+    Shutdown,
 }
 
 #[derive(Debug, Clone)]
 pub enum BroadcastMessage {
     /// Kill LSP server tasks.
     KillLsp,
+
+    /// Stop the task checking the database connection.
+    StopHeartbeat,
 }
 
 /// TaskManager is responsible for executing and stopping tasks.
@@ -45,16 +80,37 @@ pub struct TaskManager {
 
     /// Used to initialize the LSP server.
     lsp_client: LspClient,
+
+    /// Used to check the database connection.
+    db_client: DbClient,
+
+    /// Temporary directory containing the configuration file of the running LSP server. Dropping
+    /// it deletes the directory.
+    lsp_config_dir: Option<TempDir>,
+
+    /// Server type and connection that the running LSP server was started with, used to restart
+    /// it if it exits.
+    lsp_spawn: Option<(config::LspServerType, Connection)>,
+
+    /// Number of times the LSP server has been restarted since it was last started by a command.
+    lsp_restarts: u32,
 }
 
 impl TaskManager {
-    pub fn new(logger: Logger, lsp_server: LspServer, lsp_client: LspClient) -> Self {
+    pub fn new(
+        logger: Logger,
+        lsp_server: LspServer,
+        lsp_client: LspClient,
+        db_client: DbClient,
+    ) -> Self {
         let set = JoinSet::new();
 
-        let (command_tx, command_rx) = mpsc::channel(1);
+        // Commands sent before the task manager runs (when connecting on startup) are buffered
+        let (command_tx, command_rx) = mpsc::channel(8);
 
-        // Receivers will be created when tasks are spawned.
-        let (broadcast_tx, _) = broadcast::channel(1);
+        // Receivers will be created when tasks are spawned. Receivers that fall behind miss the
+        // oldest messages, so there is room for the messages of a few commands in a row.
+        let (broadcast_tx, _) = broadcast::channel(16);
 
         Self {
             logger,
@@ -64,6 +120,10 @@ impl TaskManager {
             broadcast_tx,
             lsp_server,
             lsp_client,
+            db_client,
+            lsp_config_dir: None,
+            lsp_spawn: None,
+            lsp_restarts: 0,
         }
     }
 
@@ -76,7 +136,9 @@ impl TaskManager {
         loop {
             tokio::select! {
                 command = self.command_rx.recv() => {
-                    self.handle_command(command).await;
+                    if self.handle_command(command).await.is_break() {
+                        return Ok(());
+                    }
                 }
                 Some(result) = self.set.join_next() => {
                     self.handle_task(result)?;
@@ -95,22 +157,42 @@ impl TaskManager {
         server_type: config::LspServerType,
         connection: Connection,
     ) -> anyhow::Result<()> {
+        // Make sure that no requests are sent until the new server has been initialized.
+        self.close_lsp_document().await?;
+        self.lsp_client.reset().await;
+
         if self.broadcast_tx.send(BroadcastMessage::KillLsp).is_err() {
             self.logger
                 .debug("no existing LSP server running, skipping shutdown")?;
         }
+        // The configuration of the killed server is no longer needed
+        self.lsp_config_dir = None;
+        self.lsp_spawn = Some((server_type.clone(), connection.clone()));
 
-        let protocol = match server_type {
-            config::LspServerType::Sqls | config::LspServerType::SqlLs => {
-                server_type.to_stdio_cmd(std::iter::empty())
-            }
-            config::LspServerType::PgTools => {
-                let config_path = connection.clone().to_postgres_ls_config_file()?;
-                server_type.to_stdio_cmd([format!("--config-path={config_path}")])
+        let config = get_config()?;
+        let tcp_address = config.get_lsp_tcp_address()?;
+        let extra_args = match server_type {
+            config::LspServerType::PgTools if tcp_address.is_none() => {
+                let config_dir = connection.clone().to_postgres_ls_config_file()?;
+                let args = vec![format!("--config-path={}", config_dir.path().display())];
+                self.lsp_config_dir = Some(config_dir);
+                args
             }
+            _ => vec![],
         };
+        let protocol = server_type.to_protocol(
+            extra_args,
+            tcp_address,
+            config.get_lsp_command(&server_type),
+        );
 
-        let tasks = self.lsp_server.init(protocol, &mut self.broadcast_tx)?;
+        let spinner = self
+            .logger
+            .spinner(&format!("Starting the {server_type:?} LSP server..."));
+        let tasks = self
+            .lsp_server
+            .init(protocol, &mut self.broadcast_tx, self.command_tx.clone())
+            .await?;
         for task in tasks {
             self.set.spawn(task);
         }
@@ -118,6 +200,7 @@ impl TaskManager {
         self.lsp_client
             .init_lsp_server(&server_type, connection.clone())
             .await?;
+        drop(spinner);
 
         self.logger
             .standard(&format!("Connected to {}.", connection.name))?;
@@ -125,6 +208,122 @@ impl TaskManager {
         Ok(())
     }
 
+    /// Close the document on the running LSP server, if any, so that it doesn't keep a stale
+    /// view of it.
+    async fn close_lsp_document(&self) -> anyhow::Result<()> {
+        // Fails if the server is already gone, in which case there is nothing to close
+        if let Err(e) = self.lsp_client.close_document().await {
+            self.logger
+                .debug(&format!("failed to close the LSP document: {e}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Kill any running LSP server and remove its temporary files.
+    async fn kill_lsp(&mut self) -> anyhow::Result<()> {
+        self.close_lsp_document().await?;
+        self.lsp_client.reset().await;
+
+        if self.broadcast_tx.send(BroadcastMessage::KillLsp).is_err() {
+            self.logger
+                .debug("no existing LSP server running, skipping shutdown")?;
+        }
+        self.lsp_config_dir = None;
+        self.lsp_spawn = None;
+
+        Ok(())
+    }
+
+    /// Report that the LSP server exited and restart it if enabled in the config. Does nothing if
+    /// the server has been replaced since, or if it exited cleanly after being shut down.
+    async fn handle_lsp_exit(
+        &mut self,
+        generation: u64,
+        status: ExitStatus,
+        stderr_tail: Vec<String>,
+    ) -> anyhow::Result<()> {
+        if generation != self.lsp_server.generation()
+            || (status.success() && !self.lsp_client.is_initialized().await)
+        {
+            return Ok(());
+        }
+
+        self.lsp_client.reset().await;
+        // Stop the tasks talking to the server
+        let _ = self.broadcast_tx.send(BroadcastMessage::KillLsp);
+
+        let mut msg = format!("The LSP server exited unexpectedly ({status}).");
+        if !stderr_tail.is_empty() {
+            msg.push_str(" Last lines of its stderr:");
+            for line in &stderr_tail {
+                msg.push_str(&format!("\n  {line}"));
+            }
+        }
+        self.logger.warn(&msg)?;
+
+        let restart = match &self.lsp_spawn {
+            Some(spawn) if get_config()?.get_lsp_auto_restart() => {
+                (self.lsp_restarts < MAX_LSP_RESTARTS).then(|| spawn.clone())
+            }
+            _ => None,
+        };
+        match restart {
+            Some((server_type, connection)) => {
+                self.lsp_restarts += 1;
+                self.logger.standard(&format!(
+                    "Restarting the LSP server (attempt {} of {MAX_LSP_RESTARTS})...",
+                    self.lsp_restarts
+                ))?;
+                self.spawn_lsp(server_type, connection).await
+            }
+            None => {
+                self.lsp_spawn = None;
+                self.logger.warn(
+                    "Completion falls back to the schema until the LSP server is started again \
+                    (e.g. by reconnecting).",
+                )?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Stop any running connection check and start a new one if enabled in the config.
+    fn start_heartbeat(&mut self) -> anyhow::Result<()> {
+        self.stop_heartbeat();
+
+        let Some(interval_secs) = get_config()?.get_heartbeat_interval_secs() else {
+            return Ok(());
+        };
+
+        let task = heartbeat(
+            self.db_client.clone(),
+            self.logger.clone(),
+            Duration::from_secs(interval_secs),
+            self.broadcast_tx.subscribe(),
+        );
+        self.set.spawn(task);
+
+        Ok(())
+    }
+
+    fn stop_heartbeat(&mut self) {
+        // Fails if no task is running, which is fine
+        let _ = self.broadcast_tx.send(BroadcastMessage::StopHeartbeat);
+    }
+
+    /// Kill any running LSP server and remove temporary files.
+    fn shutdown(&mut self) -> anyhow::Result<()> {
+        self.stop_heartbeat();
+        if self.broadcast_tx.send(BroadcastMessage::KillLsp).is_err() {
+            self.logger
+                .debug("no existing LSP server running, skipping shutdown")?;
+        }
+        self.lsp_config_dir = None;
+
+        Ok(())
+    }
+
     /// Handle the result of joining a task in the JoinSet.
     fn handle_task(
         &mut self,
@@ -147,17 +346,36 @@ impl TaskManager {
         }
     }
 
-    /// Handle an incoming command.
-    async fn handle_command(&mut self, command: Option<Command>) {
+    /// Handle an incoming command. Returns `ControlFlow::Break` if the task manager should stop.
+    async fn handle_command(&mut self, command: Option<Command>) -> ControlFlow<()> {
         let command = match command {
             Some(cmd) => cmd,
-            None => return,
+            None => return ControlFlow::Continue(()),
         };
 
-        let result = match command {
+        let (result, control_flow) = match command {
             Command::SpawnLsp(server_type, connection) => {
-                self.spawn_lsp(server_type, connection).await
+                self.lsp_restarts = 0;
+                (
+                    self.spawn_lsp(server_type, *connection).await,
+                    ControlFlow::Continue(()),
+                )
             }
+            Command::LspExited {
+                generation,
+                status,
+                stderr_tail,
+            } => (
+                self.handle_lsp_exit(generation, status, stderr_tail).await,
+                ControlFlow::Continue(()),
+            ),
+            Command::KillLsp => (self.kill_lsp().await, ControlFlow::Continue(())),
+            Command::StartHeartbeat => (self.start_heartbeat(), ControlFlow::Continue(())),
+            Command::StopHeartbeat => {
+                self.stop_heartbeat();
+                (Ok(()), ControlFlow::Continue(()))
+            }
+            Command::Shutdown => (self.shutdown(), ControlFlow::Break(())),
         };
 
         if let Err(e) = result {
@@ -165,6 +383,63 @@ impl TaskManager {
                 .error(&format!("task manager command failed: {e:#}"))
                 .unwrap();
         }
+
+        control_flow
+    }
+}
+
+/// Task that runs a trivial query on the database every interval, and warns when the connection
+/// is lost and again once it's back. Runs until `BroadcastMessage::StopHeartbeat` is received.
+async fn heartbeat(
+    db_client: DbClient,
+    logger: Logger,
+    interval: Duration,
+    mut broadcast_rx: broadcast::Receiver<BroadcastMessage>,
+) -> anyhow::Result<()> {
+    let mut ticker = time::interval_at(time::Instant::now() + interval, interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut connected = true;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let name = match db_client.get_current_connection().await.as_ref() {
+                    Some(connection) => connection.name.clone(),
+                    None => continue,
+                };
+
+                // A connection that was dropped without being closed can take a long time to
+                // fail, so the check gives up once the next one is due
+                let result = match time::timeout(interval, db_client.ping()).await {
+                    Ok(result) => result,
+                    Err(_) => Err(anyhow::anyhow!("no response within {interval:?}")),
+                };
+
+                match result {
+                    Ok(_) if !connected => {
+                        logger.standard(&format!("Connection to {name} is back."))?;
+                        connected = true;
+                    }
+                    Err(e) if connected => {
+                        logger.warn(&format!(
+                            "Lost the connection to {name}: {}",
+                            error::format_chain(&e)
+                        ))?;
+                        connected = false;
+                    }
+                    _ => {}
+                }
+            }
+            msg = broadcast_rx.recv() => {
+                match msg {
+                    Ok(BroadcastMessage::StopHeartbeat) => return Ok(()),
+                    Ok(BroadcastMessage::KillLsp) => continue,
+                    // Only the oldest messages are dropped, so keep receiving
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(e) => bail!(e),
+                }
+            }
+        }
     }
 }
 
@@ -0,0 +1,94 @@
+use std::{process::Stdio, time::Duration};
+
+use lsp_types::ServerInfo;
+use tokio::{process::Command, time::timeout};
+
+use crate::{
+    config::{get_config, LspServerType},
+    db_client::DbClient,
+    lsp::client::LspClient,
+};
+
+/// Version of sqlfriend.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Longest time to wait for the LSP server executable to print its version.
+const LSP_VERSION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Describe the version of sqlfriend, the connected database server (if any) and the LSP server
+/// (if it can be found out), one per line.
+///
+/// The version of the LSP server is the one it sent when it was initialized. If it didn't send
+/// one (or isn't running), the executable of server_type is asked for its version instead.
+pub async fn describe(
+    db_client: &DbClient,
+    lsp_client: Option<&LspClient>,
+    server_type: Option<&LspServerType>,
+) -> Vec<String> {
+    let mut lines = vec![format!("sqlfriend {VERSION}")];
+
+    let connection_name = db_client
+        .get_current_connection()
+        .await
+        .as_ref()
+        .map(|connection| connection.name.clone());
+    if let Some(name) = connection_name {
+        match db_client.server_version().await {
+            Ok(Some(version)) => lines.push(format!("Database ({name}): {version}")),
+            Ok(None) => (),
+            Err(e) => lines.push(format!("Database ({name}): unknown ({e})")),
+        }
+    }
+
+    let server_info = match lsp_client {
+        Some(lsp_client) => lsp_client.get_server_info().await,
+        None => None,
+    };
+    let lsp_version = match server_info {
+        Some(ServerInfo {
+            name,
+            version: Some(version),
+        }) => Some(format!("{name} {version}")),
+        _ => match server_type {
+            Some(server_type) => lsp_executable_version(server_type).await,
+            None => None,
+        },
+    };
+    if let Some(version) = lsp_version {
+        lines.push(format!("LSP server: {version}"));
+    }
+
+    lines
+}
+
+/// Run the executable of the LSP server with `--version` and return the first line it prints.
+/// Returns None if it can't be run, fails or prints nothing.
+async fn lsp_executable_version(server_type: &LspServerType) -> Option<String> {
+    let overrides = get_config()
+        .map(|config| config.get_lsp_command(server_type))
+        .unwrap_or_default();
+    let executable = overrides.executable(server_type);
+    let output = Command::new(executable)
+        .arg("--version")
+        .envs(&overrides.env)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let output = timeout(LSP_VERSION_TIMEOUT, output).await.ok()?.ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?;
+
+    // Some servers only print the version number
+    if line.starts_with(|c: char| c.is_ascii_digit()) {
+        Some(format!("{} {line}", server_type.command()))
+    } else {
+        Some(line.to_string())
+    }
+}
@@ -0,0 +1,43 @@
+use std::{env, fs, io::Write};
+
+use anyhow::Context;
+use tokio::process::Command;
+
+const DEFAULT_EDITOR: &str = "vi";
+
+/// Let the user edit the text in $VISUAL or $EDITOR (or vi if neither is set). Returns the edited
+/// text, or None if the editor exited with an error.
+///
+/// The editor is run while the line editor isn't reading a line, so it has the terminal to
+/// itself until it exits.
+pub async fn edit(text: &str) -> anyhow::Result<Option<String>> {
+    let mut file = tempfile::Builder::new()
+        .prefix("sqlfriend")
+        .suffix(".sql")
+        .tempfile()?;
+    file.write_all(text.as_bytes())?;
+    file.flush()?;
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or(DEFAULT_EDITOR.to_string());
+
+    // Run through the shell so that the command can include arguments (such as `code --wait`)
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$1\""))
+        .arg("sh")
+        .arg(file.path())
+        .status()
+        .await
+        .with_context(|| format!("failed to start editor `{editor}`"))?;
+    if !status.success() {
+        return Ok(None);
+    }
+
+    // Editors may replace the file instead of writing to it, so it's read by path
+    let edited = fs::read_to_string(file.path())?;
+    Ok(Some(edited))
+}
@@ -0,0 +1,85 @@
+use std::{
+    future::Future,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::bail;
+use tokio::sync::oneshot;
+
+/// Exit code of a process that was interrupted by Ctrl-C (128 + SIGINT).
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// What Ctrl-C does while the line editor isn't reading a line (it's a key press while it is).
+enum Action {
+    /// Exit, like without a handler.
+    Exit,
+
+    /// Cancel the running statement.
+    Cancel(Option<oneshot::Sender<()>>),
+
+    /// Nothing, e.g. while the pager handles Ctrl-C itself.
+    Ignore,
+}
+
+static ACTION: Mutex<Action> = Mutex::new(Action::Exit);
+
+fn action() -> MutexGuard<'static, Action> {
+    ACTION
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Sets the action until it's dropped, after which Ctrl-C exits again.
+struct ActionGuard;
+
+impl ActionGuard {
+    fn set(new_action: Action) -> Self {
+        *action() = new_action;
+        ActionGuard
+    }
+}
+
+impl Drop for ActionGuard {
+    fn drop(&mut self) {
+        *action() = Action::Exit;
+    }
+}
+
+/// Handle Ctrl-C for the rest of the run. A signal handler can't be uninstalled once it's
+/// installed, so a single one is used that only cancels statements run by `cancellable`, and
+/// exits otherwise.
+pub fn listen() {
+    tokio::spawn(async {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            match &mut *action() {
+                Action::Exit => std::process::exit(INTERRUPTED_EXIT_CODE),
+                Action::Cancel(cancel) => {
+                    if let Some(cancel) = cancel.take() {
+                        let _ = cancel.send(());
+                    }
+                }
+                Action::Ignore => (),
+            }
+        }
+    });
+}
+
+/// Run the statement, failing if Ctrl-C is pressed before it finishes. The statement future is
+/// dropped then, which makes the driver abandon the query.
+pub async fn cancellable<T>(
+    statement: impl Future<Output = anyhow::Result<T>>,
+) -> anyhow::Result<T> {
+    let (cancel_tx, cancel_rx) = oneshot::channel();
+    let _guard = ActionGuard::set(Action::Cancel(Some(cancel_tx)));
+
+    tokio::select! {
+        result = statement => result,
+        _ = cancel_rx => bail!("query cancelled"),
+    }
+}
+
+/// Wait for the future without exiting on Ctrl-C, for programs that handle it themselves.
+pub async fn ignored<T>(future: impl Future<Output = T>) -> T {
+    let _guard = ActionGuard::set(Action::Ignore);
+    future.await
+}
@@ -1,9 +1,15 @@
+use std::{
+    io::{self, IsTerminal},
+    path::PathBuf,
+};
+
 use crate::printer::Printer;
 
-use readline::init_repl;
-use rustyline::{CompletionType, Config as RustylineConfig, EditMode, Editor};
+use clap::{ArgGroup, Parser};
+use readline::{init_repl, to_rustyline_edit_mode};
+use rustyline::{CompletionType, Config as RustylineConfig, Editor};
 use sqlfriend_core::{
-    config::get_config,
+    config::{self, get_config, OutputFormat},
     db_client::DbClient,
     logging::{Logger, Verbosity},
     lsp::{build_lsp, notification_handler::HandlerType},
@@ -11,29 +17,134 @@ use sqlfriend_core::{
     task::{TaskController, TaskManager},
 };
 
+mod editor;
+mod interrupt;
+mod oneshot;
+mod pager;
 mod printer;
 mod readline;
 
+/// LSP-powered SQL line editor and REPL.
+#[derive(Parser, Debug)]
+#[command(about, disable_version_flag = true)]
+#[command(group(ArgGroup::new("batch").args(["query", "file"])))]
+struct Args {
+    /// Config file to use instead of the one in the default config directory.
+    #[arg(long, env = "SQLFRIEND_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Config profile to use (uses sqlfriend.<profile>.toml instead of sqlfriend.toml).
+    #[arg(long, env = "SQLFRIEND_PROFILE")]
+    profile: Option<String>,
+
+    /// Saved connection to run the query, file or piped SQL on (defaults to the active
+    /// connection).
+    #[arg(long)]
+    connection: Option<String>,
+
+    /// Run the query, print the result and exit without starting the REPL.
+    #[arg(long)]
+    query: Option<String>,
+
+    /// Run the statements in the SQL file, print the results and exit without starting the REPL.
+    #[arg(long)]
+    file: Option<PathBuf>,
+
+    /// Keep running the statements in the SQL file or piped SQL after a statement fails.
+    #[arg(long)]
+    continue_on_error: bool,
+
+    /// Output format of query results (Table, Csv or Json).
+    #[arg(long, value_parser = parse_output_format)]
+    format: Option<OutputFormat>,
+
+    /// Print the version of sqlfriend and the LSP server, and of the database server of the
+    /// connection if one is given.
+    #[arg(short = 'V', long)]
+    version: bool,
+}
+
+fn parse_output_format(value: &str) -> Result<OutputFormat, String> {
+    OutputFormat::VALUES
+        .into_iter()
+        .find(|format| format!("{format:?}").eq_ignore_ascii_case(value))
+        .ok_or(format!("expected one of {:?}", OutputFormat::VALUES))
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    config::set_config_path_override(args.config);
+    config::set_profile(args.profile.as_deref())?;
+
+    let batch_result = if args.version {
+        Some(oneshot::print_version(args.connection.as_deref()).await)
+    } else if let Some(query) = &args.query {
+        Some(oneshot::run_query(args.connection.as_deref(), query, args.format).await)
+    } else if let Some(path) = &args.file {
+        Some(
+            oneshot::run_file(
+                args.connection.as_deref(),
+                path,
+                args.format,
+                args.continue_on_error,
+            )
+            .await,
+        )
+    } else if !io::stdin().is_terminal() {
+        // SQL is piped to stdin, so the line editor can't be used
+        Some(
+            oneshot::run_stdin(
+                args.connection.as_deref(),
+                args.format,
+                args.continue_on_error,
+            )
+            .await,
+        )
+    } else {
+        None
+    };
+
+    if let Some(result) = batch_result {
+        if let Err(e) = result {
+            eprintln!("ERROR: {e:#}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     DbClient::initialize();
 
     let state = State::default();
     let config = get_config()?;
-    let printer = Printer::new(Verbosity::Standard);
+    state
+        .history
+        .lock()
+        .await
+        .set_max_size(config.get_history_size());
+    *state.diag_level.lock().await = config.get_diag_level();
+    let printer = Printer::new(Verbosity::Standard, config.get_log_format());
     let logger = Logger::new(printer.get_sender());
 
-    let (lsp_client, lsp_server, notification_handler) = build_lsp(state, logger.clone());
+    let (lsp_client, lsp_server, notification_handler) =
+        build_lsp(state, logger.clone(), config.get_lsp_channel_capacity());
     let db_client = DbClient::default();
 
     let repl_config = RustylineConfig::builder()
         .history_ignore_space(true)
+        .history_ignore_dups(true)?
+        .max_history_size(config.get_history_size())?
         .completion_type(CompletionType::List)
-        .edit_mode(EditMode::Vi)
+        .edit_mode(to_rustyline_edit_mode(config.get_edit_mode()))
         .build();
     let mut rl = Editor::with_config(repl_config)?;
 
-    let mut task_manager = TaskManager::new(logger.clone(), lsp_server, lsp_client.clone());
+    let mut task_manager = TaskManager::new(
+        logger.clone(),
+        lsp_server,
+        lsp_client.clone(),
+        db_client.clone(),
+    );
     let task_controller = TaskController::new(task_manager.get_command_tx());
 
     task_manager
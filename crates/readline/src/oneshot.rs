@@ -0,0 +1,125 @@
+use std::{
+    io::{self, Read},
+    path::Path,
+};
+
+use anyhow::anyhow;
+use sqlfriend_core::{
+    config::{get_config, OutputFormat},
+    db_client::DbClient,
+    script::{self, ScriptOutput},
+    version,
+};
+
+/// Connect to the named connection (or the current one if no name is given), run the query and
+/// print the result to stdout. Neither the LSP server nor the line editor are started.
+pub async fn run_query(
+    connection_name: Option<&str>,
+    query: &str,
+    format: Option<OutputFormat>,
+) -> anyhow::Result<()> {
+    let (db_client, format) = connect(connection_name, format).await?;
+
+    // Run like a script, so that read-only connections and `FORCE` are handled the same way
+    let result = script::run_script(&db_client, "query", query, format, false, print_output).await;
+    db_client.close().await;
+
+    result
+}
+
+/// Like `run_query`, but runs all statements in the SQL file at path.
+pub async fn run_file(
+    connection_name: Option<&str>,
+    path: &Path,
+    format: Option<OutputFormat>,
+    continue_on_error: bool,
+) -> anyhow::Result<()> {
+    let (db_client, format) = connect(connection_name, format).await?;
+
+    let result = script::run_file(&db_client, path, format, continue_on_error, print_output).await;
+    db_client.close().await;
+
+    result
+}
+
+/// Like `run_query`, but runs all statements read from stdin.
+pub async fn run_stdin(
+    connection_name: Option<&str>,
+    format: Option<OutputFormat>,
+    continue_on_error: bool,
+) -> anyhow::Result<()> {
+    let mut sql = String::new();
+    io::stdin().read_to_string(&mut sql)?;
+
+    let (db_client, format) = connect(connection_name, format).await?;
+
+    let result = script::run_script(
+        &db_client,
+        "stdin",
+        &sql,
+        format,
+        continue_on_error,
+        print_output,
+    )
+    .await;
+    db_client.close().await;
+
+    result
+}
+
+/// Print the version of sqlfriend and of the LSP server executable, and the version of the
+/// database server if a connection name is given. The LSP server isn't started.
+pub async fn print_version(connection_name: Option<&str>) -> anyhow::Result<()> {
+    let server_type = {
+        let config = get_config()?;
+        match config.get_lsp_tcp_address()? {
+            Some(_) => None,
+            None => Some(config.get_lsp_server().cloned().unwrap_or_default()),
+        }
+    };
+
+    let db_client = match connection_name {
+        Some(name) => connect(Some(name), None).await?.0,
+        None => DbClient::default(),
+    };
+
+    let lines = version::describe(&db_client, None, server_type.as_ref()).await;
+    db_client.close().await;
+    println!("{}", lines.join("\n"));
+
+    Ok(())
+}
+
+/// Connect to the named (or current) connection. Returns the client and the format to use,
+/// which defaults to the configured one.
+async fn connect(
+    connection_name: Option<&str>,
+    format: Option<OutputFormat>,
+) -> anyhow::Result<(DbClient, OutputFormat)> {
+    let config = get_config()?;
+    let connection = match connection_name {
+        Some(name) => config
+            .get_connections()
+            .iter()
+            .find(|connection| connection.name == name)
+            .ok_or(anyhow!("invalid connection name: `{name}`"))?,
+        None => config
+            .get_current_connection()
+            .ok_or(anyhow!("no connection given and no active connection"))?,
+    };
+
+    DbClient::initialize();
+    let db_client = DbClient::default();
+    db_client.connect(connection.clone(), None).await?;
+
+    Ok((db_client, format.unwrap_or(config.get_output_format())))
+}
+
+fn print_output(output: ScriptOutput) -> anyhow::Result<()> {
+    match output {
+        ScriptOutput::Result(result) => println!("{result}"),
+        ScriptOutput::Error(msg) => eprintln!("ERROR: {msg}"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::{
+    io::{self, IsTerminal},
+    process::Stdio,
+};
+
+use crate::interrupt;
+
+use anyhow::Context;
+use sqlfriend_core::{config, logging::Logger};
+use terminal_size::{terminal_size, Height};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// Output query results through the configured pager if they don't fit on the screen, or as a
+/// result through the logger otherwise.
+///
+/// The pager is run while the line editor isn't reading a line, and this returns once it exits,
+/// so that the pager and the prompt don't write to the terminal at the same time.
+pub async fn output(logger: &Logger, output: &str) -> anyhow::Result<()> {
+    let pager = config::get_config()?.get_pager();
+    match pager {
+        Some(pager) if io::stdout().is_terminal() && exceeds_terminal_height(output) => {
+            // Ctrl-C is handled by the pager while it runs
+            interrupt::ignored(page(&pager, output)).await
+        }
+        _ => logger.result(output),
+    }
+}
+
+/// Returns true if the text has more lines than fit on the terminal (along with the prompt).
+fn exceeds_terminal_height(text: &str) -> bool {
+    match terminal_size() {
+        Some((_, Height(height))) => exceeds_height(text, height.into()),
+        None => false,
+    }
+}
+
+fn exceeds_height(text: &str, height: usize) -> bool {
+    text.lines().count() >= height
+}
+
+/// Pipe the text to the stdin of the pager command and wait for the pager to exit.
+async fn page(pager: &str, text: &str) -> anyhow::Result<()> {
+    // Run through the shell so that the command can include arguments (such as `less -SFX`)
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(pager)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("failed to start pager `{pager}`"))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        let result = stdin.write_all(format!("{text}\n").as_bytes()).await;
+        // The pager may exit before reading all of the text, e.g. when it's quit early
+        if let Err(e) = result {
+            if e.kind() != io::ErrorKind::BrokenPipe {
+                return Err(e.into());
+            }
+        }
+    }
+
+    child.wait().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_detect_output_exceeding_height() {
+        assert!(!exceeds_height("a\nb", 3));
+        assert!(exceeds_height("a\nb\nc", 3));
+        assert!(!exceeds_height("", 1));
+    }
+}
@@ -1,9 +1,14 @@
+use std::io::{self, IsTerminal};
+
 use anyhow::anyhow;
 use rustyline::ExternalPrinter;
 
-use sqlfriend_core::logging::{PrintPayload, Verbosity};
+use sqlfriend_core::logging::{LogFormat, PrintPayload, Verbosity};
 use tokio::sync::mpsc;
 
+/// Moves the cursor to the start of the line and clears the line.
+const CLEAR_LINE: &str = "\r\x1b[K";
+
 /// Printer is responsible for receiving log messages (usually from a Logger) and outputting them
 /// to the screen.
 pub struct Printer {
@@ -11,15 +16,30 @@ pub struct Printer {
     log_rx: mpsc::UnboundedReceiver<PrintPayload>,
 
     verbosity: Verbosity,
+
+    log_format: LogFormat,
+
+    /// False if progress shouldn't be shown at all, e.g. when stdout isn't a terminal.
+    show_progress: bool,
+
+    /// True while the line editor is reading a line.
+    reading: bool,
+
+    /// The progress line that is currently shown, if any.
+    progress: Option<String>,
 }
 
 impl Printer {
-    pub fn new(verbosity: Verbosity) -> Self {
+    pub fn new(verbosity: Verbosity, log_format: LogFormat) -> Self {
         let (log_tx, log_rx) = mpsc::unbounded_channel::<PrintPayload>();
         Self {
             log_tx,
             log_rx,
             verbosity,
+            log_format,
+            show_progress: Self::can_show_progress(log_format),
+            reading: false,
+            progress: None,
         }
     }
 
@@ -37,20 +57,61 @@ impl Printer {
             match msg {
                 PrintPayload::Output(verbosity, output) => {
                     if self.verbosity.should_print(&verbosity) {
-                        if verbosity == Verbosity::Standard {
-                            external_printer.print(format!("{output}\n"))?;
-                        } else {
-                            external_printer.print(format!("{verbosity} {output}\n"))?;
-                        }
+                        let output = self.log_format.format(&verbosity, &output);
+                        self.print_line(&mut external_printer, &output)?;
+                    }
+                }
+                PrintPayload::Result(result) => {
+                    self.print_line(&mut external_printer, &result)?;
+                }
+                PrintPayload::Progress(Some(progress)) => {
+                    if self.show_progress && !self.reading {
+                        external_printer.print(format!("{CLEAR_LINE}{progress}"))?;
+                        self.progress = Some(progress);
+                    }
+                }
+                PrintPayload::Progress(None) => {
+                    if self.progress.take().is_some() {
+                        external_printer.print(CLEAR_LINE.to_string())?;
                     }
                 }
+                PrintPayload::SetReading(reading) => {
+                    // The line editor draws the prompt on the line of the progress
+                    if reading && self.progress.take().is_some() {
+                        external_printer.print(CLEAR_LINE.to_string())?;
+                    }
+                    self.reading = reading;
+                }
                 PrintPayload::SetVerbosity(verbosity) => {
                     self.verbosity = verbosity;
                 }
+                PrintPayload::SetLogFormat(log_format) => {
+                    self.log_format = log_format;
+                    self.show_progress = Self::can_show_progress(log_format);
+                }
             };
         }
     }
 
+    fn can_show_progress(log_format: LogFormat) -> bool {
+        // Progress lines would be mixed into the JSON objects
+        io::stdout().is_terminal() && log_format == LogFormat::Human
+    }
+
+    /// Print the line above the progress, which stays on the last line.
+    fn print_line(
+        &self,
+        external_printer: &mut impl ExternalPrinter,
+        line: &str,
+    ) -> anyhow::Result<()> {
+        match &self.progress {
+            Some(progress) => external_printer.print(format!("{CLEAR_LINE}{line}\n{progress}"))?,
+            None => external_printer.print(format!("{line}\n"))?,
+        }
+
+        Ok(())
+    }
+
     pub fn get_sender(&self) -> mpsc::UnboundedSender<PrintPayload> {
         self.log_tx.clone()
     }
@@ -1,20 +1,36 @@
+use std::borrow::Cow;
+
+use crate::{editor, interrupt, pager};
+
 use sqlfriend_core::{
-    command::{handle_command, is_maybe_command},
+    command::{confirm_destructive, handle_command, is_maybe_command, FrontendAction},
+    config,
     db_client::DbClient,
-    lsp::{client::LspClient, completer::LspCompleter},
-    task::TaskController,
+    history::HistoryScope,
+    lsp::{
+        assistant::LspAssistant,
+        client::LspClient,
+        completer::{CompletionSettings, LspCompleter},
+    },
+    output::report,
+    sql,
+    task::{self, TaskController},
 };
 
 use anyhow::bail;
 use completer::ReadlineCompleter;
+use event_handler::{CompletionTriggerHandler, FormatHandler, HoverHandler, SignatureHelpHandler};
+use hinter::ReadlineHinter;
 use rustyline::{
-    error::ReadlineError, highlight::MatchingBracketHighlighter, hint::HistoryHinter,
-    history::FileHistory, Editor,
+    config::Configurer, error::ReadlineError, highlight::MatchingBracketHighlighter,
+    history::FileHistory, EditMode, Editor, Event, EventHandler, KeyEvent,
 };
 use rustyline_derive::{Completer, Helper, Highlighter, Hinter, Validator};
-use validator::ReadlineValidator;
+use validator::{strip_terminator, ReadlineValidator};
 
 mod completer;
+mod event_handler;
+mod hinter;
 mod validator;
 
 #[derive(Helper, Completer, Highlighter, Hinter, Validator)]
@@ -26,16 +42,16 @@ pub struct ReadlineHelper {
     #[rustyline(Validator)]
     validator: ReadlineValidator,
     #[rustyline(Hinter)]
-    hinter: HistoryHinter,
+    hinter: ReadlineHinter,
 }
 
 impl ReadlineHelper {
-    pub fn new(lsp_client: &LspClient) -> Self {
+    pub fn new(lsp_completer: LspCompleter, terminator: config::Terminator) -> Self {
         ReadlineHelper {
-            completer: ReadlineCompleter::new(LspCompleter::new(lsp_client.clone())),
+            completer: ReadlineCompleter::new(lsp_completer.clone()),
             highlighter: MatchingBracketHighlighter::new(),
-            hinter: HistoryHinter::new(),
-            validator: ReadlineValidator::default(),
+            hinter: ReadlineHinter::new(lsp_completer),
+            validator: ReadlineValidator::new(terminator),
         }
     }
 }
@@ -47,17 +63,39 @@ pub async fn init_repl(
     lsp_client: LspClient,
     db_client: DbClient,
 ) -> anyhow::Result<()> {
-    let helper = ReadlineHelper::new(&lsp_client);
+    // Shared by the helper and the key bindings, so that they use the same completion cache
+    let config = config::get_config()?;
+    let lsp_completer =
+        LspCompleter::new(lsp_client.clone(), CompletionSettings::from_config(&config));
+    let helper = ReadlineHelper::new(lsp_completer.clone(), config.get_terminator());
 
     rl.set_helper(Some(helper));
+    bind_keys(&mut rl, &lsp_client, lsp_completer.clone());
+    interrupt::listen();
+
     loop {
-        let prompt = get_prompt(&db_client).await;
-        match rl.readline(&prompt) {
+        let prompt = get_prompt(&db_client, &lsp_client).await;
+        // The connection may have been changed by the previous line
+        let dialect = db_client.dialect().await;
+        lsp_completer.set_dialect(dialect)?;
+        if let Some(helper) = rl.helper_mut() {
+            helper.validator.set_dialect(dialect);
+        }
+        lsp_client.get_logger().set_reading(true)?;
+        let readline_result = rl.readline(&prompt);
+        lsp_client.get_logger().set_reading(false)?;
+
+        match readline_result {
             Ok(line) => {
-                rl.add_history_entry(line.as_str())?;
-                if let Err(e) = handle_line(&task_controller, &db_client, &lsp_client, &line).await
-                {
-                    lsp_client.get_logger().error(&e.to_string())?;
+                add_history_entry(&mut rl, &lsp_client, &line).await?;
+                match handle_line(&task_controller, &db_client, &lsp_client, &line).await {
+                    Ok(Some(FrontendAction::Quit)) => break,
+                    Ok(Some(action)) => {
+                        handle_action(&mut rl, &db_client, &lsp_client, &lsp_completer, action)
+                            .await?
+                    }
+                    Ok(None) => (),
+                    Err(e) => lsp_client.get_logger().error(&e.to_string())?,
                 }
             }
             Err(ReadlineError::Interrupted) => {
@@ -74,17 +112,222 @@ pub async fn init_repl(
         }
     }
 
+    if let Err(e) = lsp_client.shutdown().await {
+        lsp_client
+            .get_logger()
+            .debug(&format!("LSP server shutdown failed: {e:#}"))?;
+    }
+    task_controller.execute(task::Command::Shutdown).await?;
+
+    Ok(())
+}
+
+/// Bind custom key sequences.
+fn bind_keys(
+    rl: &mut Editor<ReadlineHelper, FileHistory>,
+    lsp_client: &LspClient,
+    lsp_completer: LspCompleter,
+) {
+    rl.bind_sequence(
+        KeyEvent::alt('h'),
+        EventHandler::Conditional(Box::new(HoverHandler::new(LspAssistant::new(
+            lsp_client.clone(),
+        )))),
+    );
+    rl.bind_sequence(
+        KeyEvent::alt('F'),
+        EventHandler::Conditional(Box::new(FormatHandler::new(LspAssistant::new(
+            lsp_client.clone(),
+        )))),
+    );
+
+    for trigger in ['(', ','] {
+        rl.bind_sequence(
+            KeyEvent::from(trigger),
+            EventHandler::Conditional(Box::new(SignatureHelpHandler::new(LspAssistant::new(
+                lsp_client.clone(),
+            )))),
+        );
+    }
+
+    // Any other key that triggers completion, since the trigger characters can change
+    rl.bind_sequence(
+        Event::Any,
+        EventHandler::Conditional(Box::new(CompletionTriggerHandler::new(lsp_completer))),
+    );
+}
+
+/// Store the line in the shared history, and in the editor history if it's part of the current
+/// history scope.
+async fn add_history_entry(
+    rl: &mut Editor<ReadlineHelper, FileHistory>,
+    lsp_client: &LspClient,
+    line: &str,
+) -> anyhow::Result<()> {
+    let mut history = lsp_client.get_state().history.lock().await;
+    if let Some(kind) = history.add(line) {
+        if history.get_scope().includes(kind) {
+            rl.add_history_entry(line)?;
+        }
+    }
+
     Ok(())
 }
 
-async fn get_prompt(db_client: &DbClient) -> String {
+/// Perform an action requested by a command.
+async fn handle_action(
+    rl: &mut Editor<ReadlineHelper, FileHistory>,
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    lsp_completer: &LspCompleter,
+    action: FrontendAction,
+) -> anyhow::Result<()> {
+    match action {
+        FrontendAction::RunStatement(statement) => {
+            if let Err(e) = run_statements(db_client, lsp_client, &statement).await {
+                lsp_client.get_logger().error(&e.to_string())?;
+            }
+            Ok(())
+        }
+        FrontendAction::EditStatement(statement) => {
+            if let Err(e) = edit_statement(rl, db_client, lsp_client, &statement).await {
+                lsp_client.get_logger().error(&e.to_string())?;
+            }
+            Ok(())
+        }
+        FrontendAction::Explain { query, analyze } => {
+            if let Err(e) = explain_statement(db_client, lsp_client, &query, analyze).await {
+                lsp_client.get_logger().error(&e.to_string())?;
+            }
+            Ok(())
+        }
+        FrontendAction::ShowNextPage => {
+            if let Err(e) = show_next_page(db_client, lsp_client).await {
+                lsp_client.get_logger().error(&e.to_string())?;
+            }
+            Ok(())
+        }
+        FrontendAction::SetHistoryScope(scope) => set_history_scope(rl, lsp_client, scope).await,
+        FrontendAction::SetEditMode(edit_mode) => {
+            rl.set_edit_mode(to_rustyline_edit_mode(edit_mode));
+            Ok(())
+        }
+        FrontendAction::SetHistorySize(size) => Ok(rl.set_max_history_size(size)?),
+        FrontendAction::SetCompletionSettings(settings) => lsp_completer.set_settings(settings),
+        FrontendAction::SetTerminator(terminator) => {
+            if let Some(helper) = rl.helper_mut() {
+                helper.validator.set_terminator(terminator);
+            }
+            Ok(())
+        }
+        FrontendAction::ReloadSettings {
+            edit_mode,
+            terminator,
+            history_size,
+            completion,
+        } => {
+            rl.set_edit_mode(to_rustyline_edit_mode(edit_mode));
+            lsp_completer.set_settings(completion)?;
+            if let Some(helper) = rl.helper_mut() {
+                helper.validator.set_terminator(terminator);
+            }
+            Ok(rl.set_max_history_size(history_size)?)
+        }
+        // The prompt is drawn again when the next line is read
+        FrontendAction::ClearScreen => Ok(rl.clear_screen()?),
+        // Handled by the REPL loop
+        FrontendAction::Quit => Ok(()),
+    }
+}
+
+/// Convert the configured edit mode to the rustyline equivalent.
+pub fn to_rustyline_edit_mode(edit_mode: config::EditMode) -> EditMode {
+    match edit_mode {
+        config::EditMode::Vi => EditMode::Vi,
+        config::EditMode::Emacs => EditMode::Emacs,
+    }
+}
+
+/// Replace the editor history with the entries in the given scope.
+async fn set_history_scope(
+    rl: &mut Editor<ReadlineHelper, FileHistory>,
+    lsp_client: &LspClient,
+    scope: HistoryScope,
+) -> anyhow::Result<()> {
+    let history = lsp_client.get_state().history.lock().await;
+    rl.clear_history()?;
+    for entry in history.entries_in_scope(scope) {
+        rl.add_history_entry(entry.line.as_str())?;
+    }
+
+    Ok(())
+}
+
+/// Health of the connection, shown by the `{health}` placeholder of the prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Health {
+    /// Connected to a database, and the LSP server is running.
+    Ok,
+    /// Connected to a database, but the LSP server isn't running.
+    NoLsp,
+    /// Not connected to a database.
+    Down,
+}
+
+impl Health {
+    /// A colored dot, or a word in brackets if colors are turned off.
+    fn indicator(&self, color: bool) -> &'static str {
+        match (self, color) {
+            (Self::Ok, true) => "\x1b[32m●\x1b[0m",
+            (Self::NoLsp, true) => "\x1b[33m●\x1b[0m",
+            (Self::Down, true) => "\x1b[31m●\x1b[0m",
+            (Self::Ok, false) => "[ok]",
+            (Self::NoLsp, false) => "[no lsp]",
+            (Self::Down, false) => "[down]",
+        }
+    }
+}
+
+async fn get_prompt(db_client: &DbClient, lsp_client: &LspClient) -> String {
+    let format = match config::get_config() {
+        Ok(config) => config.get_prompt().to_string(),
+        Err(_) => config::Config::default().get_prompt().to_string(),
+    };
+
     let connection = db_client.get_current_connection().await;
     let name = match &*connection {
         Some(connection) => connection.name.clone(),
         None => "sqlfriend".to_string(),
     };
+    let connected = connection.is_some();
+    drop(connection);
+
+    // Indicate that queries run in an open transaction
+    let transaction_marker = if db_client.in_transaction().await {
+        "*"
+    } else {
+        ""
+    };
+
+    let health = if !format.contains(config::Config::HEALTH_PLACEHOLDER) {
+        ""
+    } else if !connected {
+        Health::Down.indicator(report::use_color())
+    } else if !lsp_client.is_initialized().await {
+        Health::NoLsp.indicator(report::use_color())
+    } else {
+        Health::Ok.indicator(report::use_color())
+    };
+
+    format_prompt(&format, &name, transaction_marker, health)
+}
 
-    format!("{name}> ")
+/// Replace the placeholders of the prompt format.
+fn format_prompt(format: &str, name: &str, transaction_marker: &str, health: &str) -> String {
+    format
+        .replace(config::Config::CONNECTION_PLACEHOLDER, name)
+        .replace(config::Config::TRANSACTION_PLACEHOLDER, transaction_marker)
+        .replace(config::Config::HEALTH_PLACEHOLDER, health)
 }
 
 async fn handle_line(
@@ -92,14 +335,193 @@ async fn handle_line(
     db_client: &DbClient,
     lsp_client: &LspClient,
     line: &str,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<Option<FrontendAction>> {
     if is_maybe_command(line) {
-        handle_command(task_controller, db_client, lsp_client, line).await?;
+        return handle_command(task_controller, db_client, lsp_client, line).await;
+    }
+
+    // Enter on an empty line shows the next page of the last query, if there is one
+    if line.trim().is_empty() {
+        let paging = lsp_client.get_state().next_page.lock().await.is_some();
+        return Ok(paging.then_some(FrontendAction::ShowNextPage));
+    }
+
+    run_statements(db_client, lsp_client, line).await?;
+
+    Ok(None)
+}
+
+/// Run the statement and output its results. The statement is stored as the last statement if
+/// it was run successfully.
+async fn run_statement(
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    statement: &str,
+) -> anyhow::Result<()> {
+    let config = config::get_config()?;
+    let dialect = db_client.dialect().await;
+    let sql = if config.get_strip_comments() {
+        Cow::Owned(sql::strip_comments(statement, dialect))
+    } else {
+        Cow::Borrowed(statement)
+    };
+
+    let (sql, forced) = sql::strip_force_keyword(&sql);
+    if !forced {
+        db_client.ensure_writable(sql).await?;
+    }
+
+    if config.get_confirm_destructive() && !confirm_destructive(sql, dialect)? {
+        lsp_client.get_logger().standard("Statement not run.")?;
+        return Ok(());
+    }
+
+    // Ctrl-C isn't seen by the line editor while it isn't reading a line, so it's handled here
+    // instead.
+    let spinner = lsp_client.get_logger().spinner("Running query...");
+    let run = interrupt::cancellable(async {
+        Ok(db_client
+            .fetch_all_redirected(sql, lsp_client.get_state(), lsp_client.get_logger())
+            .await?)
+    });
+    let output = if forced {
+        db_client.run_forced(run).await?
     } else {
-        db_client
-            .fetch_all_with_output(line, lsp_client.get_logger())
-            .await?;
+        run.await?
+    };
+    drop(spinner);
+
+    lsp_client
+        .get_state()
+        .last_statement
+        .lock()
+        .await
+        .replace(statement.to_string());
+
+    // Ctrl-C in the pager is handled by the pager, so it's run after the query can be cancelled
+    if let Some(output) = output {
+        pager::output(lsp_client.get_logger(), &output).await?;
     }
 
     Ok(())
 }
+
+/// Output the plan of the query. Since the query is run with analyze, it's then guarded the same
+/// way as statements that are run directly.
+async fn explain_statement(
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    query: &str,
+    analyze: bool,
+) -> anyhow::Result<()> {
+    let (query, forced) = sql::strip_force_keyword(query);
+    if analyze {
+        if !forced {
+            db_client.ensure_writable(query).await?;
+        }
+
+        if config::get_config()?.get_confirm_destructive()
+            && !confirm_destructive(query, db_client.dialect().await)?
+        {
+            lsp_client.get_logger().standard("Statement not run.")?;
+            return Ok(());
+        }
+    }
+
+    let spinner = lsp_client.get_logger().spinner("Explaining query...");
+    let run = interrupt::cancellable(async { Ok(db_client.explain(query, analyze).await?) });
+    let output = if forced && analyze {
+        db_client.run_forced(run).await?
+    } else {
+        run.await?
+    };
+    drop(spinner);
+
+    pager::output(lsp_client.get_logger(), &output).await
+}
+
+/// Fetch and output the next page of the results of the last query.
+async fn show_next_page(db_client: &DbClient, lsp_client: &LspClient) -> anyhow::Result<()> {
+    let spinner = lsp_client.get_logger().spinner("Running query...");
+    let output = interrupt::cancellable(async {
+        Ok(db_client.fetch_next_page(lsp_client.get_state()).await?)
+    })
+    .await?;
+    drop(spinner);
+
+    pager::output(lsp_client.get_logger(), &output).await
+}
+
+/// Let the user edit the statement in an external editor, and run the statements in the result
+/// unless the editor failed or nothing was changed.
+async fn edit_statement(
+    rl: &mut Editor<ReadlineHelper, FileHistory>,
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    statement: &str,
+) -> anyhow::Result<()> {
+    let logger = lsp_client.get_logger();
+    let Some(edited) = editor::edit(statement).await? else {
+        logger.standard("Editor exited with an error, not running the statement.")?;
+        return Ok(());
+    };
+
+    let edited = edited.trim();
+    if edited.is_empty() || edited == statement.trim() {
+        logger.standard("Statement unchanged, not running it.")?;
+        return Ok(());
+    }
+
+    add_history_entry(rl, lsp_client, edited).await?;
+    run_statements(db_client, lsp_client, edited).await
+}
+
+/// Run the statements in the SQL (such as a line or an edited buffer) one at a time, so that
+/// `FORCE` only applies to the statement it precedes. All of the SQL is then stored as the last
+/// statement, so that `/g` runs all of them again.
+async fn run_statements(
+    db_client: &DbClient,
+    lsp_client: &LspClient,
+    sql: &str,
+) -> anyhow::Result<()> {
+    let terminator = config::get_config()?.get_terminator();
+    for statement in sql::split_statements(sql, db_client.dialect().await) {
+        run_statement(
+            db_client,
+            lsp_client,
+            strip_terminator(statement, &terminator),
+        )
+        .await?;
+    }
+
+    lsp_client
+        .get_state()
+        .last_statement
+        .lock()
+        .await
+        .replace(strip_terminator(sql, &terminator).to_string());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_format_prompt() {
+        assert_eq!(
+            format_prompt("{connection}{transaction}> ", "my_db", "*", ""),
+            "my_db*> "
+        );
+        assert_eq!(
+            format_prompt(
+                "{health} {connection}> ",
+                "my_db",
+                "",
+                Health::Down.indicator(false)
+            ),
+            "[down] my_db> "
+        );
+    }
+}
@@ -25,10 +25,20 @@ impl Completer for ReadlineCompleter {
         pos: usize,
         _ctx: &rustyline::Context<'_>,
     ) -> rustyline::Result<(usize, Vec<Self::Candidate>)> {
+        let ready = self
+            .lsp_completer
+            .complete_ready(line, pos)
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        if let Some((pos, pairs)) = ready {
+            return Ok((pos, to_rustyline_pairs(pairs)));
+        }
+
+        // The completion was requested before the prefetched one (see ReadlineHinter) finished,
+        // so wait for it for a short while
         let (pos, pairs) = task::block_in_place(move || {
             runtime::Handle::current().block_on(async move {
                 self.lsp_completer
-                    .complete_with_logging(line, pos)
+                    .complete_with_timeout(line, pos)
                     .await
                     .map_err(|e| io::Error::other(e.to_string()))
             })
@@ -0,0 +1,155 @@
+use rustyline::{
+    Cmd, ConditionalEventHandler, EditMode, Event, EventContext, InputMode, KeyCode, KeyEvent,
+    Modifiers, Movement, RepeatCount,
+};
+
+use sqlfriend_core::lsp::{assistant::LspAssistant, completer::LspCompleter};
+use tokio::{runtime, task};
+
+/// Show hover information for the token under the cursor.
+pub struct HoverHandler {
+    assistant: LspAssistant,
+}
+
+impl HoverHandler {
+    pub fn new(assistant: LspAssistant) -> Self {
+        Self { assistant }
+    }
+}
+
+impl ConditionalEventHandler for HoverHandler {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        // An error is only returned if logging failed, in which case there is nowhere left to
+        // report it.
+        let _ = task::block_in_place(|| {
+            runtime::Handle::current()
+                .block_on(self.assistant.hover_with_logging(ctx.line(), ctx.pos()))
+        });
+
+        Some(Cmd::Noop)
+    }
+}
+
+/// Replace the input with a formatted version of it.
+pub struct FormatHandler {
+    assistant: LspAssistant,
+}
+
+impl FormatHandler {
+    pub fn new(assistant: LspAssistant) -> Self {
+        Self { assistant }
+    }
+}
+
+impl ConditionalEventHandler for FormatHandler {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        let formatted = task::block_in_place(|| {
+            runtime::Handle::current().block_on(self.assistant.format_with_logging(ctx.line()))
+        });
+
+        match formatted {
+            Ok(Some(formatted)) => Some(Cmd::Replace(Movement::WholeBuffer, Some(formatted))),
+            _ => Some(Cmd::Noop),
+        }
+    }
+}
+
+/// Show signature help after inserting the typed character (such as `(` or `,`). The request is
+/// made in the background so that typing isn't blocked.
+pub struct SignatureHelpHandler {
+    assistant: LspAssistant,
+}
+
+impl SignatureHelpHandler {
+    pub fn new(assistant: LspAssistant) -> Self {
+        Self { assistant }
+    }
+}
+
+impl ConditionalEventHandler for SignatureHelpHandler {
+    fn handle(
+        &self,
+        evt: &Event,
+        n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        // Only trigger when inserting text, the keys have other meanings in vi command mode.
+        if ctx.mode() == EditMode::Vi && ctx.input_mode() != InputMode::Insert {
+            return None;
+        }
+
+        let c = match evt.get(0) {
+            Some(KeyEvent(KeyCode::Char(c), _)) => *c,
+            _ => return None,
+        };
+
+        let mut line = ctx.line().to_string();
+        line.insert(ctx.pos(), c);
+        let pos = ctx.pos() + c.len_utf8();
+
+        let assistant = self.assistant.clone();
+        runtime::Handle::current()
+            .spawn(async move { assistant.signature_help_with_logging(&line, pos).await });
+
+        Some(Cmd::SelfInsert(n, c))
+    }
+}
+
+/// List the completion candidates after a character that triggers completion (such as `.`) is
+/// typed. The key itself is handled as usual. Whether it triggers completion is checked right
+/// away, and only then is the completion made in the background so that typing isn't blocked.
+pub struct CompletionTriggerHandler {
+    completer: LspCompleter,
+}
+
+impl CompletionTriggerHandler {
+    pub fn new(completer: LspCompleter) -> Self {
+        Self { completer }
+    }
+}
+
+impl ConditionalEventHandler for CompletionTriggerHandler {
+    fn handle(
+        &self,
+        evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        // Only trigger when inserting text, the keys have other meanings in vi command mode.
+        if ctx.mode() == EditMode::Vi && ctx.input_mode() != InputMode::Insert {
+            return None;
+        }
+
+        let c = match evt.get(0) {
+            Some(KeyEvent(KeyCode::Char(c), Modifiers::NONE)) => *c,
+            _ => return None,
+        };
+
+        let mut line = ctx.line().to_string();
+        line.insert(ctx.pos(), c);
+        let pos = ctx.pos() + c.len_utf8();
+
+        if self.completer.is_triggered(&line, pos).unwrap_or(false) {
+            let completer = self.completer.clone();
+            runtime::Handle::current()
+                .spawn(async move { completer.list_with_logging(&line, pos).await });
+        }
+
+        // Let the key be inserted as usual
+        None
+    }
+}
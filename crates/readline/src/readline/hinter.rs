@@ -0,0 +1,30 @@
+use rustyline::hint::{Hinter, HistoryHinter};
+
+use sqlfriend_core::lsp::completer::LspCompleter;
+
+/// Shows hints from the history, and prefetches completions as the line changes so that
+/// completing doesn't have to wait for the LSP server.
+// Can't be pub(crate) due to the rustyline Hinter macro, like ReadlineCompleter
+pub struct ReadlineHinter {
+    history_hinter: HistoryHinter,
+    lsp_completer: LspCompleter,
+}
+
+impl ReadlineHinter {
+    pub fn new(lsp_completer: LspCompleter) -> Self {
+        ReadlineHinter {
+            history_hinter: HistoryHinter::new(),
+            lsp_completer,
+        }
+    }
+}
+
+impl Hinter for ReadlineHinter {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, ctx: &rustyline::Context<'_>) -> Option<Self::Hint> {
+        // Called whenever the line or cursor position changes
+        self.lsp_completer.prefetch(line, pos);
+        self.history_hinter.hint(line, pos, ctx)
+    }
+}
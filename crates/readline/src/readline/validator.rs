@@ -2,14 +2,38 @@ use rustyline::validate::{
     MatchingBracketValidator, ValidationContext, ValidationResult, Validator,
 };
 
-use sqlfriend_core::command::command_prefix;
+use sqlfriend_core::{
+    command::is_maybe_command,
+    config::Terminator,
+    sql::{self, Dialect},
+};
 
-#[derive(Default)]
 pub(crate) struct ReadlineValidator {
     bracket_validator: MatchingBracketValidator,
     statement_validator: StatementValidator,
 }
 
+impl ReadlineValidator {
+    pub(crate) fn new(terminator: Terminator) -> Self {
+        ReadlineValidator {
+            bracket_validator: MatchingBracketValidator::new(),
+            statement_validator: StatementValidator {
+                terminator,
+                dialect: Dialect::default(),
+            },
+        }
+    }
+
+    pub(crate) fn set_terminator(&mut self, terminator: Terminator) {
+        self.statement_validator.terminator = terminator;
+    }
+
+    /// Set the SQL dialect of the current connection, which affects where literals end.
+    pub(crate) fn set_dialect(&mut self, dialect: Dialect) {
+        self.statement_validator.dialect = dialect;
+    }
+}
+
 impl Validator for ReadlineValidator {
     fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
         let bracket_result = self.bracket_validator.validate(ctx);
@@ -31,30 +55,117 @@ fn chain_validation_result(
     first
 }
 
-/// Validate that a line ends with a semicolon.
-#[derive(Default)]
-struct StatementValidator {}
+/// Validate that a line ends with the statement terminator.
+struct StatementValidator {
+    terminator: Terminator,
+    dialect: Dialect,
+}
 
 impl Validator for StatementValidator {
     fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
-        Ok(validate_statement(ctx.input()))
+        Ok(validate_statement(
+            ctx.input(),
+            &self.terminator,
+            self.dialect,
+        ))
     }
 }
 
-fn validate_statement(input: &str) -> ValidationResult {
-    let chars = input.chars().collect::<String>();
-    if chars.starts_with(command_prefix!()) {
+fn validate_statement(input: &str, terminator: &Terminator, dialect: Dialect) -> ValidationResult {
+    // Commands are run as soon as they're entered, the same way `handle_line` recognizes them
+    if is_maybe_command(input) {
+        return ValidationResult::Valid(None);
+    }
+    // Enter without any input is accepted, e.g. to show the next page of results
+    if input.is_empty() {
         return ValidationResult::Valid(None);
     }
 
-    match input.chars().last() {
-        Some(char) => {
-            if char == ';' {
-                ValidationResult::Valid(None)
-            } else {
-                ValidationResult::Incomplete
-            }
+    let complete = match terminator {
+        Terminator::Text(text) => sql::ends_with_terminator(input, text, dialect),
+        Terminator::BlankLine => {
+            let last_line_is_blank = input
+                .rsplit_once('\n')
+                .is_some_and(|(_, last_line)| last_line.trim().is_empty());
+            last_line_is_blank && !input.trim().is_empty() && !sql::is_unclosed(input, dialect)
         }
-        None => ValidationResult::Incomplete,
+    };
+
+    if complete {
+        ValidationResult::Valid(None)
+    } else {
+        ValidationResult::Incomplete
+    }
+}
+
+/// Remove the terminator from the end of a complete statement. `;` is part of SQL, so it is kept.
+pub(crate) fn strip_terminator<'a>(input: &'a str, terminator: &Terminator) -> &'a str {
+    match terminator {
+        Terminator::Text(text) if text != ";" => {
+            let trimmed = input.trim_end();
+            trimmed.strip_suffix(text.as_str()).unwrap_or(input)
+        }
+        Terminator::Text(_) => input,
+        Terminator::BlankLine => input.trim_end(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_complete(input: &str, terminator: &Terminator) -> bool {
+        matches!(
+            validate_statement(input, terminator, Dialect::Standard),
+            ValidationResult::Valid(_)
+        )
+    }
+
+    #[test]
+    fn can_validate_with_text_terminator() {
+        let semicolon = Terminator::default();
+        assert!(is_complete("SELECT 1;", &semicolon));
+        assert!(!is_complete("SELECT 1", &semicolon));
+        assert!(is_complete("/help", &semicolon));
+        assert!(is_complete("/", &semicolon));
+        assert!(is_complete("", &semicolon));
+        assert!(!is_complete("/* comment */ SELECT 1", &semicolon));
+        assert!(is_complete("SELECT 1; -- trailing", &semicolon));
+        assert!(!is_complete("SELECT 'a;b", &semicolon));
+        assert!(!is_complete("SELECT 'a;b'", &semicolon));
+        assert!(!is_complete("SELECT \"id;\"", &semicolon));
+        assert!(is_complete("SELECT 'a;b', \"id;\";", &semicolon));
+
+        let go = Terminator::Text("\\g".to_string());
+        assert!(is_complete("SELECT 1 \\g", &go));
+        assert!(!is_complete("SELECT 1;", &go));
+        assert_eq!(strip_terminator("SELECT 1 \\g", &go), "SELECT 1 ");
+        assert_eq!(strip_terminator("SELECT 1;", &semicolon), "SELECT 1;");
+    }
+
+    #[test]
+    fn can_validate_with_blank_line_terminator() {
+        let blank = Terminator::BlankLine;
+        assert!(is_complete("SELECT 1\n", &blank));
+        assert!(is_complete("SELECT 1\n  ", &blank));
+        assert!(!is_complete("SELECT 1", &blank));
+        assert!(!is_complete("\n", &blank));
+        assert!(!is_complete("SELECT 'a\n", &blank));
+        assert_eq!(strip_terminator("SELECT 1\n", &blank), "SELECT 1");
+    }
+
+    #[test]
+    fn can_validate_in_dialect() {
+        let semicolon = Terminator::default();
+        let complete = |input, dialect| {
+            matches!(
+                validate_statement(input, &semicolon, dialect),
+                ValidationResult::Valid(_)
+            )
+        };
+        assert!(!complete("DO $$ BEGIN NULL;", Dialect::Postgres));
+        assert!(complete("DO $$ BEGIN NULL; END $$;", Dialect::Postgres));
+        assert!(!complete(r"SELECT 'it\'s;", Dialect::MySql));
+        assert!(complete(r"SELECT 'it\'s';", Dialect::MySql));
     }
 }